@@ -0,0 +1,139 @@
+// Generates src/unimath.tsv, the symbol table in the format of
+// src/symbols.tsv, from a unimathsymbols.txt file:
+//
+//     cargo run --example unimath -- data/unimathsymbols.txt > src/unimath.tsv
+//
+// Every plain `\name` or escaped character like `\#` in the LaTeX and
+// unicode-math columns and the `= \name` comments becomes an entry. Accents
+// take the letter they go on as their argument, like `\mathring{x}`. Other
+// commands that take arguments, like `\sqrt`, are left out, as are names that
+// ctrl-tex lays out itself.
+
+use ctrl_tex::SymbolTable;
+use ctrl_tex::ast::{CommandDef, CommandRegistry};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::process::ExitCode;
+
+// commands the parser reads as syntax rather than looking them up
+const RESERVED: &[&str] = &[
+    "begin",
+    "end",
+    "left",
+    "middle",
+    "right",
+    "big",
+    "Big",
+    "bigg",
+    "Bigg",
+    "newcommand",
+    "renewcommand",
+    "DeclareMathOperator",
+    "def",
+];
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: cargo run --example unimath -- UNIMATHSYMBOLS.TXT");
+        return ExitCode::FAILURE;
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(source) => {
+            print!("{}", generate(&source));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn generate(source: &str) -> String {
+    let mut table = String::from(
+        "# Generated by examples/unimath.rs from data/unimathsymbols.txt, do not\n\
+         # edit. The entries of symbols.tsv take precedence over these.\n",
+    );
+    let mut seen = HashSet::new();
+    for line in source.lines() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<_> = line.split('^').collect();
+        let [_, chr, latex, unicode_math, class, category, _, comments] = fields[..] else {
+            eprintln!("skipping malformed line `{}`", line);
+            continue;
+        };
+        let Some((class, arity)) = tex_class(class, category) else {
+            continue;
+        };
+        // an accent is a combining character after its argument
+        let output = match arity {
+            0 => escape(chr),
+            _ => format!("#1{}", escape(chr)),
+        };
+
+        let aliases = comments
+            .split(',')
+            .filter_map(|comment| comment.trim().strip_prefix("= "))
+            .map(|alias| alias.split_whitespace().next().unwrap_or(""));
+        for name in [unicode_math, latex].into_iter().chain(aliases) {
+            let Some(name) = command_name(name) else {
+                continue;
+            };
+            if RESERVED.contains(&name)
+                || !matches!(
+                    CommandRegistry::get(&SymbolTable::empty(), name),
+                    CommandDef::Symbol
+                )
+            {
+                continue;
+            }
+            if seen.insert(name) {
+                writeln!(table, "{}\t{}\t{}\t{}", escape(name), output, class, arity).unwrap();
+            }
+        }
+    }
+    table
+}
+
+// The class column of symbols.tsv and the number of arguments, which is one
+// for accents, or `None` for radicals and braces over or under their argument.
+fn tex_class(class: &str, category: &str) -> Option<(&'static str, usize)> {
+    match category {
+        "mathaccent" | "mathbotaccent" => return Some(("ord", 1)),
+        "mathover" | "mathunder" | "mathradical" => return None,
+        _ => {}
+    }
+    let class = match class {
+        "B" => "bin",
+        "R" => "rel",
+        "L" => "op",
+        "O" => "open",
+        "C" => "close",
+        "P" => "punct",
+        "D" => return None,
+        _ => "ord",
+    };
+    Some((class, 0))
+}
+
+// `name` for a plain `\name` command made of letters, or a single character
+// for escapes like `\#` and `\{`.
+fn command_name(field: &str) -> Option<&str> {
+    let name = field.strip_prefix('\\')?;
+    let mut chars = name.chars();
+    let single =
+        matches!((chars.next(), chars.next()), (Some(c), None) if c.is_ascii_punctuation());
+    (single || !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic())).then_some(name)
+}
+
+fn escape(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            // `#` would start a comment at the beginning of a line
+            ' ' | '\t' | '\\' | '#' => format!("\\u{{{:x}}}", c as u32),
+            _ => c.to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,467 @@
+use crate::Options;
+use crate::ast::{Command, Delimited, Expr, ExprKind};
+use crate::diagnostics::{Diagnostic, DiagnosticKind};
+use crate::renderer::{
+    Renderer, Spacing, UnicodeRenderer, is_next_to_factor, linear_environment, map_char_style,
+};
+use crate::reverse::STYLES;
+use crate::token::Span;
+
+// Plain ASCII, for destinations that mangle anything else: `\le` is `<=`,
+// `\alpha` is `alpha`, `x^{n+1}` is `x^(n+1)` and `\frac{a}{b}` is `a/b`.
+// Styled letters lose their style, and accents and roots are written as
+// functions like `hat(x)` and `sqrt(x)`.
+pub struct AsciiRenderer {
+    // for symbols, the classes of atoms and the spaces between them
+    inline: UnicodeRenderer,
+}
+
+impl Default for AsciiRenderer {
+    fn default() -> Self {
+        AsciiRenderer::with_options(&Options::default())
+    }
+}
+
+impl AsciiRenderer {
+    pub fn new() -> Self {
+        AsciiRenderer::default()
+    }
+
+    // TeX-like spacing needs Unicode spaces, so it is readable spacing here.
+    pub fn with_options(options: &Options) -> Self {
+        let spacing = match options.spacing {
+            Spacing::TexLike => Spacing::Readable,
+            spacing => spacing,
+        };
+        let options = Options {
+            spacing,
+            ..options.clone()
+        };
+        AsciiRenderer {
+            inline: UnicodeRenderer::with_options(&options),
+        }
+    }
+}
+
+impl Renderer for AsciiRenderer {
+    fn render(&self, exprs: &[Expr]) -> String {
+        self.sequence(exprs)
+    }
+
+    fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        self.inline.take_diagnostics()
+    }
+}
+
+impl AsciiRenderer {
+    fn sequence(&self, exprs: &[Expr]) -> String {
+        let mut result = String::new();
+        let mut previous = None;
+        let mut previous_word = false;
+        let mut previous_command = false;
+        let atoms = self.inline.classify(exprs);
+        for (i, (expr, &atom)) in exprs.iter().zip(&atoms).enumerate() {
+            let mut item = self.expr(expr);
+            if item.is_empty() {
+                continue;
+            }
+            if let ExprKind::Command(Command::Frac { .. }) = &expr.kind
+                && is_next_to_factor(&atoms[i + 1..], &result)
+            {
+                item = format!("({})", item);
+            }
+            // relations and arrows always stand apart, so `a < -b` isn't
+            // `a <- b`
+            let space = match (previous, atom) {
+                (Some(left), Some(right)) => {
+                    !self.inline.space(left, right).is_empty()
+                        || self.inline.is_thick_space(left.0, right.0)
+                }
+                _ => false,
+            };
+            // and `\ldots \cdots` isn't `......`
+            let command = matches!(&expr.kind, ExprKind::Command(_));
+            let runs = (previous_command || command)
+                && result.ends_with(is_operator_char)
+                && item.starts_with(is_operator_char);
+            // `alpha x` and `a in B` would run together into one word, and
+            // `x^n y` into a longer script
+            let word = is_word(expr, &item);
+            let before = result.trim_end_matches(|c: char| c.is_ascii_alphanumeric());
+            let script = before.ends_with(['^', '_']);
+            let joins = before.len() < result.len()
+                && item.starts_with(|c: char| c.is_ascii_alphanumeric())
+                && (previous_word || word || script);
+            if (space || joins || runs) && !result.is_empty() && !result.ends_with(' ') {
+                result.push(' ');
+            }
+            result += &item;
+            previous = atom.or(previous);
+            previous_word = word;
+            previous_command = command;
+        }
+        result
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Literal(c) => self.literal(*c, expr.span),
+            ExprKind::Group(exprs) => self.sequence(exprs),
+            ExprKind::Superscript(base, sup) => {
+                format!("{}^{}", self.base(base), self.script(sup))
+            }
+            ExprKind::Subscript(base, sub) => {
+                format!("{}_{}", self.base(base), self.script(sub))
+            }
+            ExprKind::Environment(env) => {
+                self.inline.check_environment(env, expr.span);
+                linear_environment(&self.inline, env, |cell| self.expr(cell), "||")
+            }
+            ExprKind::Delimited(delimited) => self.delimited(delimited, expr.span),
+            ExprKind::Command(cmd) => self.command(expr, cmd),
+        }
+    }
+
+    // `{a+b}^2` is `(a+b)^2`, so the script isn't read as the `b`'s.
+    fn base(&self, base: &Expr) -> String {
+        let output = self.expr(base);
+        match &base.kind {
+            ExprKind::Group(exprs) if exprs.len() > 1 && !self.inline.is_atomic(base) => {
+                format!("({})", output)
+            }
+            _ => output,
+        }
+    }
+
+    // `x^2`, `x_ij` and `x^alpha`, but `x^(n+1)`.
+    fn script(&self, expr: &Expr) -> String {
+        let script = self.inline.in_script(|| self.expr(expr));
+        if script.chars().count() == 1 || is_name(&script) || is_number(&script) {
+            script
+        } else {
+            format!("({})", script)
+        }
+    }
+
+    fn command(&self, expr: &Expr, cmd: &Command) -> String {
+        match cmd {
+            Command::Frac { numer, denom } => {
+                format!("{}/{}", self.operand(numer), self.operand(denom))
+            }
+            Command::Sqrt {
+                index: Some(index),
+                content,
+            } if !matches!(&index.kind, ExprKind::Group(exprs) if exprs.is_empty()) => {
+                format!("root({})({})", self.expr(index), self.expr(content))
+            }
+            Command::Sqrt { content, .. } => format!("sqrt({})", self.expr(content)),
+            Command::Mathcal { content }
+            | Command::Mathbb { content }
+            | Command::Mathfrak { content }
+            | Command::Mathbf { content }
+            | Command::Mathit { content }
+            | Command::Mathsf { content }
+            | Command::Mathtt { content } => self.expr(content),
+            Command::Bar { content }
+            | Command::Hat { content }
+            | Command::Vec { content }
+            | Command::Dot { content }
+            | Command::Ddot { content }
+            | Command::Tilde { content } => format!("{}({})", cmd.name(), self.expr(content)),
+            Command::Text { content }
+            | Command::Textrm { content }
+            | Command::Mbox { content }
+            | Command::Mathrm { content }
+            | Command::Operatorname { content } => self.text(content),
+            Command::Xrightarrow { below, above } => self.xarrow("->", above, below.as_deref()),
+            Command::Xleftarrow { below, above } => self.xarrow("<-", above, below.as_deref()),
+            Command::Symbol { name } => self.symbol(expr, name),
+            // accents from the symbol table, like `\overline{AB}`, are
+            // functions like the built-in ones
+            Command::Custom { name, args } => match args.as_slice() {
+                [arg] if self.inline.symbol(name).and_then(|s| s.accent()).is_some() => {
+                    format!("{}({})", name, self.expr(arg))
+                }
+                _ => {
+                    let output = self.inline.render_expr(expr);
+                    self.unicode(&output, expr.span)
+                }
+            },
+        }
+    }
+
+    // A fraction's numerator or denominator, in parentheses unless it is a
+    // single unit.
+    fn operand(&self, expr: &Expr) -> String {
+        let output = self.expr(expr);
+        if self.inline.is_atomic(expr) {
+            output
+        } else {
+            format!("({})", output)
+        }
+    }
+
+    // The argument of `\text` and the like, whose spaces are already in the input.
+    fn text(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Group(exprs) => exprs.iter().map(|e| self.text(e)).collect(),
+            _ => self.expr(expr),
+        }
+    }
+
+    fn xarrow(&self, arrow: &str, above: &Expr, below: Option<&Expr>) -> String {
+        let mut result = arrow.to_string();
+        if !matches!(&above.kind, ExprKind::Group(exprs) if exprs.is_empty()) {
+            result += &format!("^{}", self.script(above));
+        }
+        if let Some(below) = below {
+            result += &format!("_{}", self.script(below));
+        }
+        result
+    }
+
+    fn symbol(&self, expr: &Expr, name: &str) -> String {
+        // reports unknown commands, which are left as `\name`
+        let output = self.inline.render_expr(expr);
+        let Some(symbol) = self.inline.symbol(name) else {
+            return output;
+        };
+        if let Some(ascii) = ascii_symbol(name) {
+            return ascii.to_string();
+        }
+        if symbol.class.is_none() {
+            return if name == "!" { "" } else { " " }.to_string();
+        }
+        if output.is_ascii() {
+            output
+        } else if let Some(plain) = unstyled(&output) {
+            plain.to_string()
+        } else if name.chars().all(|c| c.is_ascii_alphabetic()) {
+            name.to_string()
+        } else {
+            self.unicode(&output, expr.span)
+        }
+    }
+
+    fn literal(&self, c: char, span: Span) -> String {
+        if c.is_ascii() {
+            return c.to_string();
+        }
+        match c {
+            '−' => "-".to_string(),
+            '′' => "'".to_string(),
+            _ => self.unicode(&c.to_string(), span),
+        }
+    }
+
+    // Unicode output, with each character replaced by the command that
+    // renders as it, like `α` by `alpha`.
+    fn unicode(&self, output: &str, span: Span) -> String {
+        let mut result = String::new();
+        for c in output.chars() {
+            if c.is_ascii() {
+                result.push(c);
+                continue;
+            }
+            if let Some(plain) = unstyled(&c.to_string()) {
+                result.push(plain);
+                continue;
+            }
+            let command = (self.inline.symbols().iter())
+                .filter(|(name, symbol)| {
+                    symbol.arity == 0
+                        && symbol.class.is_some()
+                        && symbol.output.chars().eq([c])
+                        && name.chars().all(|c| c.is_ascii_alphabetic())
+                })
+                .map(|(name, _)| name)
+                .min_by_key(|name| (name.len(), *name));
+            match command {
+                Some(name) => {
+                    let name = ascii_symbol(name).unwrap_or(name);
+                    if result.ends_with(|c: char| c.is_ascii_alphanumeric())
+                        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+                    {
+                        result.push(' ');
+                    }
+                    result += name;
+                }
+                None => {
+                    self.inline
+                        .report(DiagnosticKind::UnrepresentableAscii(c), span);
+                    result.push(c);
+                }
+            }
+        }
+        result
+    }
+
+    fn delimited(&self, delimited: &Delimited, span: Span) -> String {
+        let delimiter = |d: &str| match d {
+            "." => String::new(),
+            _ => match d.strip_prefix('\\') {
+                Some(name) => {
+                    let symbol = Expr::new(
+                        ExprKind::Command(Command::Symbol {
+                            name: name.to_string(),
+                        }),
+                        span,
+                    );
+                    self.symbol(&symbol, name)
+                }
+                None => self.unicode(d, span),
+            },
+        };
+        let mut result = delimiter(&delimited.open);
+        for (i, part) in delimited.parts.iter().enumerate() {
+            if let Some(middle) = i.checked_sub(1).and_then(|i| delimited.middles.get(i)) {
+                result += &delimiter(middle);
+            }
+            result += &self.expr(part);
+        }
+        result + &delimiter(&delimited.close)
+    }
+}
+
+// Whether `expr` is written as a word, like `alpha` or `sin`, that another
+// letter next to it would run into.
+fn is_word(expr: &Expr, output: &str) -> bool {
+    matches!(
+        &expr.kind,
+        ExprKind::Command(Command::Symbol { .. } | Command::Operatorname { .. })
+    ) && output.chars().filter(char::is_ascii_alphabetic).count() > 1
+}
+
+// The characters ASCII symbols like `<=`, `->` and `...` are made of.
+fn is_operator_char(c: char) -> bool {
+    matches!(
+        c,
+        '<' | '>' | '=' | '-' | '+' | '~' | '|' | '.' | '*' | '/' | '\\' | '!' | ':'
+    )
+}
+
+fn is_name(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn is_number(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_digit()) && s.chars().all(|c| c.is_ascii_digit() || c == '.')
+}
+
+// The plain letter or digit of a styled one, like `R` for `ℝ`.
+fn unstyled(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    let (Some(c), None) = (chars.next(), chars.next()) else {
+        return None;
+    };
+    let plain = ('A'..='Z').chain('a'..='z').chain('0'..='9');
+    plain
+        .into_iter()
+        .find(|&p| STYLES.iter().any(|style| map_char_style(style, p) == c))
+}
+
+// The ASCII for symbols whose name doesn't read as well.
+fn ascii_symbol(name: &str) -> Option<&'static str> {
+    let ascii = match name {
+        "le" | "leq" | "leqslant" => "<=",
+        "ge" | "geq" | "geqslant" => ">=",
+        "ne" | "neq" => "!=",
+        "ll" => "<<",
+        "gg" => ">>",
+        "equiv" => "==",
+        "approx" => "~~",
+        "sim" => "~",
+        "simeq" | "cong" => "~=",
+        "to" | "rightarrow" => "->",
+        "gets" | "leftarrow" => "<-",
+        "leftrightarrow" => "<->",
+        "longrightarrow" => "-->",
+        "longleftarrow" => "<--",
+        "Rightarrow" | "implies" => "=>",
+        "Leftarrow" => "<==",
+        "Leftrightarrow" | "iff" => "<=>",
+        "Longrightarrow" => "==>",
+        "mapsto" => "|->",
+        "pm" => "+-",
+        "mp" => "-+",
+        "times" | "cdot" | "ast" => "*",
+        "div" => "/",
+        "setminus" | "backslash" => "\\",
+        "infty" => "infinity",
+        "ldots" | "cdots" | "dots" => "...",
+        "langle" => "<",
+        "rangle" => ">",
+        "lfloor" => "|_",
+        "rfloor" => "_|",
+        "lceil" => "|~",
+        "rceil" => "~|",
+        "|" | "Vert" => "||",
+        "vert" | "mid" => "|",
+        "prime" => "'",
+        _ => return None,
+    };
+    Some(ascii)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn render(input: &str) -> String {
+        let mut parser = Parser::new(Lexer::new(input));
+        AsciiRenderer::new().render(&parser.parse())
+    }
+
+    #[test]
+    fn test_symbols() {
+        assert_eq!(render(r"\alpha \le \beta"), "alpha <= beta");
+        assert_eq!(render(r"f: A \to B, a\in A"), "f : A -> B,a in A");
+        assert_eq!(render(r"\mathbb{R} \mathbf{x} \mbfA"), "RxA");
+        assert_eq!(render(r"2\pi r \cdot \infty"), "2 pi r*infinity");
+        assert_eq!(render("α−β"), "alpha-beta");
+    }
+
+    #[test]
+    fn test_distinct_symbols() {
+        assert_eq!(render(r"a < -b"), "a < -b");
+        assert_eq!(render(r"a \gets b"), "a <- b");
+        assert_eq!(render(r"a \le b \Leftarrow c"), "a <= b <== c");
+        assert_eq!(render(r"\ldots \cdots"), "... ...");
+        assert_eq!(render(r"\langle\langle x"), "< <x");
+        assert_eq!(render(r"\sum_{i=1}^n"), "sum_(i=1)^n");
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(render(r"x^{10} + x_{ij}"), "x^10+x_ij");
+        assert_eq!(render(r"x^{n+1} e^{\alpha}"), "x^(n+1)e^alpha");
+        assert_eq!(render(r"\sum_{i=1}^n x_i y"), "sum_(i=1)^n x_i y");
+        assert_eq!(render(r"\frac{a}{b} + \frac{a+b}{2}"), "a/b+(a+b)/2");
+        assert_eq!(render(r"\frac{1}{2}x + 2\frac{1}{2}"), "(1/2)x+2(1/2)");
+        assert_eq!(
+            render(r"\frac{1}{2}(a+b) = -\frac{1}{2}"),
+            "(1/2)(a+b) = -1/2"
+        );
+        assert_eq!(render(r"\sqrt{x} \sqrt[3]{y}"), "sqrt(x)root(3)(y)");
+        assert_eq!(render(r"\hat{x} \text{if } x"), "hat(x)if x");
+        assert_eq!(render(r"\overline{AB} \check{x}"), "overline(AB)check(x)");
+        assert_eq!(render(r"\left\langle x \right\rangle"), "<x>");
+        assert_eq!(
+            render(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"),
+            "[[a, b], [c, d]]"
+        );
+    }
+
+    #[test]
+    fn test_unrepresentable() {
+        let mut parser = Parser::new(Lexer::new("x ☃"));
+        let renderer = AsciiRenderer::new();
+        assert_eq!(renderer.render(&parser.parse()), "x☃");
+        assert_eq!(
+            renderer.take_diagnostics()[0].kind,
+            DiagnosticKind::UnrepresentableAscii('☃')
+        );
+    }
+}
@@ -0,0 +1,911 @@
+use crate::ast::{Command, CommandRegistry, Delimited, Environment, Expr, ExprKind};
+use crate::diagnostics::{Diagnostic, DiagnosticKind};
+use crate::symbols::SymbolTable;
+use crate::token::Span;
+
+// What an AsciiMath name stands for, in terms of the LaTeX it is parsed as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    // a LaTeX command without arguments, `alpha` for `\alpha`
+    Symbol(&'static str),
+    // an operator written as a word, `\sin` or `\operatorname{sech}` if
+    // LaTeX has no command for it
+    Function(&'static str),
+    // a character that isn't special, `//` for `/`
+    Char(char),
+    // a LaTeX command with one argument, `bb` for `\mathbf`
+    Unary(&'static str),
+    // `frac` and `root`
+    Binary(&'static str),
+    // a letter in blackboard bold, `RR` for `\mathbb{R}`
+    Blackboard(char),
+    // a word set as text with spaces around it, like `and`
+    Word(&'static str),
+    // `text(...)`, whose argument is kept as written
+    Text,
+    // brackets, as LaTeX delimiters; the empty string is an invisible one
+    Left(&'static str),
+    Right(&'static str),
+    // `abs(x)` for `\left| x \right|`
+    Enclose(&'static str, &'static str),
+}
+
+const NAMES: &[(&str, Kind)] = &[
+    // Greek letters
+    ("alpha", Kind::Symbol("alpha")),
+    ("beta", Kind::Symbol("beta")),
+    ("gamma", Kind::Symbol("gamma")),
+    ("Gamma", Kind::Symbol("Gamma")),
+    ("delta", Kind::Symbol("delta")),
+    ("Delta", Kind::Symbol("Delta")),
+    ("epsi", Kind::Symbol("epsilon")),
+    ("epsilon", Kind::Symbol("epsilon")),
+    ("varepsilon", Kind::Symbol("varepsilon")),
+    ("zeta", Kind::Symbol("zeta")),
+    ("eta", Kind::Symbol("eta")),
+    ("theta", Kind::Symbol("theta")),
+    ("vartheta", Kind::Symbol("vartheta")),
+    ("Theta", Kind::Symbol("Theta")),
+    ("iota", Kind::Symbol("iota")),
+    ("kappa", Kind::Symbol("kappa")),
+    ("lambda", Kind::Symbol("lambda")),
+    ("lamda", Kind::Symbol("lambda")),
+    ("Lambda", Kind::Symbol("Lambda")),
+    ("Lamda", Kind::Symbol("Lambda")),
+    ("mu", Kind::Symbol("mu")),
+    ("nu", Kind::Symbol("nu")),
+    ("xi", Kind::Symbol("xi")),
+    ("Xi", Kind::Symbol("Xi")),
+    ("pi", Kind::Symbol("pi")),
+    ("Pi", Kind::Symbol("Pi")),
+    ("rho", Kind::Symbol("rho")),
+    ("sigma", Kind::Symbol("sigma")),
+    ("Sigma", Kind::Symbol("Sigma")),
+    ("tau", Kind::Symbol("tau")),
+    ("upsilon", Kind::Symbol("upsilon")),
+    ("phi", Kind::Symbol("phi")),
+    ("varphi", Kind::Symbol("varphi")),
+    ("Phi", Kind::Symbol("Phi")),
+    ("chi", Kind::Symbol("chi")),
+    ("psi", Kind::Symbol("psi")),
+    ("Psi", Kind::Symbol("Psi")),
+    ("omega", Kind::Symbol("omega")),
+    ("Omega", Kind::Symbol("Omega")),
+    // operators
+    ("*", Kind::Symbol("cdot")),
+    ("**", Kind::Symbol("ast")),
+    ("***", Kind::Symbol("star")),
+    ("//", Kind::Char('/')),
+    ("\\\\", Kind::Symbol("backslash")),
+    ("setminus", Kind::Symbol("setminus")),
+    ("xx", Kind::Symbol("times")),
+    ("|><", Kind::Symbol("ltimes")),
+    ("><|", Kind::Symbol("rtimes")),
+    ("|><|", Kind::Symbol("bowtie")),
+    ("-:", Kind::Symbol("div")),
+    ("divide", Kind::Symbol("div")),
+    ("@", Kind::Symbol("circ")),
+    ("o+", Kind::Symbol("oplus")),
+    ("ox", Kind::Symbol("otimes")),
+    ("o.", Kind::Symbol("odot")),
+    ("sum", Kind::Symbol("sum")),
+    ("prod", Kind::Symbol("prod")),
+    ("^^", Kind::Symbol("wedge")),
+    ("^^^", Kind::Symbol("bigwedge")),
+    ("vv", Kind::Symbol("vee")),
+    ("vvv", Kind::Symbol("bigvee")),
+    ("nn", Kind::Symbol("cap")),
+    ("nnn", Kind::Symbol("bigcap")),
+    ("uu", Kind::Symbol("cup")),
+    ("uuu", Kind::Symbol("bigcup")),
+    // relations
+    ("!=", Kind::Symbol("ne")),
+    ("lt", Kind::Char('<')),
+    ("gt", Kind::Char('>')),
+    ("<=", Kind::Symbol("le")),
+    ("lt=", Kind::Symbol("le")),
+    ("leq", Kind::Symbol("le")),
+    (">=", Kind::Symbol("ge")),
+    ("gt=", Kind::Symbol("ge")),
+    ("geq", Kind::Symbol("ge")),
+    ("-<", Kind::Symbol("prec")),
+    ("-lt", Kind::Symbol("prec")),
+    (">-", Kind::Symbol("succ")),
+    ("-<=", Kind::Symbol("preceq")),
+    (">-=", Kind::Symbol("succeq")),
+    ("in", Kind::Symbol("in")),
+    ("!in", Kind::Symbol("notin")),
+    ("sub", Kind::Symbol("subset")),
+    ("sup", Kind::Symbol("supset")),
+    ("sube", Kind::Symbol("subseteq")),
+    ("supe", Kind::Symbol("supseteq")),
+    ("-=", Kind::Symbol("equiv")),
+    ("~=", Kind::Symbol("cong")),
+    ("~~", Kind::Symbol("approx")),
+    ("~", Kind::Symbol("sim")),
+    ("prop", Kind::Symbol("propto")),
+    // logic
+    ("and", Kind::Word("and")),
+    ("or", Kind::Word("or")),
+    ("if", Kind::Word("if")),
+    ("not", Kind::Symbol("neg")),
+    ("=>", Kind::Symbol("implies")),
+    ("<=>", Kind::Symbol("iff")),
+    ("AA", Kind::Symbol("forall")),
+    ("EE", Kind::Symbol("exists")),
+    ("_|_", Kind::Symbol("bot")),
+    ("TT", Kind::Symbol("top")),
+    ("|--", Kind::Symbol("vdash")),
+    ("|==", Kind::Symbol("models")),
+    // miscellaneous
+    ("int", Kind::Symbol("int")),
+    ("oint", Kind::Symbol("oint")),
+    ("del", Kind::Symbol("partial")),
+    ("grad", Kind::Symbol("nabla")),
+    ("+-", Kind::Symbol("pm")),
+    ("-+", Kind::Symbol("mp")),
+    ("O/", Kind::Symbol("emptyset")),
+    ("oo", Kind::Symbol("infty")),
+    ("aleph", Kind::Symbol("aleph")),
+    ("/_", Kind::Symbol("angle")),
+    ("/_\\", Kind::Symbol("triangle")),
+    (":.", Kind::Symbol("therefore")),
+    (":'", Kind::Symbol("because")),
+    ("...", Kind::Symbol("ldots")),
+    ("cdots", Kind::Symbol("cdots")),
+    ("vdots", Kind::Symbol("vdots")),
+    ("ddots", Kind::Symbol("ddots")),
+    ("quad", Kind::Symbol("quad")),
+    ("qquad", Kind::Symbol("qquad")),
+    ("diamond", Kind::Symbol("diamond")),
+    ("square", Kind::Symbol("square")),
+    ("|__", Kind::Symbol("lfloor")),
+    ("__|", Kind::Symbol("rfloor")),
+    ("|~", Kind::Symbol("lceil")),
+    ("~|", Kind::Symbol("rceil")),
+    ("CC", Kind::Blackboard('C')),
+    ("NN", Kind::Blackboard('N')),
+    ("QQ", Kind::Blackboard('Q')),
+    ("RR", Kind::Blackboard('R')),
+    ("ZZ", Kind::Blackboard('Z')),
+    // functions
+    ("sin", Kind::Function("sin")),
+    ("cos", Kind::Function("cos")),
+    ("tan", Kind::Function("tan")),
+    ("sec", Kind::Function("sec")),
+    ("csc", Kind::Function("csc")),
+    ("cot", Kind::Function("cot")),
+    ("arcsin", Kind::Function("arcsin")),
+    ("arccos", Kind::Function("arccos")),
+    ("arctan", Kind::Function("arctan")),
+    ("sinh", Kind::Function("sinh")),
+    ("cosh", Kind::Function("cosh")),
+    ("tanh", Kind::Function("tanh")),
+    ("sech", Kind::Function("sech")),
+    ("csch", Kind::Function("csch")),
+    ("coth", Kind::Function("coth")),
+    ("exp", Kind::Function("exp")),
+    ("log", Kind::Function("log")),
+    ("ln", Kind::Function("ln")),
+    ("det", Kind::Function("det")),
+    ("dim", Kind::Function("dim")),
+    ("mod", Kind::Function("mod")),
+    ("gcd", Kind::Function("gcd")),
+    ("lcm", Kind::Function("lcm")),
+    ("lub", Kind::Function("lub")),
+    ("glb", Kind::Function("glb")),
+    ("min", Kind::Function("min")),
+    ("max", Kind::Function("max")),
+    ("lim", Kind::Function("lim")),
+    ("Lim", Kind::Function("Lim")),
+    // arrows
+    ("uarr", Kind::Symbol("uparrow")),
+    ("darr", Kind::Symbol("downarrow")),
+    ("rarr", Kind::Symbol("rightarrow")),
+    ("->", Kind::Symbol("to")),
+    ("to", Kind::Symbol("to")),
+    (">->", Kind::Symbol("rightarrowtail")),
+    ("->>", Kind::Symbol("twoheadrightarrow")),
+    ("|->", Kind::Symbol("mapsto")),
+    ("larr", Kind::Symbol("leftarrow")),
+    ("harr", Kind::Symbol("leftrightarrow")),
+    ("rArr", Kind::Symbol("Rightarrow")),
+    ("lArr", Kind::Symbol("Leftarrow")),
+    ("hArr", Kind::Symbol("Leftrightarrow")),
+    // accents and fonts
+    ("hat", Kind::Unary("hat")),
+    ("bar", Kind::Unary("bar")),
+    ("overline", Kind::Unary("bar")),
+    ("vec", Kind::Unary("vec")),
+    ("tilde", Kind::Unary("tilde")),
+    ("dot", Kind::Unary("dot")),
+    ("ddot", Kind::Unary("ddot")),
+    ("bb", Kind::Unary("mathbf")),
+    ("mathbf", Kind::Unary("mathbf")),
+    ("bbb", Kind::Unary("mathbb")),
+    ("mathbb", Kind::Unary("mathbb")),
+    ("cc", Kind::Unary("mathcal")),
+    ("mathcal", Kind::Unary("mathcal")),
+    ("tt", Kind::Unary("mathtt")),
+    ("mathtt", Kind::Unary("mathtt")),
+    ("fr", Kind::Unary("mathfrak")),
+    ("mathfrak", Kind::Unary("mathfrak")),
+    ("sf", Kind::Unary("mathsf")),
+    ("mathsf", Kind::Unary("mathsf")),
+    ("sqrt", Kind::Unary("sqrt")),
+    ("root", Kind::Binary("root")),
+    ("frac", Kind::Binary("frac")),
+    ("text", Kind::Text),
+    ("mbox", Kind::Text),
+    // brackets
+    ("(", Kind::Left("(")),
+    (")", Kind::Right(")")),
+    ("[", Kind::Left("[")),
+    ("]", Kind::Right("]")),
+    ("{", Kind::Left("\\{")),
+    ("}", Kind::Right("\\}")),
+    ("(:", Kind::Left("\\langle")),
+    (":)", Kind::Right("\\rangle")),
+    ("<<", Kind::Left("\\langle")),
+    (">>", Kind::Right("\\rangle")),
+    ("{:", Kind::Left("")),
+    (":}", Kind::Right("")),
+    ("abs", Kind::Enclose("|", "|")),
+    ("norm", Kind::Enclose("\\|", "\\|")),
+    ("floor", Kind::Enclose("\\lfloor", "\\rfloor")),
+    ("ceil", Kind::Enclose("\\lceil", "\\rceil")),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(Kind),
+    // `"..."` or the argument of `text(...)`
+    Text(String),
+    Char(char),
+    Eof,
+}
+
+// Splits AsciiMath into the longest names in `NAMES`, quoted text and single
+// characters, skipping whitespace.
+struct Lexer<'a> {
+    input: &'a str,
+    position: usize,
+    char_position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Lexer {
+            input,
+            position: 0,
+            char_position: 0,
+        }
+    }
+
+    fn next_spanned(&mut self) -> (Token, Span) {
+        let rest = &self.input[self.position..];
+        self.bump(rest.len() - rest.trim_start().len());
+
+        let start = (self.position, self.char_position);
+        let rest = &self.input[self.position..];
+        let token = if let Some(text) = rest.strip_prefix('"') {
+            let text = text.split('"').next().unwrap_or_default();
+            self.bump(text.len() + 1);
+            if self.input[self.position..].starts_with('"') {
+                self.bump(1);
+            }
+            Token::Text(text.to_string())
+        } else if let Some((name, kind)) = NAMES
+            .iter()
+            .filter(|(name, kind)| {
+                rest.starts_with(name)
+                    && (*kind != Kind::Text || text_argument(&rest[name.len()..]).is_some())
+            })
+            .max_by_key(|(name, _)| name.len())
+        {
+            self.bump(name.len());
+            match text_argument(&self.input[self.position..]) {
+                Some(text) if *kind == Kind::Text => {
+                    self.bump(text.len() + 2);
+                    Token::Text(text.to_string())
+                }
+                _ => Token::Name(*kind),
+            }
+        } else if let Some(c) = rest.chars().next() {
+            self.bump(c.len_utf8());
+            Token::Char(c)
+        } else {
+            Token::Eof
+        };
+        let span = Span::new(start.0..self.position, start.1..self.char_position);
+        (token, span)
+    }
+
+    fn bump(&mut self, bytes: usize) {
+        let skipped = &self.input[self.position..self.position + bytes];
+        self.char_position += skipped.chars().count();
+        self.position += bytes;
+    }
+}
+
+// The `...` of `text(...)`, which is kept as written.
+fn text_argument(rest: &str) -> Option<&str> {
+    Some(rest.strip_prefix('(')?.split_once(')')?.0)
+}
+
+// A simple expression, with the brackets of `(a+b)` kept apart from the
+// content so they can be dropped where AsciiMath drops them, like in
+// `x^(a+b)` and `(a+b)/2`.
+enum Simple {
+    Expr(Expr),
+    Bracketed {
+        open: &'static str,
+        close: &'static str,
+        content: Vec<Expr>,
+        // the row and column structure if the content is like `(a,b),(c,d)`
+        matrix: Option<Vec<Vec<Expr>>>,
+        span: Span,
+    },
+    // a function with a bracketed argument, like `sin(x)` in `sin(x)/x`
+    Applied {
+        function: Expr,
+        argument: Box<Simple>,
+        span: Span,
+    },
+}
+
+// Parses AsciiMath, like `sum_(i=1)^n x_i^2`, into the same tree the LaTeX
+// `Parser` builds for `\sum_{i=1}^n x_i^2`.
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    symbols: &'a SymbolTable,
+    current_token: Token,
+    current_span: Span,
+    // span of the last token that was consumed
+    previous_span: Span,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(input: &'a str) -> Self {
+        let mut p = Parser {
+            lexer: Lexer::new(input),
+            symbols: SymbolTable::builtin(),
+            current_token: Token::Eof,
+            current_span: Span::default(),
+            previous_span: Span::default(),
+            diagnostics: Vec::new(),
+        };
+        p.advance();
+        p
+    }
+
+    // Looks up the arity of commands in `symbols` instead of the built-in table.
+    pub fn with_symbols(mut self, symbols: &'a SymbolTable) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    // Problems found while parsing, which the parser recovers from.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn advance(&mut self) {
+        let (token, span) = self.lexer.next_spanned();
+        self.previous_span = self.current_span;
+        self.current_token = token;
+        self.current_span = span;
+    }
+
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous_span)
+    }
+
+    fn source(&self, span: Span) -> &'a str {
+        &self.lexer.input[span.bytes()]
+    }
+
+    pub fn parse(&mut self) -> Vec<Expr> {
+        let mut exprs = Vec::new();
+        while self.current_token != Token::Eof {
+            // a closing bracket without an opening one is just a character
+            if let Token::Name(Kind::Right(close)) = self.current_token {
+                exprs.extend(delimiter(close, self.current_span));
+                self.advance();
+                continue;
+            }
+            match self.parse_expr() {
+                Some(simple) => push_simple(&mut exprs, simple),
+                None => self.advance(),
+            }
+        }
+        exprs
+    }
+
+    // `a/b` as a fraction, or a single intermediate expression.
+    fn parse_expr(&mut self) -> Option<Simple> {
+        let start = self.current_span;
+        let numer = self.parse_intermediate()?;
+        if self.current_token != Token::Char('/') {
+            return Some(numer);
+        }
+        let operator = self.current_span;
+        self.advance();
+        let Some(denom) = self.parse_intermediate() else {
+            self.report(DiagnosticKind::MissingArgument("/".to_string()), operator);
+            return Some(numer);
+        };
+        let kind = ExprKind::Command(Command::Frac {
+            numer: Box::new(strip(numer)),
+            denom: Box::new(strip(denom)),
+        });
+        Some(Simple::Expr(Expr::new(kind, self.span_from(start))))
+    }
+
+    // A simple expression with its subscripts and superscripts.
+    fn parse_intermediate(&mut self) -> Option<Simple> {
+        let start = self.current_span;
+        let mut base = self.parse_simple()?;
+        while let Token::Char(c @ ('^' | '_')) = self.current_token {
+            let operator = self.current_span;
+            self.advance();
+            let Some(script) = self.parse_script() else {
+                self.report(DiagnosticKind::MissingArgument(c.to_string()), operator);
+                break;
+            };
+            let base_expr = Box::new(group(base));
+            let kind = if c == '^' {
+                ExprKind::Superscript(base_expr, Box::new(script))
+            } else {
+                ExprKind::Subscript(base_expr, Box::new(script))
+            };
+            base = Simple::Expr(Expr::new(kind, self.span_from(start)));
+        }
+        Some(base)
+    }
+
+    // What follows `^` or `_`, where `x^-1` means `x^{-1}`.
+    fn parse_script(&mut self) -> Option<Expr> {
+        let start = self.current_span;
+        if self.current_token != Token::Char('-') {
+            return self.parse_simple().map(strip);
+        }
+        self.advance();
+        let minus = Expr::new(ExprKind::Literal('-'), start);
+        match self.parse_simple() {
+            Some(simple) => Some(Expr::new(
+                ExprKind::Group(vec![minus, group(simple)]),
+                self.span_from(start),
+            )),
+            None => Some(minus),
+        }
+    }
+
+    fn parse_simple(&mut self) -> Option<Simple> {
+        let start = self.current_span;
+        let kind = match self.current_token.clone() {
+            // the lexer only makes `text` a name if it has an argument
+            Token::Eof | Token::Name(Kind::Right(_) | Kind::Text) => return None,
+            Token::Char(c) | Token::Name(Kind::Char(c)) => {
+                self.advance();
+                ExprKind::Literal(c)
+            }
+            Token::Text(text) => {
+                self.advance();
+                let content = text_group(&text, start);
+                self.build("text", vec![], vec![content], start)
+            }
+            Token::Name(Kind::Word(word)) => {
+                self.advance();
+                let content = text_group(&format!(" {} ", word), start);
+                self.build("text", vec![], vec![content], start)
+            }
+            Token::Name(Kind::Symbol(name)) => {
+                self.advance();
+                self.build(name, vec![], vec![], start)
+            }
+            Token::Name(Kind::Function(name)) => {
+                self.advance();
+                let kind = if self.symbols.contains(name) {
+                    self.build(name, vec![], vec![], start)
+                } else {
+                    let content = text_group(name, start);
+                    self.build("operatorname", vec![], vec![content], start)
+                };
+                let function = Expr::new(kind, self.span_from(start));
+                let Token::Name(Kind::Left(open)) = self.current_token else {
+                    return Some(Simple::Expr(function));
+                };
+                let bracket = self.current_span;
+                self.advance();
+                let argument = Box::new(self.parse_bracketed(open, bracket));
+                return Some(Simple::Applied {
+                    function,
+                    argument,
+                    span: self.span_from(start),
+                });
+            }
+            Token::Name(Kind::Blackboard(c)) => {
+                self.advance();
+                // as written in LaTeX, `\mathbb R`
+                let letter = Expr::new(ExprKind::Literal(c), start);
+                self.build("mathbb", vec![], vec![letter], start)
+            }
+            Token::Name(Kind::Unary(name)) => {
+                self.advance();
+                let args = self.parse_args(1, start);
+                self.build(name, vec![], args, start)
+            }
+            Token::Name(Kind::Binary(name)) => {
+                self.advance();
+                let mut args = self.parse_args(2, start);
+                // `root(n)(x)` is `\sqrt[n]{x}`
+                if name == "root" {
+                    // the index is always a group, like `[n]` in LaTeX
+                    let index = if args.is_empty() {
+                        vec![]
+                    } else {
+                        vec![as_group(args.remove(0))]
+                    };
+                    self.build("sqrt", index, args, start)
+                } else {
+                    self.build(name, vec![], args, start)
+                }
+            }
+            Token::Name(Kind::Enclose(open, close)) => {
+                self.advance();
+                let content = match self.parse_args(1, start).pop() {
+                    Some(arg) => as_group(arg),
+                    None => Expr::new(ExprKind::Group(vec![]), self.previous_span.shrink_to_end()),
+                };
+                ExprKind::Delimited(Delimited {
+                    open: open.to_string(),
+                    close: close.to_string(),
+                    middles: vec![],
+                    parts: vec![content],
+                })
+            }
+            Token::Name(Kind::Left(open)) => {
+                self.advance();
+                return Some(self.parse_bracketed(open, start));
+            }
+        };
+        Some(Simple::Expr(Expr::new(kind, self.span_from(start))))
+    }
+
+    // The arguments of `sqrt`, `frac` and the like, without their brackets.
+    fn parse_args(&mut self, count: usize, start: Span) -> Vec<Expr> {
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.parse_simple() {
+                Some(arg) => args.push(strip(arg)),
+                None => {
+                    let name = self.source(start).to_string();
+                    self.report(DiagnosticKind::MissingArgument(name), self.span_from(start));
+                    break;
+                }
+            }
+        }
+        args
+    }
+
+    fn build(
+        &self,
+        name: &str,
+        optional_args: Vec<Expr>,
+        args: Vec<Expr>,
+        start: Span,
+    ) -> ExprKind {
+        let span = self.span_from(start);
+        ExprKind::Command(CommandRegistry::build(
+            self.symbols,
+            name,
+            optional_args,
+            args,
+            span,
+        ))
+    }
+
+    // The rest of `(...)` after the opening bracket, which may be left open.
+    fn parse_bracketed(&mut self, open: &'static str, start: Span) -> Simple {
+        let mut items = Vec::new();
+        let close = loop {
+            match self.current_token {
+                Token::Eof => break "",
+                Token::Name(Kind::Right(close)) => {
+                    self.advance();
+                    break close;
+                }
+                _ => match self.parse_expr() {
+                    Some(simple) => items.push(simple),
+                    None => self.advance(),
+                },
+            }
+        };
+        let matrix = matrix(&items);
+        let mut content = Vec::new();
+        for item in items {
+            push_simple(&mut content, item);
+        }
+        Simple::Bracketed {
+            open,
+            close,
+            content,
+            matrix,
+            span: self.span_from(start),
+        }
+    }
+
+    fn report(&mut self, kind: DiagnosticKind, span: Span) {
+        self.diagnostics.push(Diagnostic { kind, span });
+    }
+}
+
+// The rows of `(a,b),(c,d)`: bracketed rows separated by commas, at least
+// two of them, all with the same brackets and number of cells.
+fn matrix(items: &[Simple]) -> Option<Vec<Vec<Expr>>> {
+    let mut rows = Vec::new();
+    let mut brackets = None;
+    for (i, item) in items.iter().enumerate() {
+        match item {
+            Simple::Expr(expr) if i % 2 == 1 && expr.kind == ExprKind::Literal(',') => {}
+            Simple::Bracketed {
+                open,
+                close,
+                content,
+                matrix: None,
+                ..
+            } if i % 2 == 0 && !open.is_empty() => {
+                if *brackets.get_or_insert((open, close)) != (open, close) {
+                    return None;
+                }
+                rows.push(cells(content));
+            }
+            _ => return None,
+        }
+    }
+    let columns = rows.first().map(Vec::len);
+    let rectangular = rows.iter().all(|row| Some(row.len()) == columns);
+    (rows.len() > 1 && items.len() % 2 == 1 && rectangular).then_some(rows)
+}
+
+// The content of a matrix row split at its commas, every cell as a group.
+fn cells(content: &[Expr]) -> Vec<Expr> {
+    content
+        .split(|expr| expr.kind == ExprKind::Literal(','))
+        .map(|cell| {
+            let span = match (cell.first(), cell.last()) {
+                (Some(first), Some(last)) => first.span.to(last.span),
+                _ => Span::default(),
+            };
+            Expr::new(ExprKind::Group(cell.to_vec()), span)
+        })
+        .collect()
+}
+
+// Adds `simple` to a sequence, where brackets are just characters, unless
+// it is a matrix.
+fn push_simple(exprs: &mut Vec<Expr>, simple: Simple) {
+    match simple {
+        Simple::Expr(expr) => exprs.push(expr),
+        Simple::Bracketed {
+            open,
+            close,
+            matrix: Some(rows),
+            span,
+            ..
+        } => exprs.push(environment(open, close, rows, span)),
+        Simple::Bracketed {
+            open,
+            close,
+            content,
+            span,
+            ..
+        } => {
+            exprs.extend(delimiter(open, span));
+            exprs.extend(content);
+            exprs.extend(delimiter(close, span.shrink_to_end()));
+        }
+        Simple::Applied {
+            function, argument, ..
+        } => {
+            exprs.push(function);
+            push_simple(exprs, *argument);
+        }
+    }
+}
+
+// A whole bracketed expression as a single one, like the base of `(a+b)^2`.
+fn group(simple: Simple) -> Expr {
+    match simple {
+        Simple::Expr(expr) => expr,
+        Simple::Bracketed { span, .. } | Simple::Applied { span, .. } => {
+            let mut exprs = Vec::new();
+            push_simple(&mut exprs, simple);
+            match <[Expr; 1]>::try_from(exprs) {
+                Ok([expr]) => expr,
+                Err(exprs) => Expr::new(ExprKind::Group(exprs), span),
+            }
+        }
+    }
+}
+
+// An argument, whose brackets AsciiMath drops: `sqrt(x)` is `\sqrt{x}`, which
+// the LaTeX parser reads as `\sqrt x`.
+fn strip(simple: Simple) -> Expr {
+    match simple {
+        Simple::Bracketed {
+            matrix: None,
+            mut content,
+            span,
+            ..
+        } => match content.as_slice() {
+            [
+                Expr {
+                    kind: ExprKind::Literal(_) | ExprKind::Command(Command::Symbol { .. }),
+                    ..
+                },
+            ] => content
+                .pop()
+                .unwrap_or_else(|| Expr::new(ExprKind::Group(vec![]), span)),
+            _ => Expr::new(ExprKind::Group(content), span),
+        },
+        simple => group(simple),
+    }
+}
+
+// `expr` as a group, if it isn't one already.
+fn as_group(expr: Expr) -> Expr {
+    match expr.kind {
+        ExprKind::Group(_) => expr,
+        _ => {
+            let span = expr.span;
+            Expr::new(ExprKind::Group(vec![expr]), span)
+        }
+    }
+}
+
+fn environment(open: &str, close: &str, rows: Vec<Vec<Expr>>, span: Span) -> Expr {
+    let name = match (open, close) {
+        ("(", ")") => "pmatrix",
+        ("[", "]") => "bmatrix",
+        ("\\{", "\\}") => "Bmatrix",
+        ("|", "|") => "vmatrix",
+        ("\\|", "\\|") => "Vmatrix",
+        // `{(1, x > 0), (0, x <= 0):}`
+        ("\\{", "") => "cases",
+        _ => "matrix",
+    };
+    Expr::new(
+        ExprKind::Environment(Environment {
+            name: name.to_string(),
+            columns: None,
+            rows,
+        }),
+        span,
+    )
+}
+
+// A bracket as the character or LaTeX command it stands for.
+fn delimiter(delimiter: &str, span: Span) -> Option<Expr> {
+    let kind = match delimiter.strip_prefix('\\') {
+        Some(name) => ExprKind::Command(Command::Symbol {
+            name: name.to_string(),
+        }),
+        None => ExprKind::Literal(delimiter.chars().next()?),
+    };
+    Some(Expr::new(kind, span))
+}
+
+fn text_group(text: &str, span: Span) -> Expr {
+    let chars = text
+        .chars()
+        .map(|c| Expr::new(ExprKind::Literal(c), span))
+        .collect();
+    Expr::new(ExprKind::Group(chars), span)
+}
+
+// A guess at whether `input` is AsciiMath rather than LaTeX: it has no LaTeX
+// commands or braced scripts, and something only AsciiMath gives a meaning
+// to, like `sqrt`, `RR`, `<=` or `x^(n+1)`. Anything else is read as LaTeX,
+// which treats plain input like `x^2 + 1` the same.
+pub fn is_asciimath(input: &str) -> bool {
+    if input.contains('\\') || input.contains("^{") || input.contains("_{") {
+        return false;
+    }
+    if ["^(", "_(", "^-", "_-", ")/", "/(", "),("]
+        .iter()
+        .any(|s| input.contains(s))
+    {
+        return true;
+    }
+    let mut lexer = Lexer::new(input);
+    loop {
+        match lexer.next_spanned() {
+            (Token::Eof, _) => return false,
+            (Token::Text(_), _) => return true,
+            (Token::Name(_), span) if span.end - span.start > 1 => return true,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse(input: &str) -> Vec<Expr> {
+        Parser::new(input).parse()
+    }
+
+    fn latex(input: &str) -> Vec<Expr> {
+        crate::parser::Parser::new(Lexer::new(input)).parse()
+    }
+
+    #[test]
+    fn test_same_tree() {
+        for (asciimath, tex) in [
+            ("sum_(i=1)^n x_i^2", r"\sum_{i=1}^n x_i^2"),
+            ("sqrt(x)/2", r"\frac\sqrt{x}2"),
+            ("(a+b)/c", r"\frac{a+b}c"),
+            ("x^(n+1) e^-x", r"x^{n+1} e^{-x}"),
+            ("RR bb(v) hat x", r"\mathbb{R} \mathbf{v} \hat x"),
+            ("alpha <= beta -> oo", r"\alpha \le \beta \to \infty"),
+            ("f(x) = [a, b)", "f(x) = [a, b)"),
+            ("root(3)(x) + abs(y)", r"\sqrt[3]{x} + \left| y \right|"),
+            ("sin x sech y", r"\sin x \operatorname{sech} y"),
+            ("lim_(x->0) sin(x)/x", r"\lim_{x\to0} \frac{\sin(x)}x"),
+            ("sech(x)^2 + f(x)", r"{\operatorname{sech}(x)}^2 + f(x)"),
+            ("(:x, y:) {:a:}", r"\langle x, y \rangle a"),
+            ("x \"for all\" y", r"x \text{for all} y"),
+            ("[(1,2),(3,4)]", r"\begin{bmatrix}1&2\\3&4\end{bmatrix}"),
+            (
+                "{(1, x > 0),(0, x <= 0):}",
+                r"\begin{cases}1& x > 0\\0& x \le 0\end{cases}",
+            ),
+        ] {
+            assert_eq!(parse(asciimath), latex(tex), "{}", asciimath);
+        }
+    }
+
+    #[test]
+    fn test_names_are_known() {
+        let symbols = SymbolTable::builtin();
+        for (name, kind) in NAMES {
+            if let Kind::Symbol(command) = kind {
+                assert!(symbols.contains(command), "{} is \\{}", name, command);
+            }
+        }
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut parser = Parser::new("1 + sqrt");
+        parser.parse();
+        let diagnostics = parser.take_diagnostics();
+        assert_eq!(
+            diagnostics[0].kind,
+            DiagnosticKind::MissingArgument("sqrt".to_string())
+        );
+        assert_eq!(diagnostics[0].span.bytes(), 4..8);
+    }
+
+    #[test]
+    fn test_detection() {
+        for input in [
+            "sqrt(x)/2",
+            "x in RR",
+            "alpha+1",
+            "x^(n+1)",
+            "a <= b",
+            "\"if\" x",
+        ] {
+            assert!(is_asciimath(input), "{}", input);
+        }
+        for input in [r"\alpha", "x^{n+1}", "x^2+1", "a/b", r"\sqrt(x)"] {
+            assert!(!is_asciimath(input), "{}", input);
+        }
+    }
+}
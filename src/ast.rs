@@ -1,30 +1,231 @@
+use crate::symbols::SymbolTable;
+use crate::token::Span;
+
+// An expression together with the part of the input it was parsed from.
+// Spans are ignored when comparing expressions, so trees parsed from
+// differently formatted input compare equal.
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl From<ExprKind> for Expr {
+    fn from(kind: ExprKind) -> Self {
+        Expr::new(kind, Span::default())
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
-pub enum Expr {
+pub enum ExprKind {
     Command(Command),
     Group(Vec<Expr>),
     Literal(char),
     Superscript(Box<Expr>, Box<Expr>),
     Subscript(Box<Expr>, Box<Expr>),
+    Environment(Environment),
+    Delimited(Delimited),
+}
+
+// `\begin{name} a & b \\ c & d \end{name}`, with every cell as a group.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Environment {
+    pub name: String,
+    // the column specification of `array`, like `cl`
+    pub columns: Option<String>,
+    pub rows: Vec<Vec<Expr>>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Command {
-    Frac { numer: Box<Expr>, denom: Box<Expr> },
-    Sqrt { content: Box<Expr> },
-    Mathcal { content: Box<Expr> },
-    Mathbb { content: Box<Expr> },
-    Mathfrak { content: Box<Expr> },
-    Mathbf { content: Box<Expr> },
-    Mathit { content: Box<Expr> },
-    Mathsf { content: Box<Expr> },
-    Mathtt { content: Box<Expr> },
-    Bar { content: Box<Expr> },
-    Hat { content: Box<Expr> },
-    Vec { content: Box<Expr> },
-    Dot { content: Box<Expr> },
-    Ddot { content: Box<Expr> },
-    Tilde { content: Box<Expr> },
-    Symbol { name: String },
+    Frac {
+        numer: Box<Expr>,
+        denom: Box<Expr>,
+    },
+    Sqrt {
+        index: Option<Box<Expr>>,
+        content: Box<Expr>,
+    },
+    Mathcal {
+        content: Box<Expr>,
+    },
+    Mathbb {
+        content: Box<Expr>,
+    },
+    Mathfrak {
+        content: Box<Expr>,
+    },
+    Mathbf {
+        content: Box<Expr>,
+    },
+    Mathit {
+        content: Box<Expr>,
+    },
+    Mathsf {
+        content: Box<Expr>,
+    },
+    Mathtt {
+        content: Box<Expr>,
+    },
+    Bar {
+        content: Box<Expr>,
+    },
+    Hat {
+        content: Box<Expr>,
+    },
+    Vec {
+        content: Box<Expr>,
+    },
+    Dot {
+        content: Box<Expr>,
+    },
+    Ddot {
+        content: Box<Expr>,
+    },
+    Tilde {
+        content: Box<Expr>,
+    },
+    // upright text whose spaces are kept
+    Text {
+        content: Box<Expr>,
+    },
+    Textrm {
+        content: Box<Expr>,
+    },
+    Mbox {
+        content: Box<Expr>,
+    },
+    Mathrm {
+        content: Box<Expr>,
+    },
+    Operatorname {
+        content: Box<Expr>,
+    },
+    Xrightarrow {
+        below: Option<Box<Expr>>,
+        above: Box<Expr>,
+    },
+    Xleftarrow {
+        below: Option<Box<Expr>>,
+        above: Box<Expr>,
+    },
+    Symbol {
+        name: String,
+    },
+    // a command from the symbol table that takes arguments
+    Custom {
+        name: String,
+        args: Vec<Expr>,
+    },
+}
+
+// `\left( ... \middle| ... \right)`. Delimiters are kept as written, like
+// `(` or `\langle`, with `.` for an invisible one.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Delimited {
+    pub open: String,
+    pub close: String,
+    // the `\middle` delimiters, one fewer than there are parts
+    pub middles: Vec<String>,
+    // the content between the delimiters, each as a group
+    pub parts: Vec<Expr>,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
+    }
+
+    // Calls `f` on this expression and every expression nested inside it.
+    pub fn walk(&self, f: &mut impl FnMut(&Expr)) {
+        f(self);
+        match &self.kind {
+            ExprKind::Literal(_) => {}
+            ExprKind::Command(cmd) => cmd.args().into_iter().for_each(|arg| arg.walk(f)),
+            ExprKind::Group(exprs) => exprs.iter().for_each(|e| e.walk(f)),
+            ExprKind::Superscript(base, script) | ExprKind::Subscript(base, script) => {
+                base.walk(f);
+                script.walk(f);
+            }
+            ExprKind::Environment(env) => env.rows.iter().flatten().for_each(|cell| cell.walk(f)),
+            ExprKind::Delimited(delimited) => delimited.parts.iter().for_each(|e| e.walk(f)),
+        }
+    }
+}
+
+impl Command {
+    // The name the command is written with, without the backslash.
+    pub fn name(&self) -> &str {
+        match self {
+            Command::Frac { .. } => "frac",
+            Command::Sqrt { .. } => "sqrt",
+            Command::Mathcal { .. } => "mathcal",
+            Command::Mathbb { .. } => "mathbb",
+            Command::Mathfrak { .. } => "mathfrak",
+            Command::Mathbf { .. } => "mathbf",
+            Command::Mathit { .. } => "mathit",
+            Command::Mathsf { .. } => "mathsf",
+            Command::Mathtt { .. } => "mathtt",
+            Command::Bar { .. } => "bar",
+            Command::Hat { .. } => "hat",
+            Command::Vec { .. } => "vec",
+            Command::Dot { .. } => "dot",
+            Command::Ddot { .. } => "ddot",
+            Command::Tilde { .. } => "tilde",
+            Command::Text { .. } => "text",
+            Command::Textrm { .. } => "textrm",
+            Command::Mbox { .. } => "mbox",
+            Command::Mathrm { .. } => "mathrm",
+            Command::Operatorname { .. } => "operatorname",
+            Command::Xrightarrow { .. } => "xrightarrow",
+            Command::Xleftarrow { .. } => "xleftarrow",
+            Command::Symbol { name } | Command::Custom { name, .. } => name,
+        }
+    }
+
+    pub fn args(&self) -> Vec<&Expr> {
+        match self {
+            Command::Frac { numer, denom } => vec![numer, denom],
+            Command::Sqrt {
+                index: optional,
+                content,
+            }
+            | Command::Xrightarrow {
+                below: optional,
+                above: content,
+            }
+            | Command::Xleftarrow {
+                below: optional,
+                above: content,
+            } => optional.iter().chain([content]).map(|e| &**e).collect(),
+            Command::Mathcal { content }
+            | Command::Mathbb { content }
+            | Command::Mathfrak { content }
+            | Command::Mathbf { content }
+            | Command::Mathit { content }
+            | Command::Mathsf { content }
+            | Command::Mathtt { content }
+            | Command::Bar { content }
+            | Command::Hat { content }
+            | Command::Vec { content }
+            | Command::Dot { content }
+            | Command::Ddot { content }
+            | Command::Tilde { content }
+            | Command::Text { content }
+            | Command::Textrm { content }
+            | Command::Mbox { content }
+            | Command::Mathrm { content }
+            | Command::Operatorname { content } => vec![content],
+            Command::Symbol { .. } => vec![],
+            Command::Custom { args, .. } => args.iter().collect(),
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -32,25 +233,49 @@ pub enum CommandDef {
     Symbol,
     Unary(fn(Box<Expr>) -> Command),
     Binary(fn(Box<Expr>, Box<Expr>) -> Command),
+    // a bracketed optional argument followed by a mandatory one
+    OptionalUnary(fn(Option<Box<Expr>>, Box<Expr>) -> Command),
+    // a single argument read in text mode, keeping its spaces
+    Text(fn(Box<Expr>) -> Command),
+    // a command from the symbol table with this many arguments
+    Custom(usize),
 }
 
 impl CommandDef {
     pub fn arity(&self) -> usize {
         match self {
             CommandDef::Symbol => 0,
-            CommandDef::Unary(_) => 1,
+            CommandDef::Unary(_) | CommandDef::Text(_) => 1,
             CommandDef::Binary(_) => 2,
+            CommandDef::OptionalUnary(_) => 1,
+            CommandDef::Custom(arity) => *arity,
         }
     }
+
+    pub fn optional_arity(&self) -> usize {
+        match self {
+            CommandDef::OptionalUnary(_) => 1,
+            _ => 0,
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self, CommandDef::Text(_))
+    }
 }
 
 pub struct CommandRegistry;
 
 impl CommandRegistry {
-    pub fn get(name: &str) -> CommandDef {
+    // Commands with their own layout come first, everything else is looked
+    // up in `symbols`.
+    pub fn get(symbols: &SymbolTable, name: &str) -> CommandDef {
         match name {
             "frac" => CommandDef::Binary(|a, b| Command::Frac { numer: a, denom: b }),
-            "sqrt" => CommandDef::Unary(|c| Command::Sqrt { content: c }),
+            "sqrt" => CommandDef::OptionalUnary(|i, c| Command::Sqrt {
+                index: i,
+                content: c,
+            }),
             "mathcal" => CommandDef::Unary(|c| Command::Mathcal { content: c }),
             "mathbb" => CommandDef::Unary(|c| Command::Mathbb { content: c }),
             "mathfrak" => CommandDef::Unary(|c| Command::Mathfrak { content: c }),
@@ -64,37 +289,70 @@ impl CommandRegistry {
             "dot" => CommandDef::Unary(|c| Command::Dot { content: c }),
             "ddot" => CommandDef::Unary(|c| Command::Ddot { content: c }),
             "tilde" => CommandDef::Unary(|c| Command::Tilde { content: c }),
-            _ => CommandDef::Symbol,
+            "text" => CommandDef::Text(|c| Command::Text { content: c }),
+            "textrm" => CommandDef::Text(|c| Command::Textrm { content: c }),
+            "mbox" => CommandDef::Text(|c| Command::Mbox { content: c }),
+            "mathrm" => CommandDef::Text(|c| Command::Mathrm { content: c }),
+            "operatorname" => CommandDef::Text(|c| Command::Operatorname { content: c }),
+            "xrightarrow" => {
+                CommandDef::OptionalUnary(|b, a| Command::Xrightarrow { below: b, above: a })
+            }
+            "xleftarrow" => {
+                CommandDef::OptionalUnary(|b, a| Command::Xleftarrow { below: b, above: a })
+            }
+            _ => match symbols.get(name) {
+                Some(symbol) if symbol.arity > 0 => CommandDef::Custom(symbol.arity),
+                _ => CommandDef::Symbol,
+            },
         }
     }
 
-    pub fn arity(name: &str) -> usize {
-        Self::get(name).arity()
+    pub fn arity(symbols: &SymbolTable, name: &str) -> usize {
+        Self::get(symbols, name).arity()
     }
 
-    pub fn build(name: &str, mut args: Vec<Expr>) -> Command {
-        match Self::get(name) {
+    pub fn optional_arity(symbols: &SymbolTable, name: &str) -> usize {
+        Self::get(symbols, name).optional_arity()
+    }
+
+    // Missing arguments are filled in with empty groups located at the end of `span`.
+    pub fn build(
+        symbols: &SymbolTable,
+        name: &str,
+        mut optional_args: Vec<Expr>,
+        mut args: Vec<Expr>,
+        span: Span,
+    ) -> Command {
+        let mut next_arg = || {
+            Box::new(
+                args.pop()
+                    .unwrap_or_else(|| Expr::new(ExprKind::Group(vec![]), span.shrink_to_end())),
+            )
+        };
+        match Self::get(symbols, name) {
             CommandDef::Symbol => Command::Symbol {
                 name: name.to_string(),
             },
-            CommandDef::Unary(builder) => {
-                let arg = args
-                    .pop()
-                    .map(Box::new)
-                    .unwrap_or_else(|| Box::new(Expr::Group(vec![])));
-                builder(arg)
-            }
+            CommandDef::Unary(builder) | CommandDef::Text(builder) => builder(next_arg()),
             CommandDef::Binary(builder) => {
-                let arg2 = args
-                    .pop()
-                    .map(Box::new)
-                    .unwrap_or_else(|| Box::new(Expr::Group(vec![])));
-                let arg1 = args
-                    .pop()
-                    .map(Box::new)
-                    .unwrap_or_else(|| Box::new(Expr::Group(vec![])));
+                let arg2 = next_arg();
+                let arg1 = next_arg();
                 builder(arg1, arg2)
             }
+            CommandDef::OptionalUnary(builder) => {
+                let arg = next_arg();
+                builder(optional_args.pop().map(Box::new), arg)
+            }
+            CommandDef::Custom(arity) => {
+                args.resize(
+                    arity,
+                    Expr::new(ExprKind::Group(vec![]), span.shrink_to_end()),
+                );
+                Command::Custom {
+                    name: name.to_string(),
+                    args,
+                }
+            }
         }
     }
 }
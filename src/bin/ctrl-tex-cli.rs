@@ -0,0 +1,367 @@
+use ctrl_tex::{Macros, Options, convert_markdown_with_report, convert_with_report, reverse};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: ctrl-tex-cli convert [--check] [CONVERSION OPTIONS] [--] [LATEX...]
+       ctrl-tex-cli file [--in-place] [--check] [CONVERSION OPTIONS] [--] FILE...
+       ctrl-tex-cli convert-markdown [--in-place] [--check] [CONVERSION OPTIONS] [--] FILE...
+
+commands:
+  convert       convert the arguments (joined by spaces), or stdin if none are given
+  file          convert each file to stdout, or in place with --in-place ('-' is stdin)
+  convert-markdown
+                like `file` for Markdown documents: only $...$ and $$...$$ are
+                converted, code spans and code blocks are left alone
+
+options:
+  -i, --in-place  overwrite the files with their conversion
+  --check         write nothing, fail if any input would change when converted
+  --reverse       convert Unicode math like ∀ε>0 back to LaTeX like \\forall\\varepsilon>0
+  -h, --help      show this message
+  --              end of the options, for LaTeX starting with a minus like -x^2
+
+conversion options:
+  --scope SCOPE   what to convert: `delimited` converts only $...$, $$...$$, \\(...\\)
+                  and \\[...\\] and keeps the text around them, `whole` treats all
+                  input as math, `auto` (the default) is `delimited` if the input
+                  has any delimiters and `whole` otherwise
+  --syntax SYNTAX what the math is written in: `latex`, `asciimath` like
+                  sqrt(x)/2, or `auto` (the default), which reads it as
+                  AsciiMath if it looks like it and as LaTeX otherwise
+  --spacing SPACING
+                  spaces between symbols: `compact` (the default) only separates
+                  functions like sin from their argument, `readable` also puts
+                  spaces around operators and relations, `tex-like` uses thin,
+                  medium and thick Unicode spaces where TeX would
+  --fractions FRACTIONS
+                  `vulgar` (the default) uses characters like ½ where they exist,
+                  then `slash` writes ¹²⁄₇ if both parts have script forms, and
+                  `linear` writes (a+b)/c
+  --format FORMAT
+                  `unicode` (the default) for a single line, `pretty` to lay
+                  out fractions, roots and limits over several lines, `mathml`
+                  for Presentation MathML, `html` for Unicode with <sup>,
+                  <sub> and tables, `typst` for Typst math or `ascii` for
+                  plain ASCII
+  --macros FILE   expand the \\newcommand, \\DeclareMathOperator and \\def
+                  definitions in FILE; may be given more than once
+  --symbols FILE  add or replace symbols with those in FILE, one per line with
+                  tab-separated name, output, class and arity
+
+exit codes:
+  0  success
+  1  usage or I/O error
+  2  the conversion was lossy, e.g. unknown commands were left untouched
+  3  --check: some input would change when converted";
+
+const EXIT_LOSSY: u8 = 2;
+const EXIT_CHECK: u8 = 3;
+
+#[derive(Default)]
+struct Flags {
+    in_place: bool,
+    check: bool,
+    reverse: bool,
+    markdown: bool,
+    options: Options,
+}
+
+// What happened to a single input, used to pick the exit code.
+struct Outcome {
+    changed: bool,
+    lossy: bool,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args, &mut io::stdin(), &mut io::stdout()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("ctrl-tex-cli: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String], stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<ExitCode, String> {
+    let Some((command, rest)) = args.split_first() else {
+        return Err(format!("missing command\n\n{}", USAGE));
+    };
+
+    let mut flags = Flags {
+        markdown: command == "convert-markdown",
+        ..Flags::default()
+    };
+    let mut operands = Vec::new();
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-i" | "--in-place" => flags.in_place = true,
+            "--check" => flags.check = true,
+            "--reverse" => flags.reverse = true,
+            "--scope" => {
+                let scope = rest.next().ok_or("--scope needs a value")?;
+                flags.options.scope = scope.parse()?;
+            }
+            "--syntax" => {
+                let syntax = rest.next().ok_or("--syntax needs a value")?;
+                flags.options.syntax = syntax.parse()?;
+            }
+            "--spacing" => {
+                let spacing = rest.next().ok_or("--spacing needs a value")?;
+                flags.options.spacing = spacing.parse()?;
+            }
+            "--fractions" => {
+                let fractions = rest.next().ok_or("--fractions needs a value")?;
+                flags.options.fractions = fractions.parse()?;
+            }
+            "--format" => {
+                let format = rest.next().ok_or("--format needs a value")?;
+                flags.options.format = format.parse()?;
+            }
+            "--macros" => {
+                let path = rest.next().ok_or("--macros needs a value")?;
+                let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+                flags.options.macros.extend(Macros::parse(&text));
+            }
+            "--symbols" => {
+                let path = rest.next().ok_or("--symbols needs a value")?;
+                let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+                flags
+                    .options
+                    .symbols
+                    .load_overrides(&text)
+                    .map_err(|e| format!("{}: {}", path, e))?;
+            }
+            "-h" | "--help" => {
+                print_usage(stdout)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            "--" => {
+                operands.extend(rest.by_ref().map(String::as_str));
+                break;
+            }
+            "-" => operands.push(arg.as_str()),
+            _ if arg.starts_with('-') => {
+                return Err(format!("unknown option `{}`\n\n{}", arg, USAGE));
+            }
+            _ => operands.push(arg.as_str()),
+        }
+    }
+
+    let outcomes = match command.as_str() {
+        "-h" | "--help" => {
+            print_usage(stdout)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        "convert" if flags.in_place => {
+            return Err("--in-place only applies to `file` and `convert-markdown`".to_string());
+        }
+        "convert" if operands.is_empty() => {
+            let input = read_stdin(stdin)?;
+            vec![convert("<stdin>", &input, None, &flags, stdout)?]
+        }
+        "convert" => vec![convert(
+            "<args>",
+            &operands.join(" "),
+            None,
+            &flags,
+            stdout,
+        )?],
+        "file" | "convert-markdown" if operands.is_empty() => {
+            return Err(format!("no files given\n\n{}", USAGE));
+        }
+        "file" | "convert-markdown" => operands
+            .iter()
+            .map(|&path| {
+                if path == "-" {
+                    let input = read_stdin(stdin)?;
+                    convert("<stdin>", &input, None, &flags, stdout)
+                } else {
+                    let input = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+                    convert(path, &input, Some(path), &flags, stdout)
+                }
+            })
+            .collect::<Result<_, _>>()?,
+        _ => return Err(format!("unknown command `{}`\n\n{}", command, USAGE)),
+    };
+
+    Ok(if flags.check && outcomes.iter().any(|o| o.changed) {
+        ExitCode::from(EXIT_CHECK)
+    } else if outcomes.iter().any(|o| o.lossy) {
+        ExitCode::from(EXIT_LOSSY)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn print_usage(stdout: &mut dyn Write) -> Result<(), String> {
+    writeln!(stdout, "{}", USAGE).map_err(|e| format!("<stdout>: {}", e))
+}
+
+fn read_stdin(stdin: &mut dyn Read) -> Result<String, String> {
+    let mut input = String::new();
+    stdin
+        .read_to_string(&mut input)
+        .map_err(|e| format!("<stdin>: {}", e))?;
+    Ok(input)
+}
+
+// Converts one input and writes the result to `path` with --in-place, or to
+// stdout otherwise. Nothing is written with --check.
+fn convert(
+    name: &str,
+    input: &str,
+    path: Option<&str>,
+    flags: &Flags,
+    stdout: &mut dyn Write,
+) -> Result<Outcome, String> {
+    let (mut output, report) = if flags.reverse {
+        let output = reverse::to_latex(input, &flags.options.symbols);
+        (output, Default::default())
+    } else if flags.markdown {
+        convert_markdown_with_report(input, &flags.options)
+    } else {
+        convert_with_report(input, &flags.options)
+    };
+    // math conversion drops all whitespace, including the final newline of a file
+    if input.ends_with('\n') && !output.ends_with('\n') {
+        output.push('\n');
+    }
+
+    for diagnostic in &report.diagnostics {
+        let (line, column) = line_column(input, diagnostic.span.start);
+        eprintln!("{}:{}:{}: {}", name, line, column, diagnostic);
+    }
+
+    let outcome = Outcome {
+        changed: output != input,
+        lossy: !report.is_lossless(),
+    };
+
+    if flags.check {
+        if outcome.changed {
+            eprintln!("{}: would be converted", name);
+        }
+    } else if let (true, Some(path)) = (flags.in_place, path) {
+        if outcome.changed {
+            fs::write(path, output).map_err(|e| format!("{}: {}", path, e))?;
+        }
+    } else {
+        if !output.ends_with('\n') {
+            output.push('\n');
+        }
+        stdout
+            .write_all(output.as_bytes())
+            .map_err(|e| format!("<stdout>: {}", e))?;
+    }
+
+    Ok(outcome)
+}
+
+// 1-based line and column (in chars) of a byte offset
+fn line_column(input: &str, offset: usize) -> (usize, usize) {
+    let before = &input[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The exit code and stdout of the CLI run with `args` and `stdin`.
+    fn cli(args: &[&str], stdin: &str) -> (Result<ExitCode, String>, String) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut stdout = Vec::new();
+        let code = run(&args, &mut stdin.as_bytes(), &mut stdout);
+        (code, String::from_utf8(stdout).unwrap())
+    }
+
+    // A file in the temporary directory holding `content`, named after the test.
+    fn temp_file(name: &str, content: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("ctrl-tex-cli-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(
+            cli(&["convert", r"\alpha", r"\le b"], ""),
+            (Ok(ExitCode::SUCCESS), "α≤b\n".to_string())
+        );
+        assert_eq!(
+            cli(&["convert", r"\foo"], ""),
+            (Ok(ExitCode::from(EXIT_LOSSY)), "\\foo\n".to_string())
+        );
+        assert_eq!(
+            cli(&["convert", "--check", r"\alpha"], ""),
+            (Ok(ExitCode::from(EXIT_CHECK)), String::new())
+        );
+        assert_eq!(
+            cli(&["convert", "--check", "a+b"], ""),
+            (Ok(ExitCode::SUCCESS), String::new())
+        );
+        assert!(cli(&["convert", "-x"], "").0.is_err());
+        assert!(cli(&["convert", "--in-place", "x"], "").0.is_err());
+        assert!(cli(&["frobnicate"], "").0.is_err());
+    }
+
+    #[test]
+    fn test_end_of_options() {
+        assert_eq!(
+            cli(&["convert", "--", "-x^2", "--check"], ""),
+            (Ok(ExitCode::SUCCESS), "-x²--check\n".to_string())
+        );
+        let path = temp_file("end-of-options.tex", "x^2\n");
+        assert_eq!(
+            cli(&["file", "--check", "--", &path], ""),
+            (Ok(ExitCode::from(EXIT_CHECK)), String::new())
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_stdin() {
+        assert_eq!(
+            cli(&["convert"], "x^2\n"),
+            (Ok(ExitCode::SUCCESS), "x²\n".to_string())
+        );
+        let path = temp_file("stdin.tex", r"\beta");
+        assert_eq!(
+            cli(&["file", &path, "-"], r"\alpha"),
+            (Ok(ExitCode::SUCCESS), "β\nα\n".to_string())
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_in_place() {
+        let path = temp_file("in-place.md", "Let $x_1 \\in \\mathbb{R}$.\n");
+        assert_eq!(
+            cli(&["convert-markdown", "--in-place", &path], ""),
+            (Ok(ExitCode::SUCCESS), String::new())
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Let x₁∈ℝ.\n");
+        // converting again changes nothing
+        assert_eq!(
+            cli(&["convert-markdown", "--check", &path], ""),
+            (Ok(ExitCode::SUCCESS), String::new())
+        );
+
+        fs::write(&path, "$\\foo$\n").unwrap();
+        assert_eq!(
+            cli(&["file", "-i", "--check", &path], ""),
+            (Ok(ExitCode::from(EXIT_CHECK)), String::new())
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "$\\foo$\n");
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+use ctrl_tex::{Format, Macros, Options, Syntax, convert_math_with_report};
+use serde_json::{Value, json};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+// A pandoc JSON filter: `pandoc --filter ctrl-tex-pandoc` replaces every
+// `Math` element with its Unicode conversion, for outputs without MathJax
+// like plain text, EPUB or man pages. The conversion is configured in the
+// document's metadata:
+//
+//   ctrl-tex:
+//     spacing: readable
+//     fractions: linear
+//     format: mathml
+//     macros: macros.tex
+//     symbols: symbols.tsv
+//
+// The `mathml` and `html` formats produce raw HTML, which only HTML and EPUB
+// outputs keep, and `typst` raw Typst for Typst output; for other outputs
+// they fall back to Unicode. `pretty` can't be used, since display math would
+// span several lines.
+
+const HTML_TARGETS: [&str; 6] = ["html", "html4", "html5", "epub", "epub2", "epub3"];
+
+fn main() -> ExitCode {
+    // pandoc passes the output format as the first argument
+    let target = std::env::args().nth(1).unwrap_or_default();
+    match run(&target) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("ctrl-tex-pandoc: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(target: &str) -> Result<(), String> {
+    let mut input = String::new();
+    io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|e| format!("<stdin>: {}", e))?;
+    let mut document: Value =
+        serde_json::from_str(&input).map_err(|e| format!("<stdin>: {}", e))?;
+
+    let mut options = options(&document["meta"])?;
+    if options.format.is_markup() && !HTML_TARGETS.contains(&target) {
+        eprintln!(
+            "ctrl-tex-pandoc: the {} format needs HTML or EPUB output, using unicode for {}",
+            options.format, target
+        );
+        options.format = Format::Unicode;
+    }
+    if options.format == Format::Typst && target != "typst" {
+        eprintln!(
+            "ctrl-tex-pandoc: the typst format needs Typst output, using unicode for {}",
+            target
+        );
+        options.format = Format::Unicode;
+    }
+
+    filter(&mut document, &options);
+    let output = serde_json::to_string(&document).map_err(|e| e.to_string())?;
+    io::stdout()
+        .write_all(output.as_bytes())
+        .map_err(|e| format!("<stdout>: {}", e))
+}
+
+// Reads the `ctrl-tex` map from the document metadata.
+fn options(meta: &Value) -> Result<Options, String> {
+    // pandoc math is always TeX
+    let mut options = Options {
+        syntax: Syntax::Latex,
+        ..Options::default()
+    };
+    let settings = match &meta["ctrl-tex"] {
+        Value::Null => return Ok(options),
+        Value::Object(map) if map.get("t") == Some(&json!("MetaMap")) => &map["c"],
+        _ => return Err("the ctrl-tex metadata must be a map".to_string()),
+    };
+    let Value::Object(settings) = settings else {
+        return Err("the ctrl-tex metadata must be a map".to_string());
+    };
+
+    for (key, value) in settings {
+        let value = meta_string(value).ok_or_else(|| format!("ctrl-tex.{} must be text", key))?;
+        match key.as_str() {
+            "spacing" => options.spacing = value.parse()?,
+            "fractions" => options.fractions = value.parse()?,
+            "format" => options.format = value.parse()?,
+            "macros" => {
+                let text = fs::read_to_string(&value).map_err(|e| format!("{}: {}", value, e))?;
+                options.macros.extend(Macros::parse(&text));
+            }
+            "symbols" => {
+                let text = fs::read_to_string(&value).map_err(|e| format!("{}: {}", value, e))?;
+                options
+                    .symbols
+                    .load_overrides(&text)
+                    .map_err(|e| format!("{}: {}", value, e))?;
+            }
+            _ => return Err(format!("unknown setting ctrl-tex.{}", key)),
+        }
+    }
+    if options.format == Format::Pretty {
+        return Err(
+            "the pretty format spans several lines, which a pandoc inline can't".to_string(),
+        );
+    }
+    Ok(options)
+}
+
+// The text of a metadata value, which pandoc parses as Markdown: `readable`
+// becomes `MetaInlines` with a single `Str`.
+fn meta_string(value: &Value) -> Option<String> {
+    match value["t"].as_str()? {
+        "MetaString" => value["c"].as_str().map(str::to_string),
+        "MetaInlines" => {
+            let mut text = String::new();
+            for inline in value["c"].as_array()? {
+                match inline["t"].as_str()? {
+                    "Str" => text += inline["c"].as_str()?,
+                    "Space" => text.push(' '),
+                    _ => return None,
+                }
+            }
+            Some(text)
+        }
+        _ => None,
+    }
+}
+
+// Replaces every `Math` element in `value` and everything nested inside it.
+fn filter(value: &mut Value, options: &Options) {
+    match value {
+        Value::Object(map) if map.get("t") == Some(&json!("Math")) => {
+            if let Some(replacement) = convert_math(&map["c"], options) {
+                *value = replacement;
+            }
+        }
+        Value::Object(map) => map.values_mut().for_each(|v| filter(v, options)),
+        Value::Array(values) => values.iter_mut().for_each(|v| filter(v, options)),
+        _ => {}
+    }
+}
+
+// `Math` has the math type and the LaTeX as its contents.
+fn convert_math(contents: &Value, options: &Options) -> Option<Value> {
+    let latex = contents.get(1)?.as_str()?;
+    let display = contents.get(0)?["t"] == "DisplayMath";
+    let (output, report) = convert_math_with_report(latex, options, display);
+    for diagnostic in &report.diagnostics {
+        eprintln!(
+            "ctrl-tex-pandoc: {} in `{}`",
+            diagnostic,
+            &latex[diagnostic.span.bytes()]
+        );
+    }
+
+    Some(if options.format.is_markup() {
+        json!({"t": "RawInline", "c": ["html", output]})
+    } else if options.format == Format::Typst {
+        let output = if display {
+            format!("$ {} $", output)
+        } else {
+            format!("${}$", output)
+        };
+        json!({"t": "RawInline", "c": ["typst", output]})
+    } else {
+        json!({"t": "Str", "c": output})
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn math(kind: &str, latex: &str) -> Value {
+        json!({"t": "Math", "c": [{"t": kind}, latex]})
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut document = json!({
+            "pandoc-api-version": [1, 23, 1],
+            "meta": {},
+            "blocks": [{"t": "Para", "c": [
+                {"t": "Str", "c": "Let"},
+                {"t": "Space"},
+                math("InlineMath", r"x \in \mathbb{R}"),
+                {"t": "Emph", "c": [math("DisplayMath", r"\frac{1}{2}")]},
+            ]}],
+        });
+        let options = options(&document["meta"]).unwrap();
+        filter(&mut document, &options);
+        let para = &document["blocks"][0]["c"];
+        assert_eq!(para[2], json!({"t": "Str", "c": "x∈ℝ"}));
+        assert_eq!(para[3]["c"][0], json!({"t": "Str", "c": "½"}));
+        assert_eq!(para[0], json!({"t": "Str", "c": "Let"}));
+    }
+
+    #[test]
+    fn test_metadata() {
+        let meta = json!({"ctrl-tex": {"t": "MetaMap", "c": {
+            "spacing": {"t": "MetaInlines", "c": [{"t": "Str", "c": "readable"}]},
+            "format": {"t": "MetaString", "c": "mathml"},
+        }}});
+        let options = options(&meta).unwrap();
+        assert_eq!(options.spacing.to_string(), "readable");
+        assert_eq!(options.format, Format::MathMl);
+
+        let mut display = math("DisplayMath", "x");
+        filter(&mut display, &options);
+        assert_eq!(display["t"], "RawInline");
+        assert!(
+            display["c"][1]
+                .as_str()
+                .unwrap()
+                .contains("display=\"block\"")
+        );
+
+        let options = Options {
+            format: Format::Typst,
+            ..Options::default()
+        };
+        let mut inline = math("InlineMath", r"\frac{a}{b}");
+        filter(&mut inline, &options);
+        assert_eq!(
+            inline,
+            json!({"t": "RawInline", "c": ["typst", "$frac(a, b)$"]})
+        );
+
+        let meta = json!({"ctrl-tex": {"t": "MetaMap", "c": {
+            "color": {"t": "MetaString", "c": "red"},
+        }}});
+        assert!(super::options(&meta).is_err());
+    }
+}
@@ -0,0 +1,462 @@
+use ctrl_tex::{Format, Fractions, Macros, Options, Scope, Spacing, SymbolTable, Syntax};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub hotkey: Hotkey,
+    // converts the selected Unicode back to LaTeX
+    pub reverse_hotkey: Hotkey,
+    pub restore_clipboard: bool,
+    pub delays: Delays,
+    pub conversion: Conversion,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            hotkey: Hotkey {
+                ctrl: true,
+                shift: true,
+                alt: false,
+                meta: false,
+                key: Key::Char('L'),
+            },
+            reverse_hotkey: Hotkey {
+                ctrl: true,
+                shift: true,
+                alt: true,
+                meta: false,
+                key: Key::Char('L'),
+            },
+            restore_clipboard: true,
+            delays: Delays::default(),
+            conversion: Conversion::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn hotkey(&self, action: Action) -> &Hotkey {
+        match action {
+            Action::ToUnicode => &self.hotkey,
+            Action::ToLatex => &self.reverse_hotkey,
+        }
+    }
+
+    // The action whose hotkey `is_pressed`. If both are, the one with more
+    // modifiers wins, so Ctrl+Shift+Alt+L doesn't also count as Ctrl+Shift+L.
+    pub fn action_for(&self, is_pressed: impl Fn(&Hotkey) -> bool) -> Option<Action> {
+        [Action::ToUnicode, Action::ToLatex]
+            .into_iter()
+            .filter(|&action| is_pressed(self.hotkey(action)))
+            .max_by_key(|&action| self.hotkey(action).modifier_count())
+    }
+}
+
+// What a hotkey does with the selection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    ToUnicode,
+    ToLatex,
+}
+
+// All delays are in milliseconds.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Delays {
+    // before simulating the copy, so the hotkey is no longer held
+    pub release: u64,
+    // between the press, click and release of a simulated shortcut
+    pub keystroke: u64,
+    // after the copy, for the application to fill the clipboard
+    pub copy: u64,
+    // after filling the clipboard, before pasting
+    pub paste: u64,
+    // after pasting, before putting the previous clipboard contents back
+    pub restore: u64,
+}
+
+impl Default for Delays {
+    fn default() -> Self {
+        Delays {
+            release: 50,
+            keystroke: 20,
+            copy: 150,
+            paste: 50,
+            restore: 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Conversion {
+    #[serde(deserialize_with = "from_str")]
+    pub scope: Scope,
+    #[serde(deserialize_with = "from_str")]
+    pub syntax: Syntax,
+    #[serde(deserialize_with = "from_str")]
+    pub spacing: Spacing,
+    #[serde(deserialize_with = "from_str")]
+    pub fractions: Fractions,
+    #[serde(deserialize_with = "from_str")]
+    pub format: Format,
+    // a file of `\newcommand`s and the like, relative to the config file
+    pub macro_file: Option<PathBuf>,
+    // the definitions read from `macro_file` by `load`
+    #[serde(skip)]
+    pub macros: Macros,
+    // a table of extra or replaced symbols in the format of `src/symbols.tsv`,
+    // relative to the config file
+    pub symbol_file: Option<PathBuf>,
+    // the built-in symbols with those from `symbol_file` read by `load`
+    #[serde(skip)]
+    pub symbols: SymbolTable,
+}
+
+impl Conversion {
+    pub fn options(&self) -> Options {
+        Options {
+            scope: self.scope,
+            syntax: self.syntax,
+            spacing: self.spacing,
+            fractions: self.fractions,
+            format: self.format,
+            macros: self.macros.clone(),
+            symbols: self.symbols.clone(),
+        }
+    }
+}
+
+// Deserializes a string with the type's `FromStr`, for library enums.
+fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Hotkey {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub meta: bool,
+    pub key: Key,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    // an ASCII letter (stored uppercase) or digit
+    Char(char),
+    // F1 to F24
+    Function(u8),
+}
+
+impl Hotkey {
+    fn modifier_count(&self) -> usize {
+        [self.ctrl, self.shift, self.alt, self.meta]
+            .into_iter()
+            .filter(|&held| held)
+            .count()
+    }
+}
+
+impl TryFrom<String> for Hotkey {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let mut hotkey = Hotkey {
+            ctrl: false,
+            shift: false,
+            alt: false,
+            meta: false,
+            key: Key::Char(' '),
+        };
+        let mut key = None;
+
+        for part in s.split('+').map(str::trim) {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => hotkey.ctrl = true,
+                "shift" => hotkey.shift = true,
+                "alt" => hotkey.alt = true,
+                "meta" | "super" | "win" | "cmd" => hotkey.meta = true,
+                _ if key.is_some() => {
+                    return Err(format!("hotkey `{}` has more than one non-modifier key", s));
+                }
+                _ => key = Some(parse_key(part).map_err(|e| format!("{} in hotkey `{}`", e, s))?),
+            }
+        }
+
+        hotkey.key = key.ok_or_else(|| format!("hotkey `{}` has no non-modifier key", s))?;
+        Ok(hotkey)
+    }
+}
+
+fn parse_key(name: &str) -> Result<Key, String> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next())
+        && c.is_ascii_alphanumeric()
+    {
+        return Ok(Key::Char(c.to_ascii_uppercase()));
+    }
+
+    if let Some(n) = name
+        .strip_prefix(['F', 'f'])
+        .and_then(|n| n.parse::<u8>().ok())
+        && (1..=24).contains(&n)
+    {
+        return Ok(Key::Function(n));
+    }
+
+    Err(format!(
+        "unknown key name `{}` (expected a letter, a digit or F1-F24)",
+        name
+    ))
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "Ctrl"),
+            (self.shift, "Shift"),
+            (self.alt, "Alt"),
+            (self.meta, "Meta"),
+        ] {
+            if held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match self.key {
+            Key::Char(c) => write!(f, "{}", c),
+            Key::Function(n) => write!(f, "F{}", n),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    MacroFile(PathBuf, io::Error),
+    SymbolFile(PathBuf, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Parse(e) => write!(f, "{}", e),
+            Error::MacroFile(path, e) => write!(f, "macro file {}: {}", path.display(), e),
+            Error::SymbolFile(path, e) => write!(f, "symbol file {}: {}", path.display(), e),
+        }
+    }
+}
+
+// `$XDG_CONFIG_HOME/ctrl-tex/config.toml` (falling back to `~/.config`) or
+// `%APPDATA%\ctrl-tex\config.toml` on Windows.
+pub fn path() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let base = std::env::var_os("APPDATA").map(PathBuf::from);
+    #[cfg(not(target_os = "windows"))]
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+
+    base.map(|dir| dir.join("ctrl-tex").join("config.toml"))
+}
+
+// A missing file is not an error, the defaults are used instead. The macro
+// and symbol files named in the config are read as well and must exist.
+pub fn load(path: &Path) -> Result<Config, Error> {
+    let mut config = match fs::read_to_string(path) {
+        Ok(text) => parse(&text)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Config::default(),
+        Err(e) => return Err(Error::Io(e)),
+    };
+    if let Some(macro_path) = relative_path(path, &config.conversion.macro_file) {
+        let text = fs::read_to_string(&macro_path).map_err(|e| Error::MacroFile(macro_path, e))?;
+        config.conversion.macros = Macros::parse(&text);
+    }
+    if let Some(symbol_path) = relative_path(path, &config.conversion.symbol_file) {
+        let symbols = fs::read_to_string(&symbol_path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| config.conversion.symbols.load_overrides(&text));
+        symbols.map_err(|e| Error::SymbolFile(symbol_path, e))?;
+    }
+    Ok(config)
+}
+
+// A file named in the config, with a relative path resolved against the
+// directory of the config file at `path`.
+fn relative_path(path: &Path, file: &Option<PathBuf>) -> Option<PathBuf> {
+    Some(path.parent().unwrap_or(Path::new("")).join(file.as_ref()?))
+}
+
+pub fn parse(text: &str) -> Result<Config, Error> {
+    toml::from_str(text).map_err(Error::Parse)
+}
+
+// Polls the file and its macro and symbol files for changes and swaps in the new config.
+// An invalid file is reported and the previous config stays active.
+pub fn watch(
+    path: PathBuf,
+    config: Arc<RwLock<Config>>,
+    on_reload: impl Fn(&Config) + Send + 'static,
+) {
+    thread::spawn(move || {
+        let stamps = |config: &Config| {
+            let conversion = &config.conversion;
+            let file = |file| relative_path(&path, file).as_deref().and_then(modified);
+            (
+                modified(&path),
+                file(&conversion.macro_file),
+                file(&conversion.symbol_file),
+            )
+        };
+        let mut last_modified = stamps(&config.read().unwrap());
+        loop {
+            thread::sleep(Duration::from_secs(1));
+            let current = stamps(&config.read().unwrap());
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            match load(&path) {
+                Ok(new_config) => {
+                    on_reload(&new_config);
+                    *config.write().unwrap() = new_config;
+                    eprintln!("Reloaded {}", path.display());
+                }
+                Err(e) => eprintln!("{}: {}", path.display(), e),
+            }
+        }
+    });
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(parse("").unwrap(), Config::default());
+        assert_eq!(Config::default().hotkey.to_string(), "Ctrl+Shift+L");
+        assert_eq!(
+            Config::default().reverse_hotkey.to_string(),
+            "Ctrl+Shift+Alt+L"
+        );
+    }
+
+    #[test]
+    fn test_action_for() {
+        let config = Config::default();
+        let held = |modifiers: usize| move |hotkey: &Hotkey| hotkey.modifier_count() <= modifiers;
+        assert_eq!(config.action_for(held(1)), None);
+        assert_eq!(config.action_for(held(2)), Some(Action::ToUnicode));
+        assert_eq!(config.action_for(held(3)), Some(Action::ToLatex));
+
+        let config = parse(r#"reverse_hotkey = "Ctrl+Shift+U""#).unwrap();
+        let pressed = |key| move |hotkey: &Hotkey| hotkey.key == key;
+        assert_eq!(
+            config.action_for(pressed(Key::Char('U'))),
+            Some(Action::ToLatex)
+        );
+        assert_eq!(
+            config.action_for(pressed(Key::Char('L'))),
+            Some(Action::ToUnicode)
+        );
+    }
+
+    #[test]
+    fn test_hotkey() {
+        let config = parse(r#"hotkey = "alt + super + f9""#).unwrap();
+        assert_eq!(config.hotkey.to_string(), "Alt+Meta+F9");
+        assert_eq!(config.hotkey.key, Key::Function(9));
+    }
+
+    #[test]
+    fn test_delays() {
+        let config = parse("[delays]\ncopy = 300").unwrap();
+        assert_eq!(config.delays.copy, 300);
+        assert_eq!(config.delays.paste, Delays::default().paste);
+    }
+
+    #[test]
+    fn test_restore_clipboard() {
+        assert!(Config::default().restore_clipboard);
+        let config = parse("restore_clipboard = false\n[delays]\nrestore = 1000").unwrap();
+        assert!(!config.restore_clipboard);
+        assert_eq!(config.delays.restore, 1000);
+    }
+
+    #[test]
+    fn test_conversion() {
+        assert_eq!(Config::default().conversion.scope, Scope::Auto);
+        let config = parse("[conversion]\nscope = \"delimited\"").unwrap();
+        assert_eq!(config.conversion.options().scope, Scope::Delimited);
+        let err = parse("[conversion]\nscope = \"some\"")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("unknown scope `some`"), "{}", err);
+        let config = parse("[conversion]\nspacing = \"tex-like\"").unwrap();
+        assert_eq!(config.conversion.options().spacing, Spacing::TexLike);
+        let config = parse("[conversion]\nfractions = \"linear\"").unwrap();
+        assert_eq!(config.conversion.options().fractions, Fractions::Linear);
+        let config = parse("[conversion]\nformat = \"pretty\"").unwrap();
+        assert_eq!(config.conversion.options().format, Format::Pretty);
+        assert_eq!(Config::default().conversion.syntax, Syntax::Auto);
+        let config = parse("[conversion]\nsyntax = \"asciimath\"").unwrap();
+        assert_eq!(config.conversion.options().syntax, Syntax::AsciiMath);
+    }
+
+    #[test]
+    fn test_macro_file() {
+        let dir = std::env::temp_dir().join(format!("ctrl-tex-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, "[conversion]\nmacro_file = \"macros.tex\"").unwrap();
+        let err = load(&path).unwrap_err().to_string();
+        assert!(err.starts_with("macro file"), "{}", err);
+
+        fs::write(dir.join("macros.tex"), r"\newcommand{\R}{\mathbb{R}}").unwrap();
+        let config = load(&path).unwrap();
+        assert!(config.conversion.options().macros.contains("R"));
+
+        fs::write(&path, "[conversion]\nsymbol_file = \"symbols.tsv\"").unwrap();
+        fs::write(dir.join("symbols.tsv"), "abs\t|#1|\tord\tone").unwrap();
+        let err = load(&path).unwrap_err().to_string();
+        assert!(err.contains("line 1: arity `one`"), "{}", err);
+        fs::write(dir.join("symbols.tsv"), "abs\t|#1|\tord\t1").unwrap();
+        let symbols = load(&path).unwrap().conversion.options().symbols;
+        assert_eq!(symbols.get("abs").unwrap().arity, 1);
+        assert!(symbols.contains("alpha"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_key_name() {
+        let err = parse(r#"hotkey = "Ctrl+Enterr""#).unwrap_err().to_string();
+        assert!(err.contains("unknown key name `Enterr`"), "{}", err);
+        assert!(parse(r#"hotkey = "Ctrl+F25""#).is_err());
+        assert!(parse(r#"hotkey = "Ctrl+Shift""#).is_err());
+        assert!(parse(r#"hotkey = "Ctrl+A+B""#).is_err());
+    }
+}
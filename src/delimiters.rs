@@ -0,0 +1,140 @@
+use std::ops::Range;
+
+// A piece of math in running text, e.g. `$x$` or `\[ x \]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MathSpan {
+    // byte range including the delimiters
+    pub outer: Range<usize>,
+    // byte range of the math between the delimiters
+    pub inner: Range<usize>,
+    // `$$...$$` and `\[...\]` as opposed to `$...$` and `\(...\)`
+    pub display: bool,
+}
+
+// Finds all delimited math in `input`, in order. `\$` is an escaped dollar
+// sign and never starts or ends math. Delimiters that are never closed are
+// left alone as ordinary text. A single `$` follows pandoc's rules, so the
+// amounts in `$5 and $10` aren't math: it only opens before a non-space
+// character and only closes after one, and not before a digit.
+pub fn find_math_spans(input: &str) -> Vec<MathSpan> {
+    let bytes = input.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let (open, close, display) = match (bytes[i], bytes.get(i + 1)) {
+            (b'\\', Some(b'(')) => ("\\(", "\\)", false),
+            (b'\\', Some(b'[')) => ("\\[", "\\]", true),
+            (b'\\', Some(_)) => {
+                // an escape like `\$` or `\\`, or a command outside math
+                i += 2;
+                continue;
+            }
+            (b'$', Some(b'$')) => ("$$", "$$", true),
+            (b'$', Some(next)) if !next.is_ascii_whitespace() => ("$", "$", false),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        let start = i + open.len();
+        match find_closing(input, start, close, open == "$") {
+            Some(end) => {
+                spans.push(MathSpan {
+                    outer: i..end + close.len(),
+                    inner: start..end,
+                    display,
+                });
+                i = end + close.len();
+            }
+            None => i = start,
+        }
+    }
+
+    spans
+}
+
+// Byte offset of the first `close` at or after `from` that isn't escaped or
+// inside braces, where `\text{ if $x$ }` can have math of its own. With
+// `dollar`, a single `$` after a space or before a digit doesn't count.
+fn find_closing(input: &str, from: usize, close: &str, dollar: bool) -> Option<usize> {
+    let bytes = input.as_bytes();
+    let mut depth = 0usize;
+    let mut i = from;
+    while i < bytes.len() {
+        let closes = !dollar
+            || i > from
+                && !bytes[i - 1].is_ascii_whitespace()
+                && !bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+        match bytes[i] {
+            _ if depth == 0 && closes && bytes[i..].starts_with(close.as_bytes()) => {
+                return Some(i);
+            }
+            b'{' => depth += 1,
+            b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        // skip escapes so `\$`, `\{` and `\\)` don't close anything
+        i += if bytes[i] == b'\\' { 2 } else { 1 };
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inner(input: &str) -> Vec<&str> {
+        find_math_spans(input)
+            .into_iter()
+            .map(|span| &input[span.inner])
+            .collect()
+    }
+
+    #[test]
+    fn test_inline_and_display() {
+        let input = r"Let $x_1$ and $$y$$ or \(a\) and \[b\].";
+        let spans = find_math_spans(input);
+        assert_eq!(inner(input), ["x_1", "y", "a", "b"]);
+        assert_eq!(&input[spans[0].outer.clone()], "$x_1$");
+        assert_eq!(&input[spans[3].outer.clone()], r"\[b\]");
+        let display: Vec<_> = spans.iter().map(|s| s.display).collect();
+        assert_eq!(display, [false, true, false, true]);
+    }
+
+    #[test]
+    fn test_escaped_dollars() {
+        assert_eq!(inner(r"costs \$5 and $x$"), ["x"]);
+        assert_eq!(inner(r"$a \$ b$"), [r"a \$ b"]);
+        assert_eq!(inner(r"\\ \(a \\) b\)"), [r"a \\) b"]);
+        assert_eq!(inner(r"é\é $é$"), ["é"]);
+        assert_eq!(
+            inner(r"$\text{cost in \$}$ and $x$"),
+            [r"\text{cost in \$}", "x"]
+        );
+    }
+
+    #[test]
+    fn test_math_in_text() {
+        assert_eq!(inner(r"$$a \text{ if $b$ } c$$"), [r"a \text{ if $b$ } c"]);
+        assert_eq!(inner(r"$a \text{ if $b$ } c$"), [r"a \text{ if $b$ } c"]);
+        assert_eq!(inner(r"\(\{x \mid x\)"), [r"\{x \mid x"]);
+    }
+
+    #[test]
+    fn test_currency() {
+        assert_eq!(inner("It costs $5 and $10 today"), Vec::<&str>::new());
+        assert_eq!(inner("from $ 5 to $x$"), ["x"]);
+        assert_eq!(inner("$x $ and $y$5 or $z$."), ["x $ and $y$5 or $z"]);
+        assert_eq!(inner("$$ x $$ costs $3"), [" x "]);
+    }
+
+    #[test]
+    fn test_unclosed() {
+        assert_eq!(inner("costs $5"), Vec::<&str>::new());
+        assert_eq!(inner("$$x$"), Vec::<&str>::new());
+        assert_eq!(inner(r"\( x"), Vec::<&str>::new());
+        assert_eq!(inner("no math here"), Vec::<&str>::new());
+    }
+}
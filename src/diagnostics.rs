@@ -0,0 +1,98 @@
+use crate::token::Span;
+use std::fmt;
+
+// Something in the input that could not be converted faithfully.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    // a command without a Unicode equivalent, kept as `\name`
+    UnknownCommand(String),
+    // a character in a superscript without a superscript form, kept as `^{...}`
+    UnrepresentableSuperscript(char),
+    // a character in a subscript without a subscript form, kept as `_{...}`
+    UnrepresentableSubscript(char),
+    // a character without an ASCII form in ASCII output, kept as is
+    UnrepresentableAscii(char),
+    // a `{` without a matching `}`
+    UnclosedBrace,
+    // a `}` without a matching `{`
+    UnmatchedBrace,
+    // an optional argument `[` without a matching `]`
+    UnclosedBracket,
+    // a command, `^` or `_` at the end of a group or the input, as written
+    MissingArgument(String),
+    // an environment that is laid out like a plain matrix
+    UnknownEnvironment(String),
+    // a `\begin{name}` without a matching `\end{name}`
+    UnclosedEnvironment(String),
+    // a `\left` without a matching `\right`
+    UnclosedDelimiter,
+    // a `\right` or `\middle` outside of `\left ... \right`, as written
+    UnmatchedDelimiter(String),
+    // a macro that expands too deeply or too often, probably recursively
+    MacroLimit(String),
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiagnosticKind::UnknownCommand(name) => write!(f, "unknown command `\\{}`", name),
+            DiagnosticKind::UnrepresentableSuperscript(c) => {
+                write!(f, "`{}` has no superscript form", c)
+            }
+            DiagnosticKind::UnrepresentableSubscript(c) => {
+                write!(f, "`{}` has no subscript form", c)
+            }
+            DiagnosticKind::UnrepresentableAscii(c) => write!(f, "`{}` has no ASCII form", c),
+            DiagnosticKind::UnclosedBrace => write!(f, "`{{` is never closed"),
+            DiagnosticKind::UnmatchedBrace => write!(f, "unmatched `}}`"),
+            DiagnosticKind::UnclosedBracket => write!(f, "`[` is never closed"),
+            DiagnosticKind::MissingArgument(command) => {
+                write!(f, "missing argument for `{}`", command)
+            }
+            DiagnosticKind::UnknownEnvironment(name) => {
+                write!(f, "unknown environment `{}`", name)
+            }
+            DiagnosticKind::UnclosedEnvironment(name) => {
+                write!(f, "`\\begin{{{}}}` is never closed", name)
+            }
+            DiagnosticKind::UnclosedDelimiter => write!(f, "`\\left` is never closed by `\\right`"),
+            DiagnosticKind::UnmatchedDelimiter(command) => {
+                write!(f, "`{}` without a matching `\\left`", command)
+            }
+            DiagnosticKind::MacroLimit(name) => {
+                write!(f, "`\\{}` expands too often, it may be recursive", name)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+// Everything that was lost while converting an input, in order of position.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConversionReport {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ConversionReport {
+    pub fn is_lossless(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn unknown_commands(&self) -> impl Iterator<Item = &str> {
+        self.diagnostics.iter().filter_map(|d| match &d.kind {
+            DiagnosticKind::UnknownCommand(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+}
@@ -0,0 +1,145 @@
+use crate::config::{Action, Config, Hotkey, Key};
+use evdev::{Device, EventSummary, KeyCode};
+use std::collections::HashSet;
+use std::io;
+use std::sync::{Arc, Mutex, RwLock, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CTRL: [KeyCode; 2] = [KeyCode::KEY_LEFTCTRL, KeyCode::KEY_RIGHTCTRL];
+const SHIFT: [KeyCode; 2] = [KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_RIGHTSHIFT];
+const ALT: [KeyCode; 2] = [KeyCode::KEY_LEFTALT, KeyCode::KEY_RIGHTALT];
+const META: [KeyCode; 2] = [KeyCode::KEY_LEFTMETA, KeyCode::KEY_RIGHTMETA];
+
+type Pressed = Arc<Mutex<HashSet<KeyCode>>>;
+
+// Listens for the configured hotkeys on every keyboard under /dev/input and
+// calls `on_hotkey` with the action once the chord has been released. The
+// hotkeys are re-read on every key press, so config reloads take effect
+// immediately. Blocks forever.
+pub fn listen(config: Arc<RwLock<Config>>, mut on_hotkey: impl FnMut(Action)) -> io::Result<()> {
+    let keyboards: Vec<Device> = evdev::enumerate()
+        .map(|(_, device)| device)
+        .filter(|device| {
+            device
+                .supported_keys()
+                .is_some_and(|keys| keys.contains(KeyCode::KEY_A))
+        })
+        .collect();
+
+    if keyboards.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "no readable keyboard found in /dev/input (is the user in the `input` group?)",
+        ));
+    }
+
+    let pressed: Pressed = Arc::default();
+    let (tx, rx) = mpsc::channel();
+
+    for device in keyboards {
+        let config = Arc::clone(&config);
+        let pressed = Arc::clone(&pressed);
+        let tx = tx.clone();
+        thread::spawn(move || watch_device(device, config, pressed, tx));
+    }
+    drop(tx);
+
+    for (action, hotkey) in rx {
+        wait_for_release(&pressed, &hotkey);
+        on_hotkey(action);
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "lost all keyboard devices",
+    ))
+}
+
+fn watch_device(
+    mut device: Device,
+    config: Arc<RwLock<Config>>,
+    pressed: Pressed,
+    tx: mpsc::Sender<(Action, Hotkey)>,
+) {
+    let name = device.name().unwrap_or("keyboard").to_string();
+    loop {
+        let events = match device.fetch_events() {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("Stopped reading {}: {}", name, e);
+                return;
+            }
+        };
+
+        for event in events {
+            let EventSummary::Key(_, key, value) = event.destructure() else {
+                continue;
+            };
+
+            let mut pressed = pressed.lock().unwrap();
+            match value {
+                // press
+                1 => {
+                    pressed.insert(key);
+                    let config = config.read().unwrap();
+                    let action = config.action_for(|hotkey| {
+                        key == key_code(hotkey.key) && is_chord_held(&pressed, hotkey)
+                    });
+                    if let Some(action) = action
+                        && tx.send((action, config.hotkey(action).clone())).is_err()
+                    {
+                        return;
+                    }
+                }
+                // release
+                0 => {
+                    pressed.remove(&key);
+                }
+                // auto-repeat
+                _ => {}
+            }
+        }
+    }
+}
+
+fn key_code(key: Key) -> KeyCode {
+    let name = match key {
+        Key::Char(c) => format!("KEY_{}", c),
+        Key::Function(n) => format!("KEY_F{}", n),
+    };
+    // every key accepted by the config parser has an evdev name
+    name.parse().unwrap()
+}
+
+fn modifiers(hotkey: &Hotkey) -> impl Iterator<Item = &'static [KeyCode; 2]> {
+    [
+        (hotkey.ctrl, &CTRL),
+        (hotkey.shift, &SHIFT),
+        (hotkey.alt, &ALT),
+        (hotkey.meta, &META),
+    ]
+    .into_iter()
+    .filter(|(required, _)| *required)
+    .map(|(_, keys)| keys)
+}
+
+fn is_chord_held(pressed: &HashSet<KeyCode>, hotkey: &Hotkey) -> bool {
+    modifiers(hotkey).all(|keys| keys.iter().any(|k| pressed.contains(k)))
+}
+
+// the simulated Ctrl + C would otherwise be combined with the physically held modifiers
+fn wait_for_release(pressed: &Pressed, hotkey: &Hotkey) {
+    let trigger = key_code(hotkey.key);
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while Instant::now() < deadline {
+        let held = {
+            let pressed = pressed.lock().unwrap();
+            pressed.contains(&trigger) || modifiers(hotkey).flatten().any(|k| pressed.contains(k))
+        };
+        if !held {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
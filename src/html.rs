@@ -0,0 +1,216 @@
+use crate::Options;
+use crate::ast::{Command, Delimited, Environment, Expr, ExprKind};
+use crate::diagnostics::Diagnostic;
+use crate::mathml::column_alignment;
+use crate::renderer::{AtomClass, Fractions, Renderer, UnicodeRenderer, vulgar_fraction};
+use crate::token::Span;
+
+// HTML for rich text editors and email, which mostly can't show MathML: the
+// Unicode text, with scripts as `<sup>` and `<sub>`, fractions on a slash
+// between them, roots with an overline and environments as tables.
+#[derive(Default)]
+pub struct HtmlRenderer {
+    inline: UnicodeRenderer,
+    fractions: Fractions,
+}
+
+impl HtmlRenderer {
+    pub fn new() -> Self {
+        HtmlRenderer::default()
+    }
+
+    pub fn with_options(options: &Options) -> Self {
+        HtmlRenderer {
+            inline: UnicodeRenderer::with_options(options),
+            fractions: options.fractions,
+        }
+    }
+}
+
+impl Renderer for HtmlRenderer {
+    fn render(&self, exprs: &[Expr]) -> String {
+        self.sequence(exprs)
+    }
+
+    fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        self.inline.take_diagnostics()
+    }
+}
+
+impl HtmlRenderer {
+    fn sequence(&self, exprs: &[Expr]) -> String {
+        let mut result = String::new();
+        let mut previous: Option<(AtomClass, bool)> = None;
+        for (expr, atom) in exprs.iter().zip(self.inline.classify(exprs)) {
+            if let (Some(left), Some(right)) = (previous, atom) {
+                result += self.inline.space(left, right);
+            }
+            result += &self.expr(expr);
+            previous = atom.or(previous);
+        }
+        result
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Group(exprs) => self.sequence(exprs),
+            ExprKind::Superscript(base, sup) => {
+                format!("{}<sup>{}</sup>", self.expr(base), self.script(sup))
+            }
+            ExprKind::Subscript(base, sub) => {
+                format!("{}<sub>{}</sub>", self.expr(base), self.script(sub))
+            }
+            ExprKind::Environment(env) => {
+                self.inline.check_environment(env, expr.span);
+                self.environment(env)
+            }
+            ExprKind::Delimited(delimited) => self.delimited(delimited, expr.span),
+            ExprKind::Command(Command::Frac { numer, denom }) => self.fraction(numer, denom),
+            ExprKind::Command(Command::Sqrt { index, content }) => {
+                self.sqrt(index.as_deref(), content)
+            }
+            // symbols, styles, accents and text are the same as in Unicode
+            _ => escape(&self.inline.render_expr(expr)),
+        }
+    }
+
+    // Scripts are set without the spaces around operators, like in TeX.
+    fn script(&self, expr: &Expr) -> String {
+        self.inline.in_script(|| self.expr(expr))
+    }
+
+    fn fraction(&self, numer: &Expr, denom: &Expr) -> String {
+        let numer_html = self.expr(numer);
+        let denom_html = self.expr(denom);
+        if self.fractions == Fractions::Vulgar
+            && let Some(c) = vulgar_fraction(&numer_html, &denom_html)
+        {
+            return c.to_string();
+        }
+        if self.fractions == Fractions::Linear {
+            let parenthesize = |expr: &Expr, s: String| {
+                if self.inline.is_atomic(expr) {
+                    s
+                } else {
+                    format!("({})", s)
+                }
+            };
+            return format!(
+                "{}/{}",
+                parenthesize(numer, numer_html),
+                parenthesize(denom, denom_html)
+            );
+        }
+        format!(
+            "<sup>{}</sup>\u{2044}<sub>{}</sub>",
+            self.inline.in_script(|| self.expr(numer)),
+            self.inline.in_script(|| self.expr(denom))
+        )
+    }
+
+    fn sqrt(&self, index: Option<&Expr>, content: &Expr) -> String {
+        let radical = match index.map(|index| self.script(index)).as_deref() {
+            None | Some("" | "2") => "√".to_string(),
+            Some("3") => "∛".to_string(),
+            Some("4") => "∜".to_string(),
+            Some(index) => format!("<sup>{}</sup>√", index),
+        };
+        format!(
+            "{}<span style=\"text-decoration: overline\">{}</span>",
+            radical,
+            self.expr(content)
+        )
+    }
+
+    fn environment(&self, env: &Environment) -> String {
+        let mut table =
+            String::from("<table style=\"display: inline-table; vertical-align: middle\">");
+        for row in &env.rows {
+            table += "<tr>";
+            for (j, cell) in row.iter().enumerate() {
+                table += &format!(
+                    "<td style=\"text-align: {}\">{}</td>",
+                    column_alignment(env, j),
+                    self.expr(cell)
+                );
+            }
+            table += "</tr>";
+        }
+        table += "</table>";
+
+        let (open, close) = match env.name.as_str() {
+            "pmatrix" => ("(", ")"),
+            "bmatrix" => ("[", "]"),
+            "Bmatrix" => ("{", "}"),
+            "vmatrix" => ("|", "|"),
+            "Vmatrix" => ("‖", "‖"),
+            "cases" => ("{", ""),
+            _ => ("", ""),
+        };
+        format!("{}{}{}", open, table, close)
+    }
+
+    fn delimited(&self, delimited: &Delimited, span: Span) -> String {
+        let delimiter = |d: &str| escape(&self.inline.delimiter(d, span));
+        let mut result = delimiter(&delimited.open);
+        for (i, part) in delimited.parts.iter().enumerate() {
+            if let Some(middle) = i.checked_sub(1).and_then(|i| delimited.middles.get(i)) {
+                result += &delimiter(middle);
+            }
+            result += &self.expr(part);
+        }
+        result + &delimiter(&delimited.close)
+    }
+}
+
+// `s` with the characters that mean something in HTML escaped.
+pub(crate) fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result += "&amp;",
+            '<' => result += "&lt;",
+            '>' => result += "&gt;",
+            '"' => result += "&quot;",
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn render(input: &str) -> String {
+        let mut parser = Parser::new(Lexer::new(input));
+        HtmlRenderer::new().render(&parser.parse())
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render(r"x^2 + y_{i+1}"), "x<sup>2</sup>+y<sub>i+1</sub>");
+        assert_eq!(render(r"e^{q} < \alpha"), "e<sup>q</sup>&lt;α");
+        assert_eq!(
+            render(r"\frac{1}{2} + \frac{a}{b}"),
+            "½+<sup>a</sup>\u{2044}<sub>b</sub>"
+        );
+        assert_eq!(
+            render(r"\sqrt[n]{x}"),
+            "<sup>n</sup>√<span style=\"text-decoration: overline\">x</span>"
+        );
+        assert_eq!(render(r"\mathbb{R} \text{ & }"), "ℝ &amp; ");
+    }
+
+    #[test]
+    fn test_environment() {
+        assert_eq!(
+            render(r"\begin{cases} 1 & x \end{cases}"),
+            "{<table style=\"display: inline-table; vertical-align: middle\"><tr>\
+             <td style=\"text-align: left\">1</td><td style=\"text-align: left\">x</td>\
+             </tr></table>"
+        );
+    }
+}
@@ -0,0 +1,451 @@
+use crate::Options;
+use crate::ast::{Command, Delimited, Environment, Expr, ExprKind};
+use crate::renderer::{AtomClass, Spacing, UnicodeRenderer};
+use std::cell::Cell;
+
+// Prints expressions back as LaTeX in a canonical form: braces around
+// arguments and scripts only when they have more than one token, groups in
+// running math only where the input had one, spaces around relations and
+// binary operators, and no other spaces except where a command word would run
+// into the letters after it. Inputs that differ only in these ways, like
+// `x^{2}` and `x^2`, print the same, and parsing the output gives the same
+// expressions again.
+pub struct LatexPrinter {
+    // for the classes of atoms and the spaces between them
+    inline: UnicodeRenderer,
+    // inside the argument of `\text` and friends, where spaces are kept
+    text: Cell<bool>,
+    // inside a superscript or subscript, which are printed without spaces
+    script: Cell<bool>,
+}
+
+impl Default for LatexPrinter {
+    fn default() -> Self {
+        LatexPrinter::with_options(&Options::default())
+    }
+}
+
+impl LatexPrinter {
+    pub fn new() -> Self {
+        LatexPrinter::default()
+    }
+
+    // Only the symbol table matters, for telling relations and operators apart.
+    pub fn with_options(options: &Options) -> Self {
+        let options = Options {
+            spacing: Spacing::Readable,
+            ..options.clone()
+        };
+        LatexPrinter {
+            inline: UnicodeRenderer::with_options(&options),
+            text: Cell::new(false),
+            script: Cell::new(false),
+        }
+    }
+
+    pub fn print(&self, exprs: &[Expr]) -> String {
+        self.sequence(exprs)
+    }
+
+    fn sequence(&self, exprs: &[Expr]) -> String {
+        let mut result = String::new();
+        if self.text.get() || self.script.get() {
+            for expr in exprs {
+                push(&mut result, &self.expr(expr));
+            }
+            return result;
+        }
+
+        let mut previous: Option<(AtomClass, bool)> = None;
+        for (expr, atom) in exprs.iter().zip(self.inline.classify(exprs)) {
+            if let (Some(left), Some(right)) = (previous, atom) {
+                result += match (left.0, right.0) {
+                    // `\sum_i x_i` rather than `\sum_ix_i`
+                    (AtomClass::Op, AtomClass::Ord | AtomClass::Op) => " ",
+                    _ => self.inline.space(left, right),
+                };
+            }
+            push(&mut result, &self.expr(expr));
+            previous = atom.or(previous);
+        }
+        result
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Literal(c) => c.to_string(),
+            // `{{a}}` is `{a}`
+            ExprKind::Group(exprs) => match exprs.as_slice() {
+                [
+                    inner @ Expr {
+                        kind: ExprKind::Group(_),
+                        ..
+                    },
+                ] => self.expr(inner),
+                _ => format!("{{{}}}", self.sequence(exprs)),
+            },
+            ExprKind::Superscript(base, exponent) => {
+                format!("{}^{}", self.expr(base), self.script(exponent))
+            }
+            ExprKind::Subscript(base, subscript) => {
+                format!("{}_{}", self.expr(base), self.script(subscript))
+            }
+            ExprKind::Command(cmd) => self.command(cmd),
+            ExprKind::Environment(env) => self.environment(env),
+            ExprKind::Delimited(delimited) => self.delimited(delimited),
+        }
+    }
+
+    // An argument or script, which is a single token or a group. Groups of
+    // one token lose their braces, so `x^{2}` is `x^2`, except for the tokens
+    // that `is_token` leaves out.
+    fn argument(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Superscript(..) | ExprKind::Subscript(..) => {
+                format!("{{{}}}", self.expr(expr))
+            }
+            ExprKind::Literal(_) | ExprKind::Command(Command::Symbol { .. }) if !is_token(expr) => {
+                format!("{{{}}}", self.expr(expr))
+            }
+            // in text the space after `\alpha` would be taken as part of it
+            ExprKind::Command(Command::Symbol { .. }) if self.text.get() => {
+                format!("{{{}}}", self.expr(expr))
+            }
+            ExprKind::Group(exprs) => match exprs.as_slice() {
+                [inner] if is_token(inner) => self.argument(inner),
+                [
+                    inner @ Expr {
+                        kind: ExprKind::Group(_),
+                        ..
+                    },
+                ] => self.argument(inner),
+                _ => self.expr(expr),
+            },
+            _ => self.expr(expr),
+        }
+    }
+
+    fn script(&self, expr: &Expr) -> String {
+        let outer = self.script.replace(true);
+        let result = self.argument(expr);
+        self.script.set(outer);
+        result
+    }
+
+    // The contents of a group without its braces, for the parts of the input
+    // that the parser collects into a group itself.
+    fn contents(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Group(exprs) => self.sequence(exprs),
+            _ => self.expr(expr),
+        }
+    }
+
+    fn command(&self, cmd: &Command) -> String {
+        let mut result = format!("\\{}", cmd.name());
+        match cmd {
+            Command::Sqrt { index, content }
+            | Command::Xrightarrow {
+                below: index,
+                above: content,
+            }
+            | Command::Xleftarrow {
+                below: index,
+                above: content,
+            } => {
+                if let Some(index) = index {
+                    result += &format!("[{}]", self.contents(index));
+                }
+                push(&mut result, &self.argument(content));
+            }
+            Command::Text { content }
+            | Command::Textrm { content }
+            | Command::Mbox { content }
+            | Command::Mathrm { content }
+            | Command::Operatorname { content } => match &content.kind {
+                ExprKind::Group(_) => {
+                    let outer = self.text.replace(true);
+                    result += &self.expr(content);
+                    self.text.set(outer);
+                }
+                _ => push(&mut result, &self.argument(content)),
+            },
+            _ => {
+                for arg in cmd.args() {
+                    push(&mut result, &self.argument(arg));
+                }
+            }
+        }
+        result
+    }
+
+    fn environment(&self, env: &Environment) -> String {
+        let mut rows: Vec<String> = env
+            .rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let row = self.join(row.iter().map(|cell| self.contents(cell)), "&");
+                // `\\[` would start the row spacing, as in `\\[2pt]`
+                if i > 0 && row.starts_with('[') {
+                    format!("[0pt]{}", row)
+                } else {
+                    row
+                }
+            })
+            .collect();
+        // a last row with a single empty cell needs a `\\` after it to be kept
+        if env.rows.len() > 1
+            && env.rows.last().is_some_and(
+                |row| matches!(row.as_slice(), [cell] if cell.kind == ExprKind::Group(vec![])),
+            )
+        {
+            rows.push(String::new());
+        }
+
+        let mut result = format!("\\begin{{{}}}", env.name);
+        if let Some(columns) = &env.columns {
+            result += &format!("{{{}}}", columns);
+        }
+        let body = self.join(rows.into_iter(), "\\\\");
+        if !body.is_empty() && !self.text.get() {
+            result += &format!(" {} ", body);
+        } else {
+            result += &body;
+        }
+        result + &format!("\\end{{{}}}", env.name)
+    }
+
+    fn delimited(&self, delimited: &Delimited) -> String {
+        let mut result = String::from("\\left");
+        push(&mut result, &delimited.open);
+        for (i, part) in delimited.parts.iter().enumerate() {
+            if let Some(middle) = i.checked_sub(1).and_then(|i| delimited.middles.get(i)) {
+                result += "\\middle";
+                push(&mut result, middle);
+            }
+            push(&mut result, &self.contents(part));
+        }
+        result += "\\right";
+        push(&mut result, &delimited.close);
+        result
+    }
+
+    // `a & b`, leaving out the spaces around empty items, and all spaces in
+    // text, where they would be kept.
+    fn join(&self, items: impl Iterator<Item = String>, separator: &str) -> String {
+        if self.text.get() {
+            return items.collect::<Vec<_>>().join(separator);
+        }
+        let mut result = String::new();
+        for (i, item) in items.enumerate() {
+            if i > 0 {
+                result += &format!(" {}", separator);
+                if !item.is_empty() {
+                    result.push(' ');
+                }
+            }
+            result += &item;
+        }
+        result.trim_start().to_string()
+    }
+}
+
+// Whether `expr` is a single character or a command without arguments, which
+// can be an argument without braces. Brackets keep theirs, as `\sqrt{[}x]`
+// would otherwise be read as `\sqrt[x]`.
+fn is_token(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Literal(c) => c.is_ascii_alphanumeric() || "+-=<>,.;:!?*/|".contains(*c),
+        // in an environment a bare `\\` would end the row
+        ExprKind::Command(Command::Symbol { name }) => name != "\\",
+        _ => false,
+    }
+}
+
+// Appends `next`, with a space in between if `result` ends with a command word
+// that would otherwise run into the letters of `next`, like `\alpha x`.
+fn push(result: &mut String, next: &str) {
+    let word = result.trim_end_matches(char::is_alphabetic);
+    let backslashes = word.len() - word.trim_end_matches('\\').len();
+    // in `\\x` the backslashes are a line break, not the start of a command
+    if word.len() < result.len() && backslashes % 2 == 1 && next.starts_with(char::is_alphabetic) {
+        result.push(' ');
+    }
+    *result += next;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::renderer::Renderer;
+    use proptest::prelude::*;
+
+    fn parse(input: &str) -> Vec<Expr> {
+        Parser::new(Lexer::new(input)).parse()
+    }
+
+    fn print(input: &str) -> String {
+        LatexPrinter::new().print(&parse(input))
+    }
+
+    #[test]
+    fn test_print() {
+        assert_eq!(print(r"a+b=c"), "a + b = c");
+        assert_eq!(print(r"-x \le  \alpha x"), r"-x \le \alpha x");
+        assert_eq!(print(r"\sin  x+\sin(x)"), r"\sin x + \sin(x)");
+        assert_eq!(print(r"x^{ 2 }_i \frac 1 2"), r"x^2_i\frac12");
+        assert_eq!(print(r"\frac { a+1 } b"), r"\frac{a + 1}b");
+        assert_eq!(print(r"\sum_{i = 1}^n x_i"), r"\sum_{i=1}^n x_i");
+        assert_eq!(
+            print(r"\sqrt [3] {x} \xrightarrow{f}"),
+            r"\sqrt[3]x \xrightarrow f"
+        );
+        assert_eq!(print(r"\text{ if  \alpha x}y"), r"\text{ if \alpha x}y");
+        assert_eq!(print(r"\mathbf v\cdot\hat{w}"), r"\mathbf v \cdot \hat w");
+    }
+
+    #[test]
+    fn test_canonical() {
+        for (a, b) in [
+            (r"x^{2}", r"x^2"),
+            (r"x_{\alpha}", r"x_\alpha"),
+            (r"\frac{1}{2}", r"\frac12"),
+            (r"\sqrt{{x}}", r"\sqrt x"),
+            (r"{{a}}+b", r"{a} + b"),
+            (r"\hat{ w }", r"\hat w"),
+        ] {
+            assert_eq!(print(a), print(b), "`{}` and `{}`", a, b);
+        }
+        assert_eq!(print(r"x^{10} \frac{a}{\hat x}"), r"x^{10}\frac a{\hat x}");
+        assert_eq!(print(r"\sqrt{[}x] \hat{(}"), r"\sqrt{[}x]\hat{(}");
+    }
+
+    #[test]
+    fn test_print_structures() {
+        assert_eq!(
+            print(r"\begin{pmatrix}a&b\\ &d\\\end{pmatrix}"),
+            r"\begin{pmatrix} a & b \\ & d \end{pmatrix}"
+        );
+        assert_eq!(
+            print(r"\begin{array}{c|l} 1 \\ \\ \end{array}"),
+            r"\begin{array}{c|l} 1 \\ \\ \end{array}"
+        );
+        assert_eq!(
+            print(r"\begin{matrix}a\\[1pt][b]\end{matrix}"),
+            r"\begin{matrix} a \\ [0pt][b] \end{matrix}"
+        );
+        assert_eq!(
+            print(r"\begin{cases}\end{cases}"),
+            r"\begin{cases}\end{cases}"
+        );
+        assert_eq!(
+            print(r"\left\langle x \middle| y \right."),
+            r"\left\langle x\middle|y\right."
+        );
+        // recovered errors are printed the way the parser read them
+        assert_eq!(print(r"{a } \sqrt{b"), r"{a}\sqrt b");
+        assert_eq!(print(r"a \\ b & c"), r"a\\b&c");
+    }
+
+    // Inputs made of the pieces of LaTeX that the parser knows about,
+    // including ones that are incomplete or in the wrong place.
+    fn latex() -> impl Strategy<Value = String> {
+        let leaf = prop_oneof![
+            "[a-zA-Z0-9]",
+            "[-+=<>,;:!?'|/*.()\\[\\]]",
+            prop::sample::select(vec![
+                r"\alpha",
+                r"\le",
+                r"\sin",
+                r"\sum",
+                r"\,",
+                r"\{",
+                r"\infty",
+                r"\cdot",
+                r"\foo",
+                r"\to",
+                r"\\",
+                "&",
+                "{",
+                "}",
+                "^",
+                "_",
+                r"\right)",
+                r"\middle|",
+                r"\end{x}",
+                r"\bigl(",
+                r"\mathbf",
+                r"\frac",
+                r"\sqrt",
+                "{[}",
+                "{]}",
+                "{(}",
+            ])
+            .prop_map(String::from),
+            "[a-z ]{0,6}".prop_map(|text| format!(r"\text{{{}}}", text)),
+        ];
+        leaf.prop_recursive(4, 64, 6, |inner| {
+            let sequence =
+                prop::collection::vec((inner.clone(), prop::bool::ANY), 0..5).prop_map(|parts| {
+                    parts
+                        .into_iter()
+                        .map(|(part, space)| if space { part + " " } else { part })
+                        .collect::<String>()
+                });
+            let delimiter = prop::sample::select(vec!["(", ")", "[", "|", ".", r"\langle", r"\{"]);
+            let environment = prop::sample::select(vec!["pmatrix", "cases", "array"]);
+            prop_oneof![
+                sequence.clone(),
+                sequence.clone().prop_map(|s| format!("{{{}}}", s)),
+                (inner.clone(), inner.clone()).prop_map(|(a, b)| format!("{}^{}", a, b)),
+                (inner.clone(), sequence.clone()).prop_map(|(a, b)| format!("{}_{{{}}}", a, b)),
+                (sequence.clone(), inner.clone())
+                    .prop_map(|(a, b)| format!(r"\frac{{{}}}{}", a, b)),
+                (sequence.clone(), sequence.clone())
+                    .prop_map(|(a, b)| format!(r"\sqrt[{}]{{{}}}", a, b)),
+                inner.clone().prop_map(|a| format!(r"\hat {}", a)),
+                sequence.clone().prop_map(|a| format!(r"\text{{ {} }}", a)),
+                (
+                    delimiter.clone(),
+                    sequence.clone(),
+                    sequence.clone(),
+                    delimiter
+                )
+                    .prop_map(|(open, a, b, close)| format!(
+                        r"\left{} {} \middle| {} \right{}",
+                        open, a, b, close
+                    )),
+                (
+                    environment,
+                    prop::collection::vec(sequence, 0..5),
+                    "[&\\\\]{0,3}"
+                )
+                    .prop_map(|(name, cells, end)| {
+                        let cells = cells.join(if cells.len() % 2 == 0 { "&" } else { r"\\" });
+                        format!(r"\begin{{{0}}}{{cl}} {1} {2} \end{{{0}}}", name, cells, end)
+                    }),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(input in latex()) {
+            let ast = parse(&input);
+            let printed = LatexPrinter::new().print(&ast);
+            // parsing the output gives the same expressions again
+            prop_assert_eq!(&parse(&printed), &ast, "printed as `{}`", printed);
+            prop_assert_eq!(print(&printed), printed.clone());
+            let renderer = UnicodeRenderer::new();
+            prop_assert_eq!(
+                renderer.render(&parse(&printed)),
+                renderer.render(&ast),
+                "printed as `{}`",
+                printed
+            );
+        }
+    }
+}
@@ -1,74 +1,144 @@
-use crate::token::Token;
+use crate::token::{Span, SpannedToken, Token};
 use std::iter::Peekable;
-use std::str::Chars;
+use std::str::CharIndices;
 
 pub struct Lexer<'a> {
-    input: Peekable<Chars<'a>>,
+    source: &'a str,
+    input: Peekable<CharIndices<'a>>,
+    char_offset: usize,
+    // inside the argument of `\text` and friends, where spaces are kept
+    text_mode: bool,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
         Lexer {
-            input: input.chars().peekable(),
+            source: input,
+            input: input.char_indices().peekable(),
+            char_offset: 0,
+            text_mode: false,
         }
     }
 
+    pub fn text_mode(&self) -> bool {
+        self.text_mode
+    }
+
+    // In text mode every run of whitespace becomes a single `Char(' ')` token
+    // instead of being skipped. The switch applies from the next token on.
+    pub fn set_text_mode(&mut self, text_mode: bool) {
+        self.text_mode = text_mode;
+    }
+
     pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
+        self.next_spanned().token
+    }
+
+    pub fn next_spanned(&mut self) -> SpannedToken {
+        if !self.text_mode {
+            self.skip_whitespace();
+        }
 
-        match self.input.peek() {
-            Some(&'\\') => {
-                self.input.next();
-                self.read_command()
+        let start = self.position();
+        let token = match self.peek() {
+            Some(c) if c.is_whitespace() => {
+                self.skip_whitespace();
+                Token::Char(' ')
             }
-            Some(&'{') => {
-                self.input.next();
+            Some('\\') => {
+                self.bump();
+                if self.peek() == Some('\\') {
+                    self.bump();
+                    Token::Newline
+                } else {
+                    self.read_command()
+                }
+            }
+            Some('&') => {
+                self.bump();
+                Token::Ampersand
+            }
+            Some('{') => {
+                self.bump();
                 Token::LBrace
             }
-            Some(&'}') => {
-                self.input.next();
+            Some('}') => {
+                self.bump();
                 Token::RBrace
             }
-            Some(&'[') => {
-                self.input.next();
+            Some('[') => {
+                self.bump();
                 Token::LBracket
             }
-            Some(&']') => {
-                self.input.next();
+            Some(']') => {
+                self.bump();
                 Token::RBracket
             }
-            Some(&'(') => {
-                self.input.next();
+            Some('(') => {
+                self.bump();
                 Token::LParen
             }
-            Some(&')') => {
-                self.input.next();
+            Some(')') => {
+                self.bump();
                 Token::RParen
             }
-            Some(&'^') => {
-                self.input.next();
+            Some('^') => {
+                self.bump();
                 Token::Superscript
             }
-            Some(&'_') => {
-                self.input.next();
+            Some('_') => {
+                self.bump();
                 Token::Subscript
             }
-            Some(&'%') => {
+            Some('%') => {
                 self.skip_comment();
-                self.next_token()
+                return self.next_spanned();
             }
             Some(_) => {
-                let c = self.input.next().unwrap();
+                let c = self.bump().unwrap();
                 Token::Char(c)
             }
             None => Token::EOF,
+        };
+        let end = self.position();
+
+        // like TeX, spaces after a command word don't count in text either
+        if self.text_mode
+            && let Token::Command(name) = &token
+            && name.starts_with(char::is_alphabetic)
+        {
+            self.skip_whitespace();
         }
+
+        SpannedToken {
+            token,
+            span: Span::new(start.0..end.0, start.1..end.1),
+        }
+    }
+
+    // (byte offset, char offset) of the next character
+    fn position(&mut self) -> (usize, usize) {
+        let byte = self
+            .input
+            .peek()
+            .map_or(self.source.len(), |&(index, _)| index);
+        (byte, self.char_offset)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.input.peek().map(|&(_, c)| c)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.input.next()?;
+        self.char_offset += 1;
+        Some(c)
     }
 
     fn skip_whitespace(&mut self) {
-        while let Some(&c) = self.input.peek() {
+        while let Some(c) = self.peek() {
             if c.is_whitespace() {
-                self.input.next();
+                self.bump();
             } else {
                 break;
             }
@@ -76,30 +146,30 @@ impl<'a> Lexer<'a> {
     }
 
     fn skip_comment(&mut self) {
-        self.input.next();
-        while let Some(&c) = self.input.peek() {
+        self.bump();
+        while let Some(c) = self.peek() {
             if c == '\n' || c == '\r' {
                 break;
             }
-            self.input.next();
+            self.bump();
         }
     }
 
     fn read_command(&mut self) -> Token {
-        if let Some(&c) = self.input.peek() {
+        if let Some(c) = self.peek() {
             if c.is_alphabetic() {
                 let mut command = String::new();
-                while let Some(&c) = self.input.peek() {
+                while let Some(c) = self.peek() {
                     if c.is_alphabetic() {
                         command.push(c);
-                        self.input.next();
+                        self.bump();
                     } else {
                         break;
                     }
                 }
                 Token::Command(command)
             } else {
-                let c = self.input.next().unwrap();
+                let c = self.bump().unwrap();
                 Token::Command(c.to_string())
             }
         } else {
@@ -124,7 +194,7 @@ impl<'a> Iterator for Lexer<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::token::Token;
+    use crate::token::{Span, Token};
 
     #[test]
     fn test_simple_tokens() {
@@ -153,12 +223,14 @@ mod tests {
 
     #[test]
     fn test_escaped_chars() {
-        let input = r"\{ \} \% \\";
+        let input = r"\{ \} \% \& \\ &";
         let mut lexer = Lexer::new(input);
         assert_eq!(lexer.next_token(), Token::Command("{".to_string()));
         assert_eq!(lexer.next_token(), Token::Command("}".to_string()));
         assert_eq!(lexer.next_token(), Token::Command("%".to_string()));
-        assert_eq!(lexer.next_token(), Token::Command("\\".to_string()));
+        assert_eq!(lexer.next_token(), Token::Command("&".to_string()));
+        assert_eq!(lexer.next_token(), Token::Newline);
+        assert_eq!(lexer.next_token(), Token::Ampersand);
         assert_eq!(lexer.next_token(), Token::EOF);
     }
 
@@ -180,6 +252,45 @@ mod tests {
         assert_eq!(lexer.next_token(), Token::EOF);
     }
 
+    #[test]
+    fn test_spans() {
+        let input = "é + \\beta_{1}";
+        let mut lexer = Lexer::new(input);
+        let spans: Vec<_> = std::iter::from_fn(|| {
+            let spanned = lexer.next_spanned();
+            (spanned.token != Token::EOF).then_some(spanned.span)
+        })
+        .collect();
+
+        assert_eq!(spans[0], Span::new(0..2, 0..1));
+        assert_eq!(spans[1], Span::new(3..4, 2..3));
+        assert_eq!(spans[2], Span::new(5..10, 4..9));
+        assert_eq!(&input[spans[2].bytes()], "\\beta");
+        assert_eq!(spans[5], Span::new(12..13, 11..12));
+        assert_eq!(spans.len(), 7);
+
+        assert_eq!(lexer.next_spanned().span, Span::new(14..14, 13..13));
+    }
+
+    #[test]
+    fn test_text_mode() {
+        let mut lexer = Lexer::new(r"a  b \and \t c");
+        lexer.set_text_mode(true);
+        let tokens: Vec<_> = lexer.collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Char('a'),
+                Token::Char(' '),
+                Token::Char('b'),
+                Token::Char(' '),
+                Token::Command("and".to_string()),
+                Token::Command("t".to_string()),
+                Token::Char('c'),
+            ]
+        );
+    }
+
     #[test]
     fn test_comments() {
         let input = "x % this is a comment\n y";
@@ -1,13 +1,491 @@
+pub mod ascii;
+pub mod asciimath;
 pub mod ast;
+pub mod delimiters;
+pub mod diagnostics;
+pub mod html;
+pub mod latex;
 pub mod lexer;
+pub mod macros;
+pub mod markdown;
+pub mod mathml;
 pub mod parser;
+pub mod pretty;
 pub mod renderer;
+pub mod reverse;
+pub mod symbols;
 pub mod token;
+pub mod typst;
 
+pub use ascii::AsciiRenderer;
+use delimiters::MathSpan;
+use diagnostics::{ConversionReport, Diagnostic};
+pub use html::HtmlRenderer;
+pub use latex::LatexPrinter;
+pub use macros::Macros;
+pub use mathml::MathMlRenderer;
+pub use pretty::PrettyRenderer;
+pub use renderer::{AtomClass, Fractions, Renderer, Spacing, UnicodeRenderer};
+use std::fmt;
+use std::str::FromStr;
+pub use symbols::{Symbol, SymbolTable};
+pub use typst::TypstRenderer;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    pub scope: Scope,
+    pub syntax: Syntax,
+    pub spacing: Spacing,
+    pub fractions: Fractions,
+    pub format: Format,
+    // macros to expand on top of those defined in the input
+    pub macros: Macros,
+    // the commands that render as fixed text, with their spacing and arity
+    pub symbols: SymbolTable,
+}
+
+// What the math is converted to.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Format {
+    // a single line of Unicode text
+    #[default]
+    Unicode,
+    // Unicode laid out over several lines, with stacked fractions and the
+    // like, for monospace fonts; inline math in running text stays on one line
+    Pretty,
+    // Presentation MathML, for web pages
+    MathMl,
+    // Unicode with `<sup>`, `<sub>` and tables, for rich text that can't show MathML
+    Html,
+    // Typst math, like `frac(a, b)`; math found between delimiters keeps
+    // Typst's own, `$...$` or `$ ... $`
+    Typst,
+    // plain ASCII, like `alpha<=x^(n+1)`, for places that mangle anything else
+    Ascii,
+}
+
+impl Format {
+    // Whether the output is markup, in which the text around the math is
+    // escaped; it goes on the clipboard as HTML.
+    pub fn is_markup(self) -> bool {
+        matches!(self, Format::MathMl | Format::Html)
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unicode" => Ok(Format::Unicode),
+            "pretty" => Ok(Format::Pretty),
+            "mathml" => Ok(Format::MathMl),
+            "html" => Ok(Format::Html),
+            "typst" => Ok(Format::Typst),
+            "ascii" => Ok(Format::Ascii),
+            _ => Err(format!(
+                "unknown format `{}` (expected unicode, pretty, mathml, html, typst or ascii)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Format::Unicode => "unicode",
+            Format::Pretty => "pretty",
+            Format::MathMl => "mathml",
+            Format::Html => "html",
+            Format::Typst => "typst",
+            Format::Ascii => "ascii",
+        })
+    }
+}
+
+// Which parts of the input are treated as LaTeX math.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Scope {
+    // the math delimiters if there are any, otherwise the whole input
+    #[default]
+    Auto,
+    // the whole input, without looking for delimiters
+    Whole,
+    // only the contents of `$...$`, `$$...$$`, `\(...\)` and `\[...\]`; the
+    // delimiters are removed and the text around them is kept as is
+    Delimited,
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Scope::Auto),
+            "whole" => Ok(Scope::Whole),
+            "delimited" => Ok(Scope::Delimited),
+            _ => Err(format!(
+                "unknown scope `{}` (expected auto, whole or delimited)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Scope::Auto => "auto",
+            Scope::Whole => "whole",
+            Scope::Delimited => "delimited",
+        })
+    }
+}
+
+// What the math is written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Syntax {
+    // AsciiMath if it looks like it, like `sqrt(x)/2` or `a <= b`, and
+    // LaTeX otherwise
+    #[default]
+    Auto,
+    Latex,
+    // AsciiMath, like `sum_(i=1)^n x_i^2`
+    AsciiMath,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Syntax::Auto),
+            "latex" => Ok(Syntax::Latex),
+            "asciimath" => Ok(Syntax::AsciiMath),
+            _ => Err(format!(
+                "unknown syntax `{}` (expected auto, latex or asciimath)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Syntax::Auto => "auto",
+            Syntax::Latex => "latex",
+            Syntax::AsciiMath => "asciimath",
+        })
+    }
+}
+
+// Treats the whole input as math.
 pub fn convert_latex_to_unicode(input: &str) -> String {
-    let lexer = lexer::Lexer::new(input);
-    let mut parser = parser::Parser::new(lexer);
-    let ast = parser.parse();
-    let renderer = renderer::Renderer::new();
-    renderer.render(&ast)
+    convert_latex_to_unicode_with_report(input).0
+}
+
+// Like `convert_latex_to_unicode`, but also reports everything that could not
+// be converted faithfully, sorted by position in `input`.
+pub fn convert_latex_to_unicode_with_report(input: &str) -> (String, ConversionReport) {
+    let options = Options {
+        scope: Scope::Whole,
+        syntax: Syntax::Latex,
+        ..Options::default()
+    };
+    convert_with_report(input, &options)
+}
+
+// Turns Unicode math like `∀ε>0` back into LaTeX like
+// `\forall\varepsilon>0`.
+pub fn convert_unicode_to_latex(input: &str) -> String {
+    reverse::to_latex(input, SymbolTable::builtin())
+}
+
+pub fn convert(input: &str, options: &Options) -> String {
+    convert_with_report(input, options).0
+}
+
+pub fn convert_with_report(input: &str, options: &Options) -> (String, ConversionReport) {
+    let spans = match options.scope {
+        Scope::Whole => vec![],
+        Scope::Auto | Scope::Delimited => delimiters::find_math_spans(input),
+    };
+
+    if spans.is_empty() && options.scope != Scope::Delimited {
+        return convert_math_with_report(input, options, true);
+    }
+    // text around math in markup is escaped, unlike in Markdown, which has its own
+    convert_spans(input, &spans, options, options.format.is_markup())
+}
+
+// Treats the whole input as math, on a line of its own like `$$...$$` if
+// `display` is set and inside a line of text like `$...$` otherwise.
+pub fn convert_math_with_report(
+    input: &str,
+    options: &Options,
+    display: bool,
+) -> (String, ConversionReport) {
+    let mut macros = options.macros.clone();
+    let (output, mut diagnostics) = convert_math(input, options, &mut macros, display);
+    diagnostics.sort_by_key(|d| d.span.start);
+    (output, ConversionReport { diagnostics })
+}
+
+// Converts the `$...$` and `$$...$$` math of a Markdown document and keeps
+// every other byte, including code that contains dollar signs.
+pub fn convert_markdown(input: &str, options: &Options) -> String {
+    convert_markdown_with_report(input, options).0
+}
+
+pub fn convert_markdown_with_report(input: &str, options: &Options) -> (String, ConversionReport) {
+    convert_spans(input, &markdown::find_math_spans(input), options, false)
+}
+
+// Converts the math in `spans`, replacing their delimiters, and copies the
+// prose between them, HTML-escaped if `escape_prose` is set.
+fn convert_spans(
+    input: &str,
+    spans: &[MathSpan],
+    options: &Options,
+    escape_prose: bool,
+) -> (String, ConversionReport) {
+    let prose = |text: &str| {
+        if escape_prose {
+            html::escape(text)
+        } else {
+            text.to_string()
+        }
+    };
+    // macros defined in one span of math can be used in the later ones
+    let mut macros = options.macros.clone();
+    let mut output = String::with_capacity(input.len());
+    let mut diagnostics = Vec::new();
+    let mut prose_start = 0;
+    // counted as the spans go, since recounting from the start of a long
+    // document for every span adds up
+    let (mut bytes_counted, mut chars_before) = (0, 0);
+    for span in spans {
+        output += &prose(&input[prose_start..span.outer.start]);
+        let (math, math_diagnostics) = convert_math(
+            &input[span.inner.clone()],
+            options,
+            &mut macros,
+            span.display,
+        );
+        output += &match options.format {
+            // a span that only defines macros leaves nothing
+            Format::Typst if math.is_empty() => math,
+            Format::Typst if span.display => format!("$ {} $", math),
+            Format::Typst => format!("${}$", math),
+            _ => math,
+        };
+
+        chars_before += input[bytes_counted..span.inner.start].chars().count();
+        bytes_counted = span.inner.start;
+        diagnostics.extend(math_diagnostics.into_iter().map(|mut d| {
+            d.span = d.span.offset(span.inner.start, chars_before);
+            d
+        }));
+        prose_start = span.outer.end;
+    }
+    output += &prose(&input[prose_start..]);
+
+    diagnostics.sort_by_key(|d| d.span.start);
+    (output, ConversionReport { diagnostics })
+}
+
+// `display` is false for math inside a line of text, like `$...$`.
+fn convert_math(
+    input: &str,
+    options: &Options,
+    macros: &mut Macros,
+    display: bool,
+) -> (String, Vec<Diagnostic>) {
+    let asciimath = match options.syntax {
+        Syntax::Auto => asciimath::is_asciimath(input),
+        Syntax::Latex => false,
+        Syntax::AsciiMath => true,
+    };
+    let (ast, mut diagnostics) = if asciimath {
+        let mut parser = asciimath::Parser::new(input).with_symbols(&options.symbols);
+        (parser.parse(), parser.take_diagnostics())
+    } else {
+        let lexer = lexer::Lexer::new(input);
+        let mut parser = parser::Parser::with_macros(lexer, std::mem::take(macros))
+            .with_symbols(&options.symbols);
+        let ast = parser.parse();
+        *macros = parser.macros().clone();
+        (ast, parser.take_diagnostics())
+    };
+
+    let renderer: Box<dyn Renderer> = match options.format {
+        Format::Pretty if display => Box::new(PrettyRenderer::with_options(options)),
+        Format::Unicode | Format::Pretty => Box::new(UnicodeRenderer::with_options(options)),
+        Format::MathMl => Box::new(MathMlRenderer::with_options(options).with_display(display)),
+        Format::Html => Box::new(HtmlRenderer::with_options(options)),
+        Format::Typst => Box::new(TypstRenderer::with_options(options)),
+        Format::Ascii => Box::new(AsciiRenderer::with_options(options)),
+    };
+    let output = renderer.render(&ast);
+    diagnostics.extend(renderer.take_diagnostics());
+    (output, diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diagnostics::DiagnosticKind;
+
+    #[test]
+    fn test_report() {
+        let (output, report) = convert_latex_to_unicode_with_report(r"\alpha + \beta");
+        assert_eq!(output, "α+β");
+        assert!(report.is_lossless());
+
+        let (output, report) = convert_latex_to_unicode_with_report(r"x^{q} + \foo + {\baz");
+        assert_eq!(output, r"x^{q}+\foo+\baz");
+        assert_eq!(
+            report.unknown_commands().collect::<Vec<_>>(),
+            ["foo", "baz"]
+        );
+        let kinds: Vec<_> = report.diagnostics.iter().map(|d| &d.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                &DiagnosticKind::UnrepresentableSuperscript('q'),
+                &DiagnosticKind::UnknownCommand("foo".to_string()),
+                &DiagnosticKind::UnclosedBrace,
+                &DiagnosticKind::UnknownCommand("baz".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delimited_scope() {
+        let input = r"Let $x_1 \in \mathbb{R}$ be  given, \(y^2\) costs \$5.";
+        let options = Options {
+            scope: Scope::Delimited,
+            ..Options::default()
+        };
+        assert_eq!(
+            convert(input, &options),
+            r"Let x₁∈ℝ be  given, y² costs \$5."
+        );
+        // the whole input is math when there are no delimiters
+        assert_eq!(convert(r"a \le b", &Options::default()), "a≤b");
+        assert_eq!(convert(r"a \le b", &options), r"a \le b");
+        assert_eq!(convert(r"$\alpha$", &Options::default()), "α");
+        assert_eq!(convert_latex_to_unicode(r"$\alpha$"), "$α$");
+    }
+
+    #[test]
+    fn test_delimited_report_spans() {
+        let input = "é $x^{q}$ and $\\foo$";
+        let (_, report) = convert_with_report(input, &Options::default());
+        let spans: Vec<_> = report.diagnostics.iter().map(|d| d.span).collect();
+        assert_eq!(&input[spans[0].bytes()], "q");
+        assert_eq!(spans[0].chars(), 6..7);
+        assert_eq!(&input[spans[1].bytes()], "\\foo");
+        assert_eq!(spans[1].chars(), 15..19);
+    }
+
+    #[test]
+    fn test_spacing_option() {
+        let options = Options {
+            spacing: Spacing::Readable,
+            ..Options::default()
+        };
+        assert_eq!(
+            convert(r"Let $a \le b$ and $c, d$.", &options),
+            "Let a ≤ b and c, d."
+        );
+    }
+
+    #[test]
+    fn test_pretty_format() {
+        let options = Options {
+            format: Format::Pretty,
+            ..Options::default()
+        };
+        assert_eq!(convert(r"\frac{1}{2}", &options), " 1\n───\n 2");
+        assert_eq!(
+            convert(r"so $\frac{1}{2}$ and $$\frac{1}{2}$$", &options),
+            "so ½ and  1\n───\n 2"
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let input = "# $\\alpha$\n\n`$x$` and $x^2$\n\n```\n$$y$$\n```\n";
+        assert_eq!(
+            convert_markdown(input, &Options::default()),
+            "# α\n\n`$x$` and x²\n\n```\n$$y$$\n```\n"
+        );
+        let (_, report) = convert_markdown_with_report("`\\foo` $\\foo$", &Options::default());
+        assert_eq!(report.diagnostics[0].span.start, 8);
+    }
+
+    #[test]
+    fn test_markup_formats() {
+        let options = Options {
+            format: Format::Html,
+            ..Options::default()
+        };
+        assert_eq!(
+            convert(r"if a < b then $x^n$", &options),
+            "if a &lt; b then x<sup>n</sup>"
+        );
+        let options = Options {
+            format: Format::MathMl,
+            ..Options::default()
+        };
+        assert_eq!(
+            convert(r"$x$ & $$y$$", &options),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mi>x</mi></math> &amp; \
+             <math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\"><mi>y</mi></math>"
+        );
+    }
+
+    #[test]
+    fn test_typst_format() {
+        let options = Options {
+            format: Format::Typst,
+            ..Options::default()
+        };
+        assert_eq!(convert(r"\frac{a}{b}", &options), "frac(a, b)");
+        assert_eq!(
+            convert(
+                r"$\newcommand{\R}{\mathbb{R}}$Let $x \in \R$ and $$x^{n+1}$$",
+                &options
+            ),
+            "Let $x in bb(R)$ and $ x^(n+1) $"
+        );
+    }
+
+    #[test]
+    fn test_syntax() {
+        let options = Options::default();
+        assert_eq!(convert("sum_(i=1)^n x_i", &options), "∑ᵢ₌₁ⁿxᵢ");
+        assert_eq!(
+            convert("Let $x in RR$ and $a/b$.", &options),
+            "Let x∈ℝ and a/b."
+        );
+        let options = Options {
+            syntax: Syntax::Latex,
+            ..Options::default()
+        };
+        assert_eq!(convert("x in RR", &options), "xinRR");
+    }
+
+    #[test]
+    fn test_macros() {
+        let input = r"$\newcommand{\R}{\mathbb{R}}$Let $x \in \R$ and $\norm{x} \le 1$.";
+        let options = Options {
+            macros: Macros::parse(r"\newcommand{\norm}[1]{\|#1\|}"),
+            ..Options::default()
+        };
+        assert_eq!(convert(input, &options), "Let x∈ℝ and ‖x‖≤1.");
+    }
 }
@@ -0,0 +1,456 @@
+use crate::diagnostics::{Diagnostic, DiagnosticKind};
+use crate::lexer::Lexer;
+use crate::token::{Span, SpannedToken, Token};
+use std::collections::{HashMap, VecDeque};
+
+// How many macros deep an expansion may go, which stops definitions like
+// `\def\a{\a}` from expanding forever.
+const MAX_DEPTH: usize = 64;
+// How many expansions a single input may make in total, for definitions
+// like `\def\a{\a\a}` that grow too quickly for the depth limit to help.
+const MAX_EXPANSIONS: usize = 10_000;
+// How many tokens expansions may produce in total, for definitions like
+// `\def\a#1{#1#1}` that double their argument with every use.
+const MAX_TOKENS: usize = 100_000;
+
+// Macros defined with `\newcommand`, `\renewcommand`, `\DeclareMathOperator`
+// or `\def`, by name without the backslash.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Macros {
+    definitions: HashMap<String, Macro>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Macro {
+    params: usize,
+    // the value of `#1` when it is optional and left out
+    default: Option<Vec<Token>>,
+    body: Vec<Token>,
+}
+
+impl Macros {
+    pub fn new() -> Self {
+        Macros::default()
+    }
+
+    // The macros defined in `source`, such as a file of `\newcommand`s.
+    // Everything other than definitions is ignored.
+    pub fn parse(source: &str) -> Macros {
+        let mut expander = Expander::new(Lexer::new(source), Macros::new());
+        while expander.next_spanned().token != Token::EOF {}
+        expander.macros
+    }
+
+    pub fn len(&self) -> usize {
+        self.definitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.definitions.contains_key(name)
+    }
+
+    // Adds the definitions of `other`, replacing those with the same name.
+    pub fn extend(&mut self, other: Macros) {
+        self.definitions.extend(other.definitions);
+    }
+}
+
+// Sits between the lexer and the parser: reads macro definitions and
+// replaces uses of macros by their bodies, with `#1`..`#9` substituted.
+// Expanded tokens take the span of the whole use, arguments included.
+pub struct Expander<'a> {
+    lexer: Lexer<'a>,
+    macros: Macros,
+    // tokens from expansions that come before the rest of the input, with
+    // how many macros deep they are
+    pending: VecDeque<(SpannedToken, usize)>,
+    text_mode: bool,
+    last_span: Span,
+    expansions: usize,
+    tokens: usize,
+    // set once a limit is reached, after which macros are left out
+    limited: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Expander<'a> {
+    pub fn new(mut lexer: Lexer<'a>, macros: Macros) -> Self {
+        // spaces are kept so arguments can end up in `\text`; they are
+        // dropped again on the way out in math mode
+        lexer.set_text_mode(true);
+        Expander {
+            lexer,
+            macros,
+            pending: VecDeque::new(),
+            text_mode: false,
+            last_span: Span::default(),
+            expansions: 0,
+            tokens: 0,
+            limited: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn text_mode(&self) -> bool {
+        self.text_mode
+    }
+
+    // In text mode runs of whitespace come out as `Char(' ')` tokens. The
+    // switch applies from the next token on.
+    pub fn set_text_mode(&mut self, text_mode: bool) {
+        self.text_mode = text_mode;
+    }
+
+    pub fn macros(&self) -> &Macros {
+        &self.macros
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    pub fn next_spanned(&mut self) -> SpannedToken {
+        loop {
+            let (spanned, depth) = self.next_raw();
+            let Token::Command(name) = &spanned.token else {
+                if spanned.token == Token::Char(' ') && !self.text_mode {
+                    continue;
+                }
+                return spanned;
+            };
+            match name.as_str() {
+                "newcommand" | "renewcommand" => self.read_newcommand(&name.clone()),
+                "DeclareMathOperator" => self.read_math_operator(),
+                "def" => self.read_def(),
+                _ => match self.macros.definitions.get(name) {
+                    Some(definition) => {
+                        let (name, definition) = (name.clone(), definition.clone());
+                        self.expand(&name, &definition, spanned.span, depth);
+                    }
+                    None => return spanned,
+                },
+            }
+        }
+    }
+
+    fn next_raw(&mut self) -> (SpannedToken, usize) {
+        let next = self
+            .pending
+            .pop_front()
+            .unwrap_or_else(|| (self.lexer.next_spanned(), 0));
+        self.last_span = next.0.span;
+        next
+    }
+
+    fn push_back(&mut self, next: (SpannedToken, usize)) {
+        self.pending.push_front(next);
+    }
+
+    fn skip_spaces(&mut self) {
+        loop {
+            let next = self.next_raw();
+            if next.0.token != Token::Char(' ') {
+                self.push_back(next);
+                return;
+            }
+        }
+    }
+
+    fn report(&mut self, kind: DiagnosticKind, span: Span) {
+        self.diagnostics.push(Diagnostic { kind, span });
+    }
+
+    // A `{...}` group without its braces, or else a single token.
+    fn read_argument(&mut self) -> Option<Vec<Token>> {
+        self.skip_spaces();
+        let (spanned, depth) = self.next_raw();
+        match spanned.token {
+            Token::LBrace => Some(self.read_balanced(spanned.span, Token::RBrace)),
+            Token::RBrace | Token::EOF => {
+                self.push_back((spanned, depth));
+                None
+            }
+            token => Some(vec![token]),
+        }
+    }
+
+    // A `[...]` argument without its brackets, if there is one.
+    fn read_optional(&mut self) -> Option<Vec<Token>> {
+        self.skip_spaces();
+        let next = self.next_raw();
+        if next.0.token == Token::LBracket {
+            Some(self.read_balanced(next.0.span, Token::RBracket))
+        } else {
+            self.push_back(next);
+            None
+        }
+    }
+
+    // The tokens up to `close` outside of nested braces, after the opening one.
+    fn read_balanced(&mut self, open_span: Span, close: Token) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut nesting = 0;
+        loop {
+            let (spanned, _) = self.next_raw();
+            match spanned.token {
+                Token::EOF => {
+                    self.report(DiagnosticKind::UnclosedBrace, open_span);
+                    return tokens;
+                }
+                ref token if *token == close && nesting == 0 => return tokens,
+                Token::LBrace => nesting += 1,
+                Token::RBrace => nesting -= 1,
+                _ => {}
+            }
+            tokens.push(spanned.token);
+        }
+    }
+
+    // The `\name` being defined, either braced or not.
+    fn read_macro_name(&mut self, command: &str, start: Span) -> Option<String> {
+        match self.read_argument().as_deref() {
+            Some([Token::Command(name)]) => Some(name.clone()),
+            _ => {
+                self.report(
+                    DiagnosticKind::MissingArgument(format!("\\{}", command)),
+                    start.to(self.last_span),
+                );
+                None
+            }
+        }
+    }
+
+    // `\newcommand{\name}[params][default]{body}`
+    fn read_newcommand(&mut self, command: &str) {
+        let start = self.last_span;
+        let name = self.read_macro_name(command, start);
+        let params = self
+            .read_optional()
+            .map(|tokens| {
+                let digits: String = tokens
+                    .iter()
+                    .filter_map(|t| match t {
+                        Token::Char(c) => Some(*c),
+                        _ => None,
+                    })
+                    .collect();
+                digits.trim().parse().unwrap_or(0)
+            })
+            .unwrap_or(0);
+        let default = self.read_optional();
+        let body = self.read_argument().unwrap_or_default();
+        if let Some(name) = name {
+            let definition = Macro {
+                params: params.min(9),
+                default,
+                body,
+            };
+            self.macros.definitions.insert(name, definition);
+        }
+    }
+
+    // `\DeclareMathOperator{\name}{text}`, or with a `*` for limits
+    fn read_math_operator(&mut self) {
+        let start = self.last_span;
+        self.skip_spaces();
+        let next = self.next_raw();
+        if next.0.token != Token::Char('*') {
+            self.push_back(next);
+        }
+        let name = self.read_macro_name("DeclareMathOperator", start);
+        let text = self.read_argument().unwrap_or_default();
+        if let Some(name) = name {
+            let mut body = vec![Token::Command("operatorname".to_string()), Token::LBrace];
+            body.extend(text);
+            body.push(Token::RBrace);
+            let definition = Macro {
+                params: 0,
+                default: None,
+                body,
+            };
+            self.macros.definitions.insert(name, definition);
+        }
+    }
+
+    // `\def\name#1#2{body}`, with undelimited parameters only
+    fn read_def(&mut self) {
+        let start = self.last_span;
+        self.skip_spaces();
+        let (spanned, depth) = self.next_raw();
+        let Token::Command(name) = spanned.token else {
+            self.push_back((spanned, depth));
+            self.report(DiagnosticKind::MissingArgument("\\def".to_string()), start);
+            return;
+        };
+
+        let mut params = 0;
+        loop {
+            let next = self.next_raw();
+            match next.0.token {
+                Token::Char('#') => params += 1,
+                Token::LBrace | Token::EOF => {
+                    self.push_back(next);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let body = self.read_argument().unwrap_or_default();
+        let definition = Macro {
+            params: params.min(9),
+            default: None,
+            body,
+        };
+        self.macros.definitions.insert(name, definition);
+    }
+
+    fn expand(&mut self, name: &str, definition: &Macro, span: Span, depth: usize) {
+        if self.limited {
+            return;
+        }
+        if depth >= MAX_DEPTH || self.expansions >= MAX_EXPANSIONS {
+            self.limit(name, span);
+            return;
+        }
+        self.expansions += 1;
+
+        let mut args = Vec::new();
+        if let Some(default) = &definition.default {
+            args.push(self.read_optional().unwrap_or_else(|| default.clone()));
+        }
+        while args.len() < definition.params {
+            match self.read_argument() {
+                Some(arg) => args.push(arg),
+                None => {
+                    self.report(
+                        DiagnosticKind::MissingArgument(format!("\\{}", name)),
+                        span.to(self.last_span),
+                    );
+                    args.push(Vec::new());
+                }
+            }
+        }
+        let span = span.to(self.last_span);
+
+        let mut expansion = Vec::new();
+        let mut body = definition.body.iter().peekable();
+        while let Some(token) = body.next() {
+            if *token == Token::Char('#')
+                && let Some(Token::Char(digit)) = body.peek()
+                && let Some(arg) = digit
+                    .to_digit(10)
+                    .and_then(|n| args.get((n as usize).checked_sub(1)?))
+            {
+                body.next();
+                expansion.extend(arg.iter().cloned());
+            } else {
+                expansion.push(token.clone());
+            }
+        }
+        self.tokens += expansion.len();
+        if self.tokens > MAX_TOKENS {
+            self.limit(name, span);
+            return;
+        }
+        for token in expansion.into_iter().rev() {
+            self.pending
+                .push_front((SpannedToken { token, span }, depth + 1));
+        }
+    }
+
+    // Reports the first macro to run into a limit. A runaway definition
+    // would otherwise report every one of its uses.
+    fn limit(&mut self, name: &str, span: Span) {
+        self.limited = true;
+        self.report(DiagnosticKind::MacroLimit(name.to_string()), span);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(input: &str, macros: Macros) -> (Vec<Token>, Vec<DiagnosticKind>) {
+        let mut expander = Expander::new(Lexer::new(input), macros);
+        let tokens = std::iter::from_fn(|| {
+            let token = expander.next_spanned().token;
+            (token != Token::EOF).then_some(token)
+        })
+        .collect();
+        let diagnostics = expander.take_diagnostics();
+        (tokens, diagnostics.into_iter().map(|d| d.kind).collect())
+    }
+
+    fn tokens(input: &str) -> Vec<Token> {
+        expand(input, Macros::new()).0
+    }
+
+    #[test]
+    fn test_newcommand() {
+        let input =
+            r"\newcommand{\R}{\mathbb{R}} \renewcommand\norm[1]{\| #1 \|} x \in \R, \norm{v}";
+        assert_eq!(tokens(input), tokens(r"x \in \mathbb{R}, \| v \|"));
+    }
+
+    #[test]
+    fn test_optional_argument() {
+        let input = r"\newcommand{\E}[2][X]{\mathbb{E}_{#1}[#2]} \E{Y} \E[Z]{W}";
+        assert_eq!(
+            tokens(input),
+            tokens(r"\mathbb{E}_{X}[Y] \mathbb{E}_{Z}[W]")
+        );
+    }
+
+    #[test]
+    fn test_def_and_operator() {
+        let input =
+            r"\def\pair#1#2{(#1, #2)} \DeclareMathOperator*{\argmax}{arg\,max} \pair a{b} \argmax";
+        assert_eq!(tokens(input), tokens(r"(a, b) \operatorname{arg\,max}"));
+    }
+
+    #[test]
+    fn test_macro_file() {
+        let macros =
+            Macros::parse("% project macros\n\\newcommand{\\N}{\\mathbb{N}}\nignored text\n");
+        assert_eq!(macros.len(), 1);
+        assert!(macros.contains("N"));
+        assert_eq!(expand(r"n \in \N", macros).0, tokens(r"n \in \mathbb{N}"));
+    }
+
+    #[test]
+    fn test_spans() {
+        let input = r"\def\twice#1{#1#1} a \twice{b}";
+        let mut expander = Expander::new(Lexer::new(input), Macros::new());
+        assert_eq!(expander.next_spanned().token, Token::Char('a'));
+        let b = expander.next_spanned();
+        assert_eq!(b.token, Token::Char('b'));
+        assert_eq!(&input[b.span.bytes()], r"\twice{b}");
+    }
+
+    #[test]
+    fn test_limits() {
+        let (tokens, diagnostics) = expand(r"\def\a{x\a} \a", Macros::new());
+        assert_eq!(tokens.len(), MAX_DEPTH);
+        assert_eq!(diagnostics, [DiagnosticKind::MacroLimit("a".to_string())]);
+
+        let (_, diagnostics) = expand(r"\def\a{\a\a} \a \a", Macros::new());
+        assert_eq!(diagnostics, [DiagnosticKind::MacroLimit("a".to_string())]);
+
+        // the argument doubles with every use, far beyond the expansion limit
+        let input = format!(r"\def\a#1{{#1#1}} {}x{}", r"\a{".repeat(40), "}".repeat(40));
+        let (tokens, diagnostics) = expand(&input, Macros::new());
+        assert!(tokens.len() < MAX_TOKENS);
+        assert_eq!(diagnostics, [DiagnosticKind::MacroLimit("a".to_string())]);
+
+        let (tokens, diagnostics) = expand(r"\newcommand{\f}[2]{#1#2} \f{a}", Macros::new());
+        assert_eq!(tokens, [Token::Char('a')]);
+        assert_eq!(
+            diagnostics,
+            [DiagnosticKind::MissingArgument(r"\f".to_string())]
+        );
+    }
+}
@@ -1,65 +1,262 @@
 #![cfg_attr(not(test), windows_subsystem = "windows")]
 
-use arboard::Clipboard;
-use ctrl_tex::convert_latex_to_unicode;
+mod config;
+#[cfg(target_os = "linux")]
+mod hotkey;
+
+use arboard::{Clipboard, ImageData};
+use config::{Action, Config};
+use ctrl_tex::{Format, Options, convert, convert_with_report, reverse};
 use enigo::{Direction, Enigo, Key, Keyboard, Settings};
 #[cfg(target_os = "windows")]
-use inputbot::KeybdKey::*;
+use inputbot::KeybdKey;
+use std::path::PathBuf;
+#[cfg(target_os = "windows")]
+use std::sync::Mutex;
+use std::sync::{Arc, RwLock};
 use std::{thread, time::Duration};
 
-#[cfg(target_os = "windows")]
-fn main() {
-    LKey.bind(|| {
-        if LControlKey.is_pressed() && LShiftKey.is_pressed() {
-            handle_conversion();
+// Keyboard and clipboard handles, kept alive across conversions when running
+// resident so that every hotkey press doesn't pay for a new connection.
+struct Session {
+    enigo: Enigo,
+    clipboard: Clipboard,
+}
+
+impl Session {
+    fn new() -> Self {
+        Session {
+            enigo: Enigo::new(&Settings::default()).unwrap(),
+            clipboard: Clipboard::new().unwrap(),
         }
-    });
-    inputbot::handle_input_events();
+    }
+
+    fn handle_conversion(&mut self, config: &Config, action: Action) {
+        let delays = &config.delays;
+
+        // wait for hotkey release
+        sleep_ms(delays.release);
+
+        let saved = config.restore_clipboard.then(|| {
+            let saved = self.save_clipboard();
+            // so an empty selection doesn't convert the old clipboard contents
+            let _ = self.clipboard.clear();
+            saved
+        });
+
+        // copy selection
+        let _ = self.enigo.key(Key::Control, Direction::Press);
+        sleep_ms(delays.keystroke);
+        #[cfg(target_os = "windows")]
+        let _ = self.enigo.key(Key::C, Direction::Click);
+        #[cfg(not(target_os = "windows"))]
+        let _ = self.enigo.key(Key::Unicode('c'), Direction::Click);
+        sleep_ms(delays.keystroke);
+        let _ = self.enigo.key(Key::Control, Direction::Release);
+        sleep_ms(delays.copy);
+
+        if let Ok(text) = self.clipboard.get_text() {
+            let options = config.conversion.options();
+            let converted = match action {
+                Action::ToUnicode => {
+                    let (output, report) = convert_with_report(&text, &options);
+                    for diagnostic in &report.diagnostics {
+                        eprintln!(
+                            "Warning: {} in `{}`",
+                            diagnostic,
+                            &text[diagnostic.span.bytes()]
+                        );
+                    }
+                    output
+                }
+                Action::ToLatex => reverse::to_latex(&text, &config.conversion.symbols),
+            };
+
+            let result = if action == Action::ToUnicode && options.format.is_markup() {
+                // for applications that only paste plain text
+                let plain = Options {
+                    format: Format::Unicode,
+                    ..options
+                };
+                self.clipboard
+                    .set_html(converted, Some(convert(&text, &plain)))
+            } else {
+                self.clipboard.set_text(converted)
+            };
+            match result {
+                Ok(()) => {
+                    sleep_ms(delays.paste);
+
+                    // paste
+                    let _ = self.enigo.key(Key::Control, Direction::Press);
+                    sleep_ms(delays.keystroke);
+                    #[cfg(target_os = "windows")]
+                    let _ = self.enigo.key(Key::V, Direction::Click);
+                    #[cfg(not(target_os = "windows"))]
+                    let _ = self.enigo.key(Key::Unicode('v'), Direction::Click);
+                    sleep_ms(delays.keystroke);
+                    let _ = self.enigo.key(Key::Control, Direction::Release);
+                }
+                // nothing to paste, but the clipboard was cleared above and
+                // still has to be restored, so don't return early
+                Err(e) => eprintln!("Failed to set clipboard: {}", e),
+            }
+        }
+
+        if let Some(saved) = saved {
+            // give the application time to read the converted text first
+            sleep_ms(delays.restore);
+            self.restore_clipboard(saved);
+        }
+    }
+
+    fn save_clipboard(&mut self) -> SavedClipboard {
+        SavedClipboard {
+            text: self.clipboard.get_text().ok(),
+            html: self.clipboard.get().html().ok(),
+            image: self.clipboard.get_image().ok(),
+        }
+    }
+
+    fn restore_clipboard(&mut self, saved: SavedClipboard) {
+        let result = match saved {
+            SavedClipboard {
+                html: Some(html),
+                text,
+                ..
+            } => self.clipboard.set_html(html, text),
+            SavedClipboard {
+                image: Some(image), ..
+            } => self.clipboard.set_image(image),
+            SavedClipboard {
+                text: Some(text), ..
+            } => self.clipboard.set_text(text),
+            _ => self.clipboard.clear(),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to restore clipboard: {}", e);
+        }
+    }
 }
 
-#[cfg(target_os = "linux")]
+// The clipboard formats `arboard` can read back. Only one of HTML (with its
+// plain-text alternative), image or text can be written again, in that order.
+struct SavedClipboard {
+    text: Option<String>,
+    html: Option<String>,
+    image: Option<ImageData<'static>>,
+}
+
+fn sleep_ms(ms: u64) {
+    thread::sleep(Duration::from_millis(ms));
+}
+
+// Loads the config file, exiting with a readable error if it is invalid.
+fn load_config() -> (Option<PathBuf>, Config) {
+    let Some(path) = config::path() else {
+        return (None, Config::default());
+    };
+    match config::load(&path) {
+        Ok(config) => (Some(path), config),
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
 fn main() {
-    handle_conversion();
+    let (path, config) = load_config();
+    let config = Arc::new(RwLock::new(config));
+    let session = Arc::new(Mutex::new(Session::new()));
+
+    for trigger in triggers(&config.read().unwrap()) {
+        bind(trigger, Arc::clone(&config), Arc::clone(&session));
+    }
+    if let Some(path) = path {
+        let (config, session) = (Arc::clone(&config), Arc::clone(&session));
+        config::watch(path, Arc::clone(&config), move |new_config| {
+            let old_triggers = triggers(&config.read().unwrap());
+            let new_triggers = triggers(new_config);
+            if old_triggers != new_triggers {
+                for trigger in old_triggers {
+                    trigger.unbind();
+                }
+                for trigger in new_triggers {
+                    bind(trigger, Arc::clone(&config), Arc::clone(&session));
+                }
+            }
+        });
+    }
+    inputbot::handle_input_events();
 }
 
-fn handle_conversion() {
-    // wait for hotkey release
-    thread::sleep(Duration::from_millis(50));
-
-    let mut enigo = Enigo::new(&Settings::default()).unwrap();
-    let mut clipboard = Clipboard::new().unwrap();
-
-    // copy selection
-    let _ = enigo.key(Key::Control, Direction::Press);
-    thread::sleep(Duration::from_millis(20));
-    #[cfg(target_os = "windows")]
-    let _ = enigo.key(Key::C, Direction::Click);
-    #[cfg(not(target_os = "windows"))]
-    let _ = enigo.key(Key::Unicode('c'), Direction::Click);
-    thread::sleep(Duration::from_millis(20));
-    let _ = enigo.key(Key::Control, Direction::Release);
-    thread::sleep(Duration::from_millis(150));
-
-    if let Ok(latex_text) = clipboard.get_text() {
-        let unicode_text = convert_latex_to_unicode(&latex_text);
-
-        if let Err(e) = clipboard.set_text(unicode_text) {
-            eprintln!("Failed to set clipboard: {}", e);
-            return;
+#[cfg(target_os = "windows")]
+fn bind(trigger: KeybdKey, config: Arc<RwLock<Config>>, session: Arc<Mutex<Session>>) {
+    trigger.bind(move || {
+        let config = config.read().unwrap().clone();
+        let held = |codes: &[u64]| codes.iter().any(|&c| KeybdKey::from(c).is_pressed());
+        // VK_LCONTROL/VK_RCONTROL, VK_LSHIFT/VK_RSHIFT, VK_LMENU/VK_RMENU, VK_LWIN/VK_RWIN
+        let action = config.action_for(|hotkey| {
+            windows_key(hotkey.key) == trigger
+                && (!hotkey.ctrl || held(&[0xA2, 0xA3]))
+                && (!hotkey.shift || held(&[0xA0, 0xA1]))
+                && (!hotkey.alt || held(&[0xA4, 0xA5]))
+                && (!hotkey.meta || held(&[0x5B, 0x5C]))
+        });
+        if let Some(action) = action {
+            session.lock().unwrap().handle_conversion(&config, action);
         }
-        thread::sleep(Duration::from_millis(50));
+    });
+}
 
-        // paste
-        let _ = enigo.key(Key::Control, Direction::Press);
-        thread::sleep(Duration::from_millis(20));
-        #[cfg(target_os = "windows")]
-        let _ = enigo.key(Key::V, Direction::Click);
-        #[cfg(not(target_os = "windows"))]
-        let _ = enigo.key(Key::Unicode('v'), Direction::Click);
-        thread::sleep(Duration::from_millis(20));
-        let _ = enigo.key(Key::Control, Direction::Release);
-    } 
+// The keys to bind, one per hotkey unless they share their key.
+#[cfg(target_os = "windows")]
+fn triggers(config: &Config) -> Vec<KeybdKey> {
+    let mut triggers = vec![windows_key(config.hotkey.key)];
+    let reverse = windows_key(config.reverse_hotkey.key);
+    if !triggers.contains(&reverse) {
+        triggers.push(reverse);
+    }
+    triggers
+}
 
-    thread::sleep(Duration::from_secs(2));
+#[cfg(target_os = "windows")]
+fn windows_key(key: config::Key) -> KeybdKey {
+    // virtual-key codes: letters and digits are their ASCII value, F1 is 0x70
+    match key {
+        config::Key::Char(c) => KeybdKey::from(c as u64),
+        config::Key::Function(n) => KeybdKey::from(0x6F + n as u64),
+    }
 }
 
+#[cfg(target_os = "linux")]
+fn main() {
+    let (path, config) = load_config();
+    let mut session = Session::new();
+
+    if std::env::args().any(|arg| arg == "--daemon") {
+        let config = Arc::new(RwLock::new(config));
+        if let Some(path) = path {
+            config::watch(path, Arc::clone(&config), |_| {});
+        }
+        let listener = hotkey::listen(Arc::clone(&config), |action| {
+            let config = config.read().unwrap().clone();
+            session.handle_conversion(&config, action);
+        });
+        if let Err(e) = listener {
+            eprintln!("Hotkey listener failed: {}", e);
+            std::process::exit(1);
+        }
+    } else {
+        let action = if std::env::args().any(|arg| arg == "--reverse") {
+            Action::ToLatex
+        } else {
+            Action::ToUnicode
+        };
+        session.handle_conversion(&config, action);
+        // keep owning the clipboard until the paste has been served
+        thread::sleep(Duration::from_secs(2));
+    }
+}
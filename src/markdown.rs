@@ -0,0 +1,54 @@
+use crate::delimiters::MathSpan;
+use pulldown_cmark::{Event, Options, Parser};
+
+// Finds the `$...$` and `$$...$$` math in a CommonMark document, in order.
+// Dollars in code spans, fenced and indented code blocks, HTML and link
+// destinations are not math, and neither is `\$`.
+pub fn find_math_spans(input: &str) -> Vec<MathSpan> {
+    Parser::new_ext(input, Options::ENABLE_MATH)
+        .into_offset_iter()
+        .filter_map(|(event, range)| {
+            let (delimiter, display) = match event {
+                Event::InlineMath(_) => (1, false),
+                Event::DisplayMath(_) => (2, true),
+                _ => return None,
+            };
+            Some(MathSpan {
+                inner: range.start + delimiter..range.end - delimiter,
+                outer: range,
+                display,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inner(input: &str) -> Vec<&str> {
+        find_math_spans(input)
+            .into_iter()
+            .map(|span| &input[span.inner])
+            .collect()
+    }
+
+    #[test]
+    fn test_math() {
+        assert_eq!(
+            inner("# $x$ and\n\n- $$\\frac{1}{2}$$ costs \\$5, $y$\n"),
+            ["x", r"\frac{1}{2}", "y"]
+        );
+        let spans = find_math_spans("a $$b$$");
+        assert_eq!((spans[0].outer.clone(), spans[0].display), (2..7, true));
+    }
+
+    #[test]
+    fn test_code() {
+        assert_eq!(inner("`$x$` $y$ ``$` `` $z$"), ["y", "z"]);
+        assert_eq!(
+            inner("```\n$x$\n```\n\n    $y$\n\n~~~sh\necho $HOME $z$\n~~~\n$w$"),
+            ["w"]
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use crate::Options;
+use crate::ast::{Command, Delimited, Environment, Expr, ExprKind};
+use crate::diagnostics::Diagnostic;
+use crate::html::escape;
+use crate::pretty::has_limits;
+use crate::renderer::{AtomClass, Renderer, UnicodeRenderer, map_char_style};
+use crate::token::Span;
+use std::cell::Cell;
+
+// Presentation MathML, for pasting into web pages and rich text editors. The
+// symbols and diagnostics come from the Unicode renderer; spacing and the
+// layout of fractions, roots, scripts and accents are left to the browser.
+#[derive(Default)]
+pub struct MathMlRenderer {
+    inline: UnicodeRenderer,
+    // `<math display="block">`, with limits above and below big operators
+    display: bool,
+    // the style of `\mathbb` and the like around the letters being rendered
+    style: Cell<Option<&'static str>>,
+}
+
+impl MathMlRenderer {
+    pub fn new() -> Self {
+        MathMlRenderer::default()
+    }
+
+    pub fn with_options(options: &Options) -> Self {
+        MathMlRenderer {
+            inline: UnicodeRenderer::with_options(options),
+            ..MathMlRenderer::default()
+        }
+    }
+
+    // Math on a line of its own rather than inside a line of text.
+    pub fn with_display(mut self, display: bool) -> Self {
+        self.display = display;
+        self
+    }
+}
+
+impl Renderer for MathMlRenderer {
+    fn render(&self, exprs: &[Expr]) -> String {
+        let display = if self.display {
+            " display=\"block\""
+        } else {
+            ""
+        };
+        format!(
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"{}>{}</math>",
+            display,
+            self.sequence(exprs)
+        )
+    }
+
+    fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        self.inline.take_diagnostics()
+    }
+}
+
+impl MathMlRenderer {
+    fn sequence(&self, exprs: &[Expr]) -> String {
+        let mut result = String::new();
+        let mut i = 0;
+        while i < exprs.len() {
+            // numbers like `3.14` are a single `<mn>`
+            let number: String = exprs[i..]
+                .iter()
+                .map_while(|e| match e.kind {
+                    ExprKind::Literal(c) if c.is_ascii_digit() || c == '.' => Some(c),
+                    _ => None,
+                })
+                .collect();
+            let number = number.trim_end_matches('.');
+            if number.starts_with(|c: char| c.is_ascii_digit()) {
+                result += &self.token("mn", number);
+                i += number.len();
+                continue;
+            }
+
+            result += &self.expr(&exprs[i]);
+            // `sin x` rather than `sinx`
+            if i + 1 < exprs.len() && self.is_named_function(&exprs[i]) {
+                result += "<mo>\u{2061}</mo>";
+            }
+            i += 1;
+        }
+        result
+    }
+
+    // A single element, as the arguments of `<mfrac>` and the like must be.
+    fn argument(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Group(exprs) if exprs.len() == 1 => self.argument(&exprs[0]),
+            ExprKind::Group(exprs) => format!("<mrow>{}</mrow>", self.sequence(exprs)),
+            _ => self.sequence(std::slice::from_ref(expr)),
+        }
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Literal(c) if c.is_alphabetic() => self.token("mi", &c.to_string()),
+            ExprKind::Literal(c) if c.is_ascii_digit() => self.token("mn", &c.to_string()),
+            ExprKind::Literal(c) if c.is_whitespace() => text(" "),
+            // the characters typed for these, rather than the proper ones
+            ExprKind::Literal('-') => self.token("mo", "−"),
+            ExprKind::Literal('\'') => self.token("mo", "′"),
+            ExprKind::Literal(c) => self.token("mo", &c.to_string()),
+            ExprKind::Group(exprs) => format!("<mrow>{}</mrow>", self.sequence(exprs)),
+            ExprKind::Superscript(..) | ExprKind::Subscript(..) => self.scripts(expr),
+            ExprKind::Environment(env) => {
+                self.inline.check_environment(env, expr.span);
+                self.environment(env)
+            }
+            ExprKind::Delimited(delimited) => self.delimited(delimited, expr.span),
+            ExprKind::Command(cmd) => self.command(expr, cmd),
+        }
+    }
+
+    fn command(&self, expr: &Expr, cmd: &Command) -> String {
+        match cmd {
+            Command::Frac { numer, denom } => format!(
+                "<mfrac>{}{}</mfrac>",
+                self.argument(numer),
+                self.argument(denom)
+            ),
+            // `\sqrt[]{x}` is a square root too
+            Command::Sqrt {
+                index: Some(index),
+                content,
+            } if !matches!(&index.kind, ExprKind::Group(exprs) if exprs.is_empty()) => format!(
+                "<mroot>{}{}</mroot>",
+                self.argument(content),
+                self.argument(index)
+            ),
+            Command::Sqrt { content, .. } => format!("<msqrt>{}</msqrt>", self.argument(content)),
+            Command::Mathcal { content } => self.styled("mathcal", content),
+            Command::Mathbb { content } => self.styled("mathbb", content),
+            Command::Mathfrak { content } => self.styled("mathfrak", content),
+            Command::Mathbf { content } => self.styled("mathbf", content),
+            Command::Mathit { content } => self.styled("mathit", content),
+            Command::Mathsf { content } => self.styled("mathsf", content),
+            Command::Mathtt { content } => self.styled("mathtt", content),
+            Command::Bar { content } => self.accent(content, '¯'),
+            Command::Hat { content } => self.accent(content, '^'),
+            Command::Vec { content } => self.accent(content, '→'),
+            Command::Dot { content } => self.accent(content, '˙'),
+            Command::Ddot { content } => self.accent(content, '¨'),
+            Command::Tilde { content } => self.accent(content, '~'),
+            Command::Text { .. } | Command::Textrm { .. } | Command::Mbox { .. } => {
+                text(&self.inline.render_expr(expr))
+            }
+            // upright words, which `<mi>` gives anything longer than a letter
+            Command::Mathrm { .. } | Command::Operatorname { .. } => {
+                let word = self.inline.render_expr(expr);
+                if word.chars().count() == 1 {
+                    format!("<mi mathvariant=\"normal\">{}</mi>", escape(&word))
+                } else {
+                    format!("<mi>{}</mi>", escape(&word))
+                }
+            }
+            Command::Xrightarrow { below, above } => self.xarrow('→', above, below.as_deref()),
+            Command::Xleftarrow { below, above } => self.xarrow('←', above, below.as_deref()),
+            Command::Symbol { name } | Command::Custom { name, .. } => self.symbol(expr, name),
+        }
+    }
+
+    fn symbol(&self, expr: &Expr, name: &str) -> String {
+        // reports unknown commands, which are left as `\name`
+        let output = self.inline.render_expr(expr);
+        let Some(symbol) = self.inline.symbol(name) else {
+            return text(&output);
+        };
+        match symbol.class {
+            None => match space_width(name) {
+                Some(width) => format!("<mspace width=\"{}\"/>", width),
+                None if output.is_empty() => String::new(),
+                None => text(&output),
+            },
+            Some(AtomClass::Ord) => self.token("mi", &output),
+            Some(AtomClass::Op) if symbol.named => self.token("mi", &output),
+            Some(_) => self.token("mo", &output),
+        }
+    }
+
+    fn is_named_function(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Command(Command::Symbol { name }) => {
+                self.inline.symbol(name).is_some_and(|symbol| symbol.named)
+            }
+            ExprKind::Command(Command::Operatorname { .. }) => true,
+            ExprKind::Superscript(base, _) | ExprKind::Subscript(base, _) => {
+                self.is_named_function(base)
+            }
+            _ => false,
+        }
+    }
+
+    // `text` in the current style, as a token element like `<mi>`.
+    fn token(&self, tag: &str, text: &str) -> String {
+        let text: String = match self.style.get() {
+            Some(style) => text.chars().map(|c| map_char_style(style, c)).collect(),
+            None => text.to_string(),
+        };
+        format!("<{}>{}</{}>", tag, escape(&text), tag)
+    }
+
+    // Styled letters are the Unicode mathematical alphanumerics, as MathML
+    // Core recommends over `mathvariant`.
+    fn styled(&self, style: &'static str, content: &Expr) -> String {
+        let outer = self.style.replace(Some(style));
+        let result = self.argument(content);
+        self.style.set(outer);
+        result
+    }
+
+    fn accent(&self, content: &Expr, mark: char) -> String {
+        format!(
+            "<mover accent=\"true\">{}<mo>{}</mo></mover>",
+            self.argument(content),
+            mark
+        )
+    }
+
+    fn xarrow(&self, arrow: char, above: &Expr, below: Option<&Expr>) -> String {
+        let arrow = format!("<mo stretchy=\"true\">{}</mo>", arrow);
+        match below {
+            Some(below) => format!(
+                "<munderover>{}{}{}</munderover>",
+                arrow,
+                self.argument(below),
+                self.argument(above)
+            ),
+            None => format!("<mover>{}{}</mover>", arrow, self.argument(above)),
+        }
+    }
+
+    // `base^sup_sub` with the scripts in either order.
+    fn scripts(&self, expr: &Expr) -> String {
+        let (base, sup, sub) = match &expr.kind {
+            ExprKind::Superscript(inner, sup) => match &inner.kind {
+                ExprKind::Subscript(base, sub) => (&**base, Some(&**sup), Some(&**sub)),
+                _ => (&**inner, Some(&**sup), None),
+            },
+            ExprKind::Subscript(inner, sub) => match &inner.kind {
+                ExprKind::Superscript(base, sup) => (&**base, Some(&**sup), Some(&**sub)),
+                _ => (&**inner, None, Some(&**sub)),
+            },
+            _ => return self.argument(expr),
+        };
+
+        let limits = self.display && has_limits(base);
+        let (tag, scripts) = match (sub, sup) {
+            (Some(sub), Some(sup)) => (
+                if limits { "munderover" } else { "msubsup" },
+                self.argument(sub) + &self.argument(sup),
+            ),
+            (Some(sub), None) => (if limits { "munder" } else { "msub" }, self.argument(sub)),
+            (None, Some(sup)) => (if limits { "mover" } else { "msup" }, self.argument(sup)),
+            (None, None) => return self.argument(base),
+        };
+        format!("<{}>{}{}</{}>", tag, self.argument(base), scripts, tag)
+    }
+
+    fn environment(&self, env: &Environment) -> String {
+        let mut table = String::from("<mtable>");
+        for row in &env.rows {
+            table += "<mtr>";
+            for (j, cell) in row.iter().enumerate() {
+                table += &match column_alignment(env, j) {
+                    "center" => format!("<mtd>{}</mtd>", self.argument(cell)),
+                    align => format!(
+                        "<mtd columnalign=\"{}\">{}</mtd>",
+                        align,
+                        self.argument(cell)
+                    ),
+                };
+            }
+            table += "</mtr>";
+        }
+        table += "</mtable>";
+
+        let (open, close) = match env.name.as_str() {
+            "pmatrix" => ("(", ")"),
+            "bmatrix" => ("[", "]"),
+            "Bmatrix" => ("{", "}"),
+            "vmatrix" => ("|", "|"),
+            "Vmatrix" => ("‖", "‖"),
+            "cases" => ("{", ""),
+            _ => return table,
+        };
+        format!("<mrow>{}{}{}</mrow>", fence(open), table, fence(close))
+    }
+
+    fn delimited(&self, delimited: &Delimited, span: Span) -> String {
+        let fence = |delimiter: &str| fence(&self.inline.delimiter(delimiter, span));
+        let mut result = String::from("<mrow>");
+        result += &fence(&delimited.open);
+        for (i, part) in delimited.parts.iter().enumerate() {
+            if let Some(middle) = i.checked_sub(1).and_then(|i| delimited.middles.get(i)) {
+                result += &fence(middle);
+            }
+            result += &self.argument(part);
+        }
+        result += &fence(&delimited.close);
+        result + "</mrow>"
+    }
+}
+
+// A delimiter that grows with what it encloses; nothing for an invisible one.
+fn fence(delimiter: &str) -> String {
+    if delimiter.is_empty() {
+        String::new()
+    } else {
+        format!("<mo stretchy=\"true\">{}</mo>", escape(delimiter))
+    }
+}
+
+// Leading and trailing spaces in `<mtext>` would be dropped.
+fn text(s: &str) -> String {
+    format!("<mtext>{}</mtext>", escape(s).replace(' ', "\u{A0}"))
+}
+
+// The width of an explicit space like `\,` or `\quad`.
+fn space_width(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "," => "0.167em",
+        ":" | ">" => "0.222em",
+        ";" => "0.278em",
+        " " => "0.25em",
+        "!" => "-0.167em",
+        "quad" => "1em",
+        "qquad" => "2em",
+        _ => return None,
+    })
+}
+
+// How column `j` of an environment is aligned, as a CSS `text-align` value.
+pub(crate) fn column_alignment(env: &Environment, j: usize) -> &'static str {
+    match env.name.as_str() {
+        // `rl` pairs around the alignment points
+        "aligned" if j.is_multiple_of(2) => "right",
+        "aligned" | "cases" => "left",
+        "array" => match env
+            .columns
+            .iter()
+            .flat_map(|c| c.chars())
+            .filter(|c| c.is_alphabetic())
+            .nth(j)
+        {
+            Some('l') => "left",
+            Some('r') => "right",
+            _ => "center",
+        },
+        _ => "center",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn render(input: &str) -> String {
+        let mut parser = Parser::new(Lexer::new(input));
+        let math = MathMlRenderer::new().render(&parser.parse());
+        let inner = math
+            .strip_prefix("<math xmlns=\"http://www.w3.org/1998/Math/MathML\">")
+            .and_then(|m| m.strip_suffix("</math>"));
+        inner.expect("wrapped in <math>").to_string()
+    }
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(
+            render(r"x' \le 3.14 - 1"),
+            "<mi>x</mi><mo>′</mo><mo>≤</mo><mn>3.14</mn><mo>−</mo><mn>1</mn>"
+        );
+        assert_eq!(
+            render(r"\sin x < \alpha"),
+            "<mi>sin</mi><mo>\u{2061}</mo><mi>x</mi><mo>&lt;</mo><mi>α</mi>"
+        );
+        assert_eq!(render(r"\mathbb{R}^2"), "<msup><mi>ℝ</mi><mn>2</mn></msup>");
+        assert_eq!(
+            render(r"a\,\text{if }b"),
+            "<mi>a</mi><mspace width=\"0.167em\"/><mtext>if\u{A0}</mtext><mi>b</mi>"
+        );
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(
+            render(r"\frac{1}{x+1}"),
+            "<mfrac><mn>1</mn><mrow><mi>x</mi><mo>+</mo><mn>1</mn></mrow></mfrac>"
+        );
+        assert_eq!(
+            render(r"\sqrt{2} \sqrt[3]{x}"),
+            "<msqrt><mn>2</mn></msqrt><mroot><mi>x</mi><mn>3</mn></mroot>"
+        );
+        assert_eq!(
+            render("x_i^2"),
+            "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>"
+        );
+        assert_eq!(
+            render(r"\hat{x}"),
+            "<mover accent=\"true\"><mi>x</mi><mo>^</mo></mover>"
+        );
+        assert_eq!(
+            render(r"\left( x \right."),
+            "<mrow><mo stretchy=\"true\">(</mo><mi>x</mi></mrow>"
+        );
+        assert_eq!(
+            render(r"\begin{pmatrix} a & b \end{pmatrix}"),
+            "<mrow><mo stretchy=\"true\">(</mo><mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr></mtable><mo stretchy=\"true\">)</mo></mrow>"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let mut parser = Parser::new(Lexer::new(r"\sum_{i=1}^n i"));
+        let math = MathMlRenderer::new()
+            .with_display(true)
+            .render(&parser.parse());
+        assert!(math.contains(" display=\"block\">"));
+        assert!(math.contains("<munderover><mo>∑</mo>"));
+    }
+}
@@ -1,34 +1,88 @@
-use crate::ast::{CommandRegistry, Expr};
+use crate::ast::{Command, CommandRegistry, Delimited, Environment, Expr, ExprKind};
+use crate::diagnostics::{Diagnostic, DiagnosticKind};
 use crate::lexer::Lexer;
-use crate::token::Token;
+use crate::macros::{Expander, Macros};
+use crate::symbols::SymbolTable;
+use crate::token::{Span, Token};
 
 pub struct Parser<'a> {
-    lexer: Lexer<'a>,
+    tokens: Expander<'a>,
+    symbols: &'a SymbolTable,
     current_token: Token,
+    current_span: Span,
+    // span of the last token that was consumed
+    previous_span: Span,
+    diagnostics: Vec<Diagnostic>,
+    // how many environments the current token is in, where `&` and `\\`
+    // separate cells instead of being symbols
+    environment_depth: usize,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(lexer: Lexer<'a>) -> Self {
+        Parser::with_macros(lexer, Macros::new())
+    }
+
+    // Expands `macros` as well as any macros defined in the input itself.
+    pub fn with_macros(lexer: Lexer<'a>, macros: Macros) -> Self {
         let mut p = Parser {
-            lexer,
+            tokens: Expander::new(lexer, macros),
+            symbols: SymbolTable::builtin(),
             current_token: Token::EOF,
+            current_span: Span::default(),
+            previous_span: Span::default(),
+            diagnostics: Vec::new(),
+            environment_depth: 0,
         };
         p.advance();
         p
     }
 
     fn advance(&mut self) {
-        self.current_token = self.lexer.next_token();
+        let next = self.tokens.next_spanned();
+        self.previous_span = self.current_span;
+        self.current_token = next.token;
+        self.current_span = next.span;
+    }
+
+    // The span from `start` up to and including the last consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous_span)
+    }
+
+    fn report(&mut self, kind: DiagnosticKind, span: Span) {
+        self.diagnostics.push(Diagnostic { kind, span });
+    }
+
+    // Problems found while parsing, such as unbalanced braces or missing
+    // arguments. The parser recovers from all of them.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        diagnostics.extend(self.tokens.take_diagnostics());
+        diagnostics
+    }
+
+    // Looks up the arity of commands in `symbols` instead of the built-in table.
+    pub fn with_symbols(mut self, symbols: &'a SymbolTable) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    // The macros given to the parser, plus those defined in the input so far.
+    pub fn macros(&self) -> &Macros {
+        self.tokens.macros()
     }
 
     pub fn parse(&mut self) -> Vec<Expr> {
         let mut exprs = Vec::new();
         while self.current_token != Token::EOF {
             if self.current_token == Token::RBrace {
-                break;
+                self.report(DiagnosticKind::UnmatchedBrace, self.current_span);
+                self.advance();
+                continue;
             }
             if let Some(expr) = self.parse_expr() {
-                exprs.push(expr);
+                push_expr(&mut exprs, expr);
             } else {
                 self.advance();
             }
@@ -40,7 +94,7 @@ impl<'a> Parser<'a> {
         let mut exprs = Vec::new();
         while self.current_token != Token::EOF && self.current_token != Token::RBrace {
             if let Some(expr) = self.parse_expr() {
-                exprs.push(expr);
+                push_expr(&mut exprs, expr);
             } else {
                 self.advance();
             }
@@ -48,24 +102,275 @@ impl<'a> Parser<'a> {
         exprs
     }
 
+    // `[...]` after a command that declares an optional argument, as a group.
+    fn parse_optional_arg(&mut self) -> Expr {
+        let start = self.current_span;
+        self.advance();
+        let mut content = Vec::new();
+        while !matches!(
+            self.current_token,
+            Token::RBracket | Token::RBrace | Token::EOF
+        ) {
+            if let Some(expr) = self.parse_expr() {
+                push_expr(&mut content, expr);
+            } else {
+                self.advance();
+            }
+        }
+        if self.current_token == Token::RBracket {
+            self.advance();
+        } else {
+            self.report(DiagnosticKind::UnclosedBracket, start);
+        }
+        Expr::new(ExprKind::Group(content), self.span_from(start))
+    }
+
+    // `{...}` after a text command, as a group. Spaces are kept as `' '`
+    // literals and `^` and `_` are plain characters.
+    fn parse_text_arg(&mut self) -> Expr {
+        let start = self.current_span;
+        let outer_mode = self.tokens.text_mode();
+        // the token after `{` is the first one read in text mode
+        self.tokens.set_text_mode(true);
+        self.advance();
+        // like any group, the text doesn't belong to the cell around it
+        let outer_depth = std::mem::replace(&mut self.environment_depth, 0);
+
+        let mut content = Vec::new();
+        loop {
+            match self.current_token {
+                Token::RBrace | Token::EOF => break,
+                Token::LBrace => content.push(self.parse_text_arg()),
+                _ => match self.parse_base() {
+                    Some(expr) => push_expr(&mut content, expr),
+                    None => self.advance(),
+                },
+            }
+        }
+
+        // switch back before the token after `}` is read
+        self.environment_depth = outer_depth;
+        self.tokens.set_text_mode(outer_mode);
+        if self.current_token == Token::RBrace {
+            self.advance();
+        } else {
+            self.report(DiagnosticKind::UnclosedBrace, start);
+        }
+        Expr::new(group(content), self.span_from(start))
+    }
+
+    // The rest of `\begin{name} ... \end{name}` after `\begin`.
+    fn parse_environment(&mut self, start: Span) -> ExprKind {
+        let name = self.parse_environment_name("begin");
+        let columns = if name == "array" && self.current_token == Token::LBrace {
+            self.parse_base().map(|spec| {
+                let mut columns = String::new();
+                spec.walk(&mut |e| {
+                    if let ExprKind::Literal(c) = e.kind {
+                        columns.push(c);
+                    }
+                });
+                columns
+            })
+        } else {
+            None
+        };
+
+        self.environment_depth += 1;
+        let mut rows = Vec::new();
+        let mut cells = Vec::new();
+        loop {
+            cells.push(self.parse_cell());
+            match self.current_token {
+                Token::Ampersand => self.advance(),
+                Token::Newline => {
+                    self.advance();
+                    // extra row spacing like `\\[2pt]`
+                    if self.current_token == Token::LBracket {
+                        self.parse_optional_arg();
+                    }
+                    rows.push(std::mem::take(&mut cells));
+                }
+                _ => break,
+            }
+        }
+        self.environment_depth -= 1;
+
+        // a `\\` after the last row doesn't start another one
+        let trailing_newline =
+            matches!(cells.as_slice(), [cell] if cell.kind == ExprKind::Group(vec![]));
+        if !trailing_newline || rows.is_empty() {
+            rows.push(cells);
+        }
+
+        let closed = self.current_token == Token::Command("end".to_string()) && {
+            self.advance();
+            self.parse_environment_name("end") == name
+        };
+        if !closed {
+            self.report(DiagnosticKind::UnclosedEnvironment(name.clone()), start);
+        }
+        ExprKind::Environment(Environment {
+            name,
+            columns,
+            rows,
+        })
+    }
+
+    // `{name}` after `\begin` or `\end`.
+    fn parse_environment_name(&mut self, command: &str) -> String {
+        let start = self.current_span;
+        let mut name = String::new();
+        if self.current_token != Token::LBrace {
+            self.report(
+                DiagnosticKind::MissingArgument(format!("\\{}", command)),
+                self.previous_span,
+            );
+            return name;
+        }
+        self.advance();
+        while let Token::Char(c) = self.current_token {
+            name.push(c);
+            self.advance();
+        }
+        if self.current_token == Token::RBrace {
+            self.advance();
+        } else {
+            self.report(DiagnosticKind::UnclosedBrace, start);
+        }
+        name
+    }
+
+    // One cell of an environment, up to the next `&`, `\\` or `\end`.
+    fn parse_cell(&mut self) -> Expr {
+        let mut content = Vec::new();
+        loop {
+            match &self.current_token {
+                Token::Ampersand | Token::Newline | Token::EOF => break,
+                Token::Command(name) if name == "end" => break,
+                Token::RBrace => {
+                    self.report(DiagnosticKind::UnmatchedBrace, self.current_span);
+                    self.advance();
+                }
+                _ => match self.parse_expr() {
+                    Some(expr) => push_expr(&mut content, expr),
+                    None => self.advance(),
+                },
+            }
+        }
+        let span = match (content.first(), content.last()) {
+            (Some(first), Some(last)) => first.span.to(last.span),
+            _ => self.previous_span.shrink_to_end(),
+        };
+        Expr::new(ExprKind::Group(content), span)
+    }
+
+    // The rest of `\left( ... \middle| ... \right)` after `\left`.
+    fn parse_delimited(&mut self, start: Span) -> ExprKind {
+        let open = self.parse_delimiter("left");
+        let mut middles = Vec::new();
+        let mut parts = Vec::new();
+        let close = loop {
+            parts.push(self.parse_delimited_part());
+            match &self.current_token {
+                Token::Command(name) if name == "middle" => {
+                    self.advance();
+                    middles.push(self.parse_delimiter("middle"));
+                }
+                Token::Command(name) if name == "right" => {
+                    self.advance();
+                    break self.parse_delimiter("right");
+                }
+                _ => {
+                    self.report(DiagnosticKind::UnclosedDelimiter, start);
+                    break ".".to_string();
+                }
+            }
+        };
+        ExprKind::Delimited(Delimited {
+            open,
+            close,
+            middles,
+            parts,
+        })
+    }
+
+    // Content up to the next `\middle` or `\right`, or the end of the
+    // enclosing group, cell or environment.
+    fn parse_delimited_part(&mut self) -> Expr {
+        let mut content = Vec::new();
+        loop {
+            match &self.current_token {
+                Token::EOF | Token::RBrace => break,
+                Token::Ampersand | Token::Newline if self.environment_depth > 0 => break,
+                Token::Command(name) if matches!(name.as_str(), "middle" | "right" | "end") => {
+                    break;
+                }
+                _ => match self.parse_expr() {
+                    Some(expr) => push_expr(&mut content, expr),
+                    None => self.advance(),
+                },
+            }
+        }
+        let span = match (content.first(), content.last()) {
+            (Some(first), Some(last)) => first.span.to(last.span),
+            _ => self.previous_span.shrink_to_end(),
+        };
+        Expr::new(ExprKind::Group(content), span)
+    }
+
+    // The delimiter after `\left`, `\middle` or `\right`, as written.
+    fn parse_delimiter(&mut self, command: &str) -> String {
+        let delimiter = match &self.current_token {
+            Token::Char(c) => c.to_string(),
+            Token::LParen => "(".to_string(),
+            Token::RParen => ")".to_string(),
+            Token::LBracket => "[".to_string(),
+            Token::RBracket => "]".to_string(),
+            Token::Command(name) => format!("\\{}", name),
+            _ => {
+                self.report(
+                    DiagnosticKind::MissingArgument(format!("\\{}", command)),
+                    self.previous_span,
+                );
+                return ".".to_string();
+            }
+        };
+        self.advance();
+        delimiter
+    }
+
     fn parse_expr(&mut self) -> Option<Expr> {
+        let start = self.current_span;
         let mut base = self.parse_base()?;
 
         loop {
             match self.current_token {
+                // in text `^` and `_` are plain characters
+                _ if self.tokens.text_mode() => break,
                 Token::Superscript => {
+                    let operator = self.current_span;
                     self.advance();
-                    if let Some(exponent) = self.parse_base() {
-                        base = Expr::Superscript(Box::new(base), Box::new(exponent));
+                    if let Some(exponent) = self.parse_argument() {
+                        base = Expr::new(
+                            ExprKind::Superscript(Box::new(base), Box::new(exponent)),
+                            self.span_from(start),
+                        );
                     } else {
+                        self.report(DiagnosticKind::MissingArgument("^".to_string()), operator);
                         break;
                     }
                 }
                 Token::Subscript => {
+                    let operator = self.current_span;
                     self.advance();
-                    if let Some(subscript) = self.parse_base() {
-                        base = Expr::Subscript(Box::new(base), Box::new(subscript));
+                    if let Some(subscript) = self.parse_argument() {
+                        base = Expr::new(
+                            ExprKind::Subscript(Box::new(base), Box::new(subscript)),
+                            self.span_from(start),
+                        );
                     } else {
+                        self.report(DiagnosticKind::MissingArgument("_".to_string()), operator);
                         break;
                     }
                 }
@@ -75,55 +380,195 @@ impl<'a> Parser<'a> {
         Some(base)
     }
 
+    // An argument or script. A group around a single character or symbol is
+    // left out, so `x^{2}` is read the same as `x^2`.
+    fn parse_argument(&mut self) -> Option<Expr> {
+        let arg = self.parse_base()?;
+        match arg.kind {
+            ExprKind::Group(mut content)
+                if matches!(
+                    content.as_slice(),
+                    [Expr {
+                        kind: ExprKind::Literal(_) | ExprKind::Command(Command::Symbol { .. }),
+                        ..
+                    }]
+                ) =>
+            {
+                content.pop()
+            }
+            kind => Some(Expr::new(kind, arg.span)),
+        }
+    }
+
     fn parse_base(&mut self) -> Option<Expr> {
-        match &self.current_token {
+        let start = self.current_span;
+        let kind = match &self.current_token {
             Token::Char(c) => {
-                let expr = Expr::Literal(*c);
+                let kind = ExprKind::Literal(*c);
                 self.advance();
-                Some(expr)
+                kind
             }
             Token::Command(s) => {
                 let name = s.clone();
                 self.advance();
+                match name.as_str() {
+                    "begin" => {
+                        let kind = self.parse_environment(start);
+                        return Some(Expr::new(kind, self.span_from(start)));
+                    }
+                    "left" => {
+                        let kind = self.parse_delimited(start);
+                        return Some(Expr::new(kind, self.span_from(start)));
+                    }
+                    // `\left ... \right` stops in front of these, so here they are stray
+                    "right" | "middle" => {
+                        self.report(
+                            DiagnosticKind::UnmatchedDelimiter(format!("\\{}", name)),
+                            start,
+                        );
+                        return self.parse_base();
+                    }
+                    // the size of the delimiter that follows doesn't matter here
+                    _ if is_sizing_command(&name) => return self.parse_base(),
+                    _ => {}
+                }
 
-                let arity = CommandRegistry::arity(&name);
-                let mut args = Vec::with_capacity(arity);
-                for _ in 0..arity {
-                    if let Some(arg) = self.parse_base() {
+                let mut optional_args = Vec::new();
+                for _ in 0..CommandRegistry::optional_arity(self.symbols, &name) {
+                    if self.current_token != Token::LBracket {
+                        break;
+                    }
+                    optional_args.push(self.parse_optional_arg());
+                }
+
+                let def = CommandRegistry::get(self.symbols, &name);
+                let mut args = Vec::with_capacity(def.arity());
+                for _ in 0..def.arity() {
+                    let arg = if def.is_text() && self.current_token == Token::LBrace {
+                        Some(self.parse_text_arg())
+                    } else {
+                        self.parse_argument()
+                    };
+                    if let Some(arg) = arg {
                         args.push(arg);
                     } else {
+                        self.report(
+                            DiagnosticKind::MissingArgument(format!("\\{}", name)),
+                            self.span_from(start),
+                        );
                         break;
                     }
                 }
-                Some(Expr::Command(CommandRegistry::build(&name, args)))
+                let span = self.span_from(start);
+                ExprKind::Command(CommandRegistry::build(
+                    self.symbols,
+                    &name,
+                    optional_args,
+                    args,
+                    span,
+                ))
             }
             Token::LBrace => {
                 self.advance();
+                // braces hide `&` and `\\` from the environment around them
+                let outer_depth = std::mem::replace(&mut self.environment_depth, 0);
                 let content = self.parse_sequence();
+                self.environment_depth = outer_depth;
                 if self.current_token == Token::RBrace {
                     self.advance();
+                } else {
+                    self.report(DiagnosticKind::UnclosedBrace, start);
                 }
-                Some(Expr::Group(content))
+                group(content)
+            }
+            Token::Superscript if self.tokens.text_mode() => {
+                self.advance();
+                ExprKind::Literal('^')
+            }
+            Token::Subscript if self.tokens.text_mode() => {
+                self.advance();
+                ExprKind::Literal('_')
             }
             Token::LParen => {
                 self.advance();
-                Some(Expr::Literal('('))
+                ExprKind::Literal('(')
             }
             Token::RParen => {
                 self.advance();
-                Some(Expr::Literal(')'))
+                ExprKind::Literal(')')
             }
             Token::LBracket => {
                 self.advance();
-                Some(Expr::Literal('['))
+                ExprKind::Literal('[')
             }
             Token::RBracket => {
                 self.advance();
-                Some(Expr::Literal(']'))
+                ExprKind::Literal(']')
             }
-            _ => None,
+            // outside environments these keep their old meaning
+            Token::Ampersand if self.environment_depth == 0 => {
+                self.advance();
+                ExprKind::Literal('&')
+            }
+            Token::Newline if self.environment_depth == 0 => {
+                self.advance();
+                ExprKind::Command(Command::Symbol {
+                    name: "\\".to_string(),
+                })
+            }
+            _ => return None,
+        };
+        Some(Expr::new(kind, self.span_from(start)))
+    }
+}
+
+// The group around `content`. Braces directly inside braces make no
+// difference, so `{{a}}` is the same group as `{a}`.
+fn group(mut content: Vec<Expr>) -> ExprKind {
+    match content.as_slice() {
+        [
+            Expr {
+                kind: ExprKind::Group(_),
+                ..
+            },
+        ] => content
+            .pop()
+            .map_or(ExprKind::Group(vec![]), |inner| inner.kind),
+        _ => ExprKind::Group(content),
+    }
+}
+
+// Adds `expr` to `exprs`. A space right after another one is merged into it,
+// as a run of whitespace is a single space even when a skipped token, like a
+// stray `}`, splits it. For the same reason a space after a command word is
+// left out, as it would be without the skipped token.
+fn push_expr(exprs: &mut Vec<Expr>, expr: Expr) {
+    if expr.kind == ExprKind::Literal(' ')
+        && let Some(last) = exprs.last_mut()
+    {
+        match &last.kind {
+            ExprKind::Literal(' ') => {
+                last.span = last.span.to(expr.span);
+                return;
+            }
+            ExprKind::Command(Command::Symbol { name })
+                if name.starts_with(char::is_alphabetic) =>
+            {
+                return;
+            }
+            _ => {}
         }
     }
+    exprs.push(expr);
+}
+
+// `\big`, `\Bigl`, `\biggr`, `\Biggm` and the rest of the family.
+fn is_sizing_command(name: &str) -> bool {
+    let size = name
+        .strip_suffix(['l', 'r', 'm'])
+        .filter(|size| !size.is_empty())
+        .unwrap_or(name);
+    matches!(size, "big" | "Big" | "bigg" | "Bigg")
 }
 
 #[cfg(test)]
@@ -138,9 +583,9 @@ mod tests {
         let mut parser = Parser::new(lexer);
         let ast = parser.parse();
         assert_eq!(ast.len(), 3);
-        assert_eq!(ast[0], Expr::Literal('a'));
-        assert_eq!(ast[1], Expr::Literal('+'));
-        assert_eq!(ast[2], Expr::Literal('b'));
+        assert_eq!(ast[0].kind, ExprKind::Literal('a'));
+        assert_eq!(ast[1].kind, ExprKind::Literal('+'));
+        assert_eq!(ast[2].kind, ExprKind::Literal('b'));
     }
 
     #[test]
@@ -150,10 +595,10 @@ mod tests {
         let mut parser = Parser::new(lexer);
         let ast = parser.parse();
         assert_eq!(ast.len(), 1);
-        match &ast[0] {
-            Expr::Superscript(base, exp) => {
-                assert_eq!(**base, Expr::Literal('x'));
-                assert_eq!(**exp, Expr::Literal('2'));
+        match &ast[0].kind {
+            ExprKind::Superscript(base, exp) => {
+                assert_eq!(base.kind, ExprKind::Literal('x'));
+                assert_eq!(exp.kind, ExprKind::Literal('2'));
             }
             _ => panic!("Expected Superscript"),
         }
@@ -166,11 +611,11 @@ mod tests {
         let mut parser = Parser::new(lexer);
         let ast = parser.parse();
         assert_eq!(ast.len(), 1);
-        match &ast[0] {
-            Expr::Group(content) => {
+        match &ast[0].kind {
+            ExprKind::Group(content) => {
                 assert_eq!(content.len(), 2);
-                assert_eq!(content[0], Expr::Literal('a'));
-                assert_eq!(content[1], Expr::Literal('b'));
+                assert_eq!(content[0].kind, ExprKind::Literal('a'));
+                assert_eq!(content[1].kind, ExprKind::Literal('b'));
             }
             _ => panic!("Expected Group"),
         }
@@ -186,15 +631,15 @@ mod tests {
         let ast = parser.parse();
         assert_eq!(ast.len(), 1);
         // Structure: ((\alpha)_1)^2
-        match &ast[0] {
-            Expr::Superscript(base, exp) => {
-                assert_eq!(**exp, Expr::Literal('2'));
-                match &**base {
-                    Expr::Subscript(inner_base, sub) => {
-                        assert_eq!(**sub, Expr::Literal('1'));
+        match &ast[0].kind {
+            ExprKind::Superscript(base, exp) => {
+                assert_eq!(exp.kind, ExprKind::Literal('2'));
+                match &base.kind {
+                    ExprKind::Subscript(inner_base, sub) => {
+                        assert_eq!(sub.kind, ExprKind::Literal('1'));
                         assert_eq!(
-                            **inner_base,
-                            Expr::Command(Command::Symbol {
+                            inner_base.kind,
+                            ExprKind::Command(Command::Symbol {
                                 name: "alpha".to_string()
                             })
                         );
@@ -215,10 +660,10 @@ mod tests {
         let mut parser = Parser::new(lexer);
         let ast = parser.parse();
         assert_eq!(ast.len(), 1);
-        match &ast[0] {
-            Expr::Command(Command::Frac { numer, denom }) => {
-                assert_eq!(**numer, Expr::Group(vec![Expr::Literal('a')]));
-                assert_eq!(**denom, Expr::Group(vec![Expr::Literal('b')]));
+        match &ast[0].kind {
+            ExprKind::Command(Command::Frac { numer, denom }) => {
+                assert_eq!(numer.kind, ExprKind::Literal('a'));
+                assert_eq!(denom.kind, ExprKind::Literal('b'));
             }
             _ => panic!("Expected Command Frac"),
         }
@@ -233,11 +678,264 @@ mod tests {
         let mut parser = Parser::new(lexer);
         let ast = parser.parse();
         assert_eq!(ast.len(), 1);
-        match &ast[0] {
-            Expr::Command(Command::Sqrt { content }) => {
-                assert_eq!(**content, Expr::Group(vec![Expr::Literal('x')]));
+        match &ast[0].kind {
+            ExprKind::Command(Command::Sqrt {
+                index: None,
+                content,
+            }) => {
+                assert_eq!(content.kind, ExprKind::Literal('x'));
             }
             _ => panic!("Expected Command Sqrt"),
         }
     }
+
+    #[test]
+    fn test_single_token_groups() {
+        let parse = |input| Parser::new(Lexer::new(input)).parse();
+        assert_eq!(parse(r"x^{2}_{\alpha}"), parse(r"x^2_\alpha"));
+        assert_eq!(parse(r"\frac{a}{b}"), parse(r"\frac ab"));
+        assert_eq!(parse(r"{{a}}"), parse(r"{a}"));
+        // groups in running math and groups of more than one token stay
+        assert_ne!(parse(r"{a}"), parse(r"a"));
+        assert_ne!(parse(r"x^{\hat a}"), parse(r"x^\hat a"));
+    }
+
+    #[test]
+    fn test_spans() {
+        use crate::ast::Command;
+
+        let input = r"a + \frac{x}{y_2}^3";
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse();
+        assert_eq!(ast.len(), 3);
+        assert_eq!(&input[ast[0].span.bytes()], "a");
+        assert_eq!(&input[ast[2].span.bytes()], r"\frac{x}{y_2}^3");
+
+        let ExprKind::Superscript(frac, exp) = &ast[2].kind else {
+            panic!("Expected Superscript");
+        };
+        assert_eq!(&input[frac.span.bytes()], r"\frac{x}{y_2}");
+        assert_eq!(&input[exp.span.bytes()], "3");
+
+        let ExprKind::Command(Command::Frac { numer, denom }) = &frac.kind else {
+            panic!("Expected Command Frac");
+        };
+        assert_eq!(&input[numer.span.bytes()], "x");
+        assert_eq!(&input[denom.span.bytes()], "{y_2}");
+    }
+
+    #[test]
+    fn test_missing_argument_span() {
+        let input = r"x \sqrt";
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse();
+        let ExprKind::Command(cmd) = &ast[1].kind else {
+            panic!("Expected Command");
+        };
+        let arg = cmd.args()[0];
+        assert_eq!(arg.kind, ExprKind::Group(vec![]));
+        assert_eq!(arg.span.bytes(), 7..7);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let input = r"{a} } {x^} {\frac{b}";
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse();
+        assert_eq!(ast.len(), 3);
+
+        let diagnostics = parser.take_diagnostics();
+        let kinds: Vec<_> = diagnostics.iter().map(|d| d.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::UnmatchedBrace,
+                DiagnosticKind::MissingArgument("^".to_string()),
+                DiagnosticKind::MissingArgument(r"\frac".to_string()),
+                DiagnosticKind::UnclosedBrace,
+            ]
+        );
+        assert_eq!(&input[diagnostics[0].span.bytes()], "}");
+        assert_eq!(&input[diagnostics[1].span.bytes()], "^");
+        assert_eq!(&input[diagnostics[2].span.bytes()], r"\frac{b}");
+        assert_eq!(diagnostics[3].span.bytes(), 11..12);
+    }
+
+    #[test]
+    fn test_optional_argument() {
+        use crate::ast::Command;
+
+        let input = r"\sqrt[n+1]{x} [y]";
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let ast = parser.parse();
+        assert_eq!(ast.len(), 4);
+        match &ast[0].kind {
+            ExprKind::Command(Command::Sqrt {
+                index: Some(index),
+                content,
+            }) => {
+                assert_eq!(&input[index.span.bytes()], "[n+1]");
+                let ExprKind::Group(index) = &index.kind else {
+                    panic!("Expected Group");
+                };
+                assert_eq!(index.len(), 3);
+                assert_eq!(content.kind, ExprKind::Literal('x'));
+            }
+            _ => panic!("Expected Command Sqrt with index"),
+        }
+        // brackets without a command taking them stay literal
+        assert_eq!(ast[1].kind, ExprKind::Literal('['));
+        assert!(parser.take_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_text_mode() {
+        use crate::ast::Command;
+        let input = r"\text{if  {a} x^2 } y z";
+        let mut parser = Parser::new(Lexer::new(input));
+        let ast = parser.parse();
+        assert_eq!(ast.len(), 3);
+        match &ast[0].kind {
+            ExprKind::Command(Command::Text { content }) => {
+                let ExprKind::Group(content) = &content.kind else {
+                    panic!("Expected Group");
+                };
+                let literal = |c| Expr::from(ExprKind::Literal(c));
+                let expected = vec![
+                    literal('i'),
+                    literal('f'),
+                    literal(' '),
+                    ExprKind::Group(vec![literal('a')]).into(),
+                    literal(' '),
+                    literal('x'),
+                    literal('^'),
+                    literal('2'),
+                    literal(' '),
+                ];
+                assert_eq!(content, &expected);
+            }
+            _ => panic!("Expected Command Text"),
+        }
+        // math mode resumes after the closing brace
+        assert_eq!(ast[1].kind, ExprKind::Literal('y'));
+        assert_eq!(ast[2].kind, ExprKind::Literal('z'));
+    }
+
+    #[test]
+    fn test_spaces_around_skipped_token() {
+        let input = r"\text{\begin{matrix}a } b\end{matrix}}";
+        let mut parser = Parser::new(Lexer::new(input));
+        let ast = parser.parse();
+        let ExprKind::Command(Command::Text { content }) = &ast[0].kind else {
+            panic!("Expected Command Text");
+        };
+        let ExprKind::Group(content) = &content.kind else {
+            panic!("Expected Group");
+        };
+        let ExprKind::Environment(env) = &content[0].kind else {
+            panic!("Expected Environment");
+        };
+        let literal = |c| Expr::from(ExprKind::Literal(c));
+        assert_eq!(
+            env.rows[0][0].kind,
+            ExprKind::Group(vec![literal('a'), literal(' '), literal('b')])
+        );
+    }
+
+    #[test]
+    fn test_environment() {
+        let input = r"\begin{pmatrix} a & b^2 \\ c & \end{pmatrix} x";
+        let mut parser = Parser::new(Lexer::new(input));
+        let ast = parser.parse();
+        assert_eq!(ast.len(), 2);
+        let ExprKind::Environment(env) = &ast[0].kind else {
+            panic!("Expected Environment");
+        };
+        assert_eq!(env.name, "pmatrix");
+        assert_eq!(env.columns, None);
+        let shape: Vec<_> = env.rows.iter().map(Vec::len).collect();
+        assert_eq!(shape, [2, 2]);
+        assert_eq!(&input[env.rows[0][1].span.bytes()], "b^2");
+        assert_eq!(env.rows[1][1].kind, ExprKind::Group(vec![]));
+        assert_eq!(ast[1].kind, ExprKind::Literal('x'));
+        assert!(parser.take_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_environment_details() {
+        let input = r"\begin{array}{c|l} 1 & 2 \\[2pt] 3 & 4 \\ \end{array}";
+        let mut parser = Parser::new(Lexer::new(input));
+        let ast = parser.parse();
+        let ExprKind::Environment(env) = &ast[0].kind else {
+            panic!("Expected Environment");
+        };
+        assert_eq!(env.columns.as_deref(), Some("c|l"));
+        assert_eq!(env.rows.len(), 2);
+
+        // braces and text keep `&` and `\\` from the cells around them
+        let input = r"\begin{matrix} {a & b} \text{c \\ d} \end{matrix}";
+        let ast = Parser::new(Lexer::new(input)).parse();
+        let ExprKind::Environment(env) = &ast[0].kind else {
+            panic!("Expected Environment");
+        };
+        assert_eq!(env.rows.len(), 1);
+        assert_eq!(env.rows[0].len(), 1);
+
+        let mut parser = Parser::new(Lexer::new(r"a & b \\ \begin{cases} x"));
+        let ast = parser.parse();
+        assert_eq!(ast[1].kind, ExprKind::Literal('&'));
+        let kinds: Vec<_> = parser
+            .take_diagnostics()
+            .into_iter()
+            .map(|d| d.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [DiagnosticKind::UnclosedEnvironment("cases".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_delimited() {
+        let input = r"\left\langle a \middle| b^2 \right\rangle x";
+        let mut parser = Parser::new(Lexer::new(input));
+        let ast = parser.parse();
+        assert_eq!(ast.len(), 2);
+        let ExprKind::Delimited(delimited) = &ast[0].kind else {
+            panic!("Expected Delimited");
+        };
+        assert_eq!(delimited.open, r"\langle");
+        assert_eq!(delimited.middles, ["|"]);
+        assert_eq!(delimited.close, r"\rangle");
+        assert_eq!(&input[delimited.parts[1].span.bytes()], "b^2");
+        assert!(parser.take_diagnostics().is_empty());
+
+        let mut parser = Parser::new(Lexer::new(r"\bigl( a \Bigr) \left. b \right) \left( c"));
+        let ast = parser.parse();
+        assert_eq!(ast[0].kind, ExprKind::Literal('('));
+        assert_eq!(ast[2].kind, ExprKind::Literal(')'));
+        let kinds: Vec<_> = parser
+            .take_diagnostics()
+            .into_iter()
+            .map(|d| d.kind)
+            .collect();
+        assert_eq!(kinds, [DiagnosticKind::UnclosedDelimiter]);
+
+        let mut parser = Parser::new(Lexer::new(r"a \right)"));
+        let ast = parser.parse();
+        assert_eq!(ast[1].kind, ExprKind::Literal(')'));
+        let kinds: Vec<_> = parser
+            .take_diagnostics()
+            .into_iter()
+            .map(|d| d.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [DiagnosticKind::UnmatchedDelimiter(r"\right".to_string())]
+        );
+    }
 }
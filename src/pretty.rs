@@ -0,0 +1,532 @@
+use crate::Options;
+use crate::ast::{Command, Delimited, Environment, Expr, ExprKind};
+use crate::diagnostics::Diagnostic;
+use crate::renderer::{Renderer, UnicodeRenderer, to_subscript, to_superscript};
+use crate::token::Span;
+
+// Lays expressions out on a grid of characters, with stacked fractions, roots
+// with an overbar and limits above and below big operators. Everything else
+// is rendered like `UnicodeRenderer` does. The output is meant for monospace fonts.
+#[derive(Default)]
+pub struct PrettyRenderer {
+    inline: UnicodeRenderer,
+}
+
+impl PrettyRenderer {
+    pub fn new() -> Self {
+        PrettyRenderer::default()
+    }
+
+    pub fn with_options(options: &Options) -> Self {
+        PrettyRenderer {
+            inline: UnicodeRenderer::with_options(options),
+        }
+    }
+}
+
+impl Renderer for PrettyRenderer {
+    fn render(&self, exprs: &[Expr]) -> String {
+        self.sequence(exprs).to_string()
+    }
+
+    fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        self.inline.take_diagnostics()
+    }
+}
+
+impl PrettyRenderer {
+    fn sequence(&self, exprs: &[Expr]) -> Block {
+        let mut blocks = Vec::new();
+        let mut previous = None;
+        for (expr, atom) in exprs.iter().zip(self.inline.classify(exprs)) {
+            if let (Some(left), Some(right)) = (previous, atom) {
+                let space = self.inline.space(left, right);
+                if !space.is_empty() {
+                    blocks.push(Block::text(space));
+                }
+            }
+            blocks.push(self.block(expr));
+            previous = atom.or(previous);
+        }
+        Block::row(blocks)
+    }
+
+    fn block(&self, expr: &Expr) -> Block {
+        match &expr.kind {
+            ExprKind::Group(exprs) => self.sequence(exprs),
+            ExprKind::Command(Command::Frac { numer, denom }) => self.fraction(numer, denom),
+            ExprKind::Command(Command::Sqrt { index, content }) => {
+                self.sqrt(index.as_deref(), content)
+            }
+            ExprKind::Superscript(..) | ExprKind::Subscript(..) => self.scripts(expr),
+            ExprKind::Environment(env) => {
+                self.inline.check_environment(env, expr.span);
+                self.environment(env)
+            }
+            ExprKind::Delimited(delimited) => self.delimited(delimited, expr.span),
+            _ => Block::text(&self.inline.render_expr(expr)),
+        }
+    }
+
+    fn fraction(&self, numer: &Expr, denom: &Expr) -> Block {
+        let numer = self.block(numer);
+        let denom = self.block(denom);
+        // one column of room on both sides keeps adjacent fractions apart
+        let bar = Block::text(&"─".repeat(numer.width().max(denom.width()) + 2));
+        let baseline = numer.height();
+        Block::stack(vec![numer, bar, denom], baseline)
+    }
+
+    // The overbar runs along the top, the root sign sits at the bottom left.
+    fn sqrt(&self, index: Option<&Expr>, content: &Expr) -> Block {
+        let radical = self.inline.radical(index);
+        let content = self.block(content);
+        let indent = " ".repeat(width(&radical) - 1);
+
+        let mut lines = vec![format!("{} {}", indent, "_".repeat(content.width()))];
+        let last = content.height() - 1;
+        for (i, line) in content.lines.iter().enumerate() {
+            if i == last {
+                lines.push(format!("{}{}", radical, line));
+            } else {
+                lines.push(format!("{}│{}", indent, line));
+            }
+        }
+        Block {
+            lines,
+            baseline: content.baseline + 1,
+        }
+    }
+
+    // `base^sup_sub` with the scripts in either order.
+    fn scripts(&self, expr: &Expr) -> Block {
+        let (base, sup, sub) = match &expr.kind {
+            ExprKind::Superscript(inner, sup) => match &inner.kind {
+                ExprKind::Subscript(base, sub) => (&**base, Some(&**sup), Some(&**sub)),
+                _ => (&**inner, Some(&**sup), None),
+            },
+            ExprKind::Subscript(inner, sub) => match &inner.kind {
+                ExprKind::Superscript(base, sup) => (&**base, Some(&**sup), Some(&**sub)),
+                _ => (&**inner, None, Some(&**sub)),
+            },
+            _ => return self.block(expr),
+        };
+
+        let base_block = self.block(base);
+        let sup = sup.map(|e| self.inline.in_script(|| self.block(e)));
+        let sub = sub.map(|e| self.inline.in_script(|| self.block(e)));
+
+        if has_limits(base) {
+            let baseline = sup.as_ref().map_or(0, Block::height) + base_block.baseline;
+            let blocks = sup.into_iter().chain([base_block]).chain(sub).collect();
+            return Block::stack(blocks, baseline);
+        }
+
+        // scripts with a Unicode form stay on the line, like inline
+        let sup_text = sup
+            .as_ref()
+            .map(|b| b.single_line().and_then(to_superscript));
+        let sub_text = sub.as_ref().map(|b| b.single_line().and_then(to_subscript));
+        if !matches!(sup_text, Some(None)) && !matches!(sub_text, Some(None)) {
+            let scripts: String = sup_text
+                .flatten()
+                .into_iter()
+                .chain(sub_text.flatten())
+                .collect();
+            return Block::row(vec![base_block, Block::text(&scripts)]);
+        }
+
+        // otherwise they go on the lines above and below the base
+        let sup_height = sup.as_ref().map_or(0, Block::height);
+        let mut lines: Vec<String> = sup.map(|b| b.lines).unwrap_or_default();
+        lines.extend((0..base_block.height()).map(|_| String::new()));
+        lines.extend(sub.map(|b| b.lines).unwrap_or_default());
+        let scripts = Block {
+            lines,
+            baseline: sup_height + base_block.baseline,
+        };
+        Block::row(vec![base_block, scripts])
+    }
+}
+
+impl PrettyRenderer {
+    // The cells on a grid with aligned columns, between the environment's
+    // delimiters.
+    fn environment(&self, env: &Environment) -> Block {
+        let aligned = env.name == "aligned";
+        let rows: Vec<Vec<Block>> = env
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .enumerate()
+                    .map(|(i, cell)| {
+                        let block = self.block(cell);
+                        let space = if aligned && i > 0 {
+                            self.inline.cell_space(&row[i - 1], cell)
+                        } else {
+                            ""
+                        };
+                        if space.is_empty() {
+                            block
+                        } else {
+                            Block::row(vec![Block::text(space), block])
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|j| {
+                rows.iter()
+                    .filter_map(|row| row.get(j))
+                    .map(Block::width)
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let align = |j: usize| match env.name.as_str() {
+            // `rl` pairs around the alignment points
+            "aligned" if j.is_multiple_of(2) => Align::Right,
+            "aligned" | "cases" => Align::Left,
+            "array" => match env
+                .columns
+                .iter()
+                .flat_map(|c| c.chars())
+                .filter(|c| c.is_alphabetic())
+                .nth(j)
+            {
+                Some('l') => Align::Left,
+                Some('r') => Align::Right,
+                _ => Align::Center,
+            },
+            _ => Align::Center,
+        };
+        let gap = if aligned { "" } else { "  " };
+
+        let lines: Vec<Block> = rows
+            .into_iter()
+            .map(|row| {
+                let mut blocks = Vec::new();
+                for (j, width) in widths.iter().enumerate() {
+                    if j > 0 {
+                        blocks.push(Block::text(gap));
+                    }
+                    let cell = row.get(j).cloned().unwrap_or_else(|| Block::text(""));
+                    blocks.push(cell.align(*width, align(j)));
+                }
+                Block::row(blocks)
+            })
+            .collect();
+        let height: usize = lines.iter().map(Block::height).sum();
+        let grid = Block::stack(lines, height.saturating_sub(1) / 2);
+
+        let (open, close) = match env.name.as_str() {
+            "pmatrix" => (Some("("), Some(")")),
+            "bmatrix" => (Some("["), Some("]")),
+            "Bmatrix" => (Some("{"), Some("}")),
+            "vmatrix" => (Some("|"), Some("|")),
+            "Vmatrix" => (Some("‖"), Some("‖")),
+            "cases" => (Some("{"), None),
+            _ => (None, None),
+        };
+        let (height, baseline) = (grid.height(), grid.baseline);
+        let mut blocks = Vec::new();
+        if let Some(open) = open {
+            blocks.push(delimiter(open, height, baseline));
+        }
+        if env.name == "cases" {
+            blocks.push(Block::text(" "));
+        }
+        blocks.push(grid);
+        if let Some(close) = close {
+            blocks.push(delimiter(close, height, baseline));
+        }
+        Block::row(blocks)
+    }
+}
+
+impl PrettyRenderer {
+    // The delimiters grow to the height of everything between them.
+    fn delimited(&self, delimited: &Delimited, span: Span) -> Block {
+        let parts: Vec<Block> = delimited.parts.iter().map(|p| self.block(p)).collect();
+        let content = Block::row(parts.clone());
+        let (height, baseline) = (content.height(), content.baseline);
+        let stretch = |d: &str| delimiter(&self.inline.delimiter(d, span), height, baseline);
+
+        let mut blocks = vec![stretch(&delimited.open)];
+        for (i, part) in parts.into_iter().enumerate() {
+            if let Some(middle) = i.checked_sub(1).and_then(|i| delimited.middles.get(i)) {
+                blocks.push(stretch(middle));
+            }
+            blocks.push(part);
+        }
+        blocks.push(stretch(&delimited.close));
+        Block::row(blocks)
+    }
+}
+
+// `delimiter` stretched over `height` lines, built from the pieces Unicode
+// has for tall brackets. Others only go on the baseline.
+fn delimiter(delimiter: &str, height: usize, baseline: usize) -> Block {
+    let mut chars = delimiter.chars();
+    let pieces = match (chars.next(), chars.next()) {
+        (Some(c), None) if height > 1 => match c {
+            '(' => Some(('⎛', '⎜', '⎝', '⎜')),
+            ')' => Some(('⎞', '⎟', '⎠', '⎟')),
+            '[' => Some(('⎡', '⎢', '⎣', '⎢')),
+            ']' => Some(('⎤', '⎥', '⎦', '⎥')),
+            '{' if height == 2 => Some(('⎰', '⎰', '⎱', '⎰')),
+            '}' if height == 2 => Some(('⎱', '⎱', '⎰', '⎱')),
+            '{' => Some(('⎧', '⎨', '⎩', '⎪')),
+            '}' => Some(('⎫', '⎬', '⎭', '⎪')),
+            '⌈' => Some(('⎡', '⎢', '⎢', '⎢')),
+            '⌉' => Some(('⎤', '⎥', '⎥', '⎥')),
+            '⌊' => Some(('⎢', '⎢', '⎣', '⎢')),
+            '⌋' => Some(('⎥', '⎥', '⎦', '⎥')),
+            '|' => Some(('│', '│', '│', '│')),
+            '‖' => Some(('‖', '‖', '‖', '‖')),
+            _ => None,
+        },
+        _ => None,
+    };
+    let Some((top, middle, bottom, extension)) = pieces else {
+        let mut lines = vec![String::new(); height];
+        lines[baseline] = delimiter.to_string();
+        return Block { lines, baseline };
+    };
+
+    let lines = (0..height)
+        .map(|i| {
+            let piece = if i == 0 {
+                top
+            } else if i == height - 1 {
+                bottom
+            } else if i == (height - 1) / 2 {
+                middle
+            } else {
+                extension
+            };
+            piece.to_string()
+        })
+        .collect();
+    Block { lines, baseline }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+// Operators whose scripts TeX sets above and below in display style.
+pub(crate) fn has_limits(expr: &Expr) -> bool {
+    matches!(
+        &expr.kind,
+        ExprKind::Command(Command::Symbol { name })
+            if matches!(name.as_str(), "sum" | "prod" | "lim" | "min" | "max" | "sup" | "inf")
+    )
+}
+
+// A rectangle of text lines, one of which is the baseline that lines up with
+// the baseline of its neighbours.
+#[derive(Debug, Clone)]
+struct Block {
+    lines: Vec<String>,
+    baseline: usize,
+}
+
+impl Block {
+    fn text(s: &str) -> Block {
+        Block {
+            lines: vec![s.to_string()],
+            baseline: 0,
+        }
+    }
+
+    fn height(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn width(&self) -> usize {
+        self.lines.iter().map(|l| width(l)).max().unwrap_or(0)
+    }
+
+    // The block moved right within a column of `width`.
+    fn align(self, width: usize, align: Align) -> Block {
+        let extra = width.saturating_sub(self.width());
+        let indent = " ".repeat(match align {
+            Align::Left => 0,
+            Align::Center => extra / 2,
+            Align::Right => extra,
+        });
+        Block {
+            lines: self
+                .lines
+                .iter()
+                .map(|l| pad(&format!("{}{}", indent, l), width))
+                .collect(),
+            baseline: self.baseline,
+        }
+    }
+
+    fn single_line(&self) -> Option<&str> {
+        match self.lines.as_slice() {
+            [line] => Some(line),
+            _ => None,
+        }
+    }
+
+    // Blocks side by side, aligned on their baselines.
+    fn row(blocks: Vec<Block>) -> Block {
+        let above = blocks.iter().map(|b| b.baseline).max().unwrap_or(0);
+        let below = blocks
+            .iter()
+            .map(|b| b.height() - b.baseline - 1)
+            .max()
+            .unwrap_or(0);
+
+        let lines = (0..above + below + 1)
+            .map(|row| {
+                let mut line = String::new();
+                for block in &blocks {
+                    let text = (row + block.baseline)
+                        .checked_sub(above)
+                        .and_then(|i| block.lines.get(i))
+                        .map_or("", String::as_str);
+                    line += &pad(text, block.width());
+                }
+                line
+            })
+            .collect();
+        Block {
+            lines,
+            baseline: above,
+        }
+    }
+
+    // Blocks on top of each other, centered, with the given line as baseline.
+    fn stack(blocks: Vec<Block>, baseline: usize) -> Block {
+        let width = blocks.iter().map(Block::width).max().unwrap_or(0);
+        let lines = blocks
+            .iter()
+            .flat_map(|block| {
+                let indent = " ".repeat((width - block.width()) / 2);
+                block.lines.iter().map(move |l| format!("{}{}", indent, l))
+            })
+            .collect();
+        Block { lines, baseline }
+    }
+}
+
+impl std::fmt::Display for Block {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let lines: Vec<_> = self.lines.iter().map(|l| l.trim_end()).collect();
+        f.write_str(&lines.join("\n"))
+    }
+}
+
+// Columns taken by `s` in a monospace font. Combining accents take none.
+fn width(s: &str) -> usize {
+    s.chars()
+        .filter(|c| !matches!(c, '\u{0300}'..='\u{036F}' | '\u{20D0}'..='\u{20FF}'))
+        .count()
+}
+
+fn pad(s: &str, to: usize) -> String {
+    format!("{}{}", s, " ".repeat(to.saturating_sub(width(s))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn render(input: &str) -> String {
+        let mut parser = Parser::new(Lexer::new(input));
+        PrettyRenderer::new().render(&parser.parse())
+    }
+
+    #[test]
+    fn test_fraction() {
+        assert_eq!(render(r"\frac{1}{2}"), " 1\n───\n 2");
+        assert_eq!(render(r"x=\frac{a+b}{2}+1"), "   a+b\nx=─────+1\n    2");
+        assert_eq!(
+            render(r"\frac{\frac{1}{2}}{3}"),
+            "  1\n ───\n  2\n─────\n  3"
+        );
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(render(r"\sqrt{x+1}"), " ___\n√x+1");
+        assert_eq!(render(r"\sqrt[3]{x}"), " _\n∛x");
+        assert_eq!(render(r"\sqrt{\frac{a}{b}}"), " ___\n│ a\n│───\n√ b");
+    }
+
+    #[test]
+    fn test_limits() {
+        assert_eq!(render(r"\sum_{i=1}^{n} i"), " n\n ∑ i\ni=1");
+        assert_eq!(render(r"\lim_{x \to 0} f"), "lim f\nx→0");
+    }
+
+    #[test]
+    fn test_scripts() {
+        assert_eq!(render("x^2_i"), "x²ᵢ");
+        assert_eq!(render("e^{q}"), " q\ne");
+        assert_eq!(render(r"e^{\frac{1}{2}}"), "  1\n ───\n  2\ne");
+        assert_eq!(render("x_{q}^{2}"), " 2\nx\n q");
+    }
+
+    #[test]
+    fn test_environments() {
+        assert_eq!(
+            render(r"\begin{pmatrix} a & bb \\ ccc & d \end{pmatrix}"),
+            "⎛ a   bb⎞\n⎝ccc  d ⎠"
+        );
+        assert_eq!(
+            render(r"A=\begin{bmatrix} 1 & 0 & 0 \\ 0 & 1 & 0 \\ 0 & 0 & 1 \end{bmatrix}"),
+            "  ⎡1  0  0⎤\nA=⎢0  1  0⎥\n  ⎣0  0  1⎦"
+        );
+        assert_eq!(
+            render(r"\begin{cases} 1 & x > 0 \\ 0 & \text{otherwise} \end{cases}"),
+            "⎰ 1  x>0\n⎱ 0  otherwise"
+        );
+        assert_eq!(
+            render(r"\begin{aligned} a &= b + c \\ a + b &= d \end{aligned}"),
+            "  a=b+c\na+b=d"
+        );
+        assert_eq!(
+            render(r"\begin{array}{lr} 1 & 22 \\ 333 & 4 \end{array}"),
+            "1    22\n333   4"
+        );
+    }
+
+    #[test]
+    fn test_delimited() {
+        assert_eq!(
+            render(r"\left( \frac{a}{b} \right)^2"),
+            "⎛ a ⎞\n⎜───⎟²\n⎝ b ⎠"
+        );
+        assert_eq!(
+            render(r"\left\lfloor \frac{n}{2} \middle| x \right\rfloor"),
+            "⎢ n │ ⎥\n⎢───│x⎥\n⎣ 2 │ ⎦"
+        );
+        assert_eq!(
+            render(r"\left\langle \frac{1}{x} \right."),
+            "  1\n⟨───\n  x"
+        );
+    }
+
+    #[test]
+    fn test_lossless() {
+        let mut parser = Parser::new(Lexer::new("x^{q} + y_{w}"));
+        let renderer = PrettyRenderer::new();
+        renderer.render(&parser.parse());
+        assert!(renderer.take_diagnostics().is_empty());
+    }
+}
@@ -1,53 +1,424 @@
-use crate::ast::{Command, Expr};
+use crate::Options;
+use crate::ast::{Command, Delimited, Environment, Expr, ExprKind};
+use crate::diagnostics::{Diagnostic, DiagnosticKind};
+use crate::symbols::{Symbol, SymbolTable};
+use crate::token::Span;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::str::FromStr;
 
+// Turns parsed math into one output format.
+pub trait Renderer {
+    fn render(&self, exprs: &[Expr]) -> String;
+
+    // Lossy conversions made by `render` since the last call, such as unknown
+    // commands or characters without a superscript form.
+    fn take_diagnostics(&self) -> Vec<Diagnostic>;
+}
+
+// Plain Unicode text on a single line.
 #[derive(Default)]
-pub struct Renderer;
+pub struct UnicodeRenderer {
+    diagnostics: RefCell<Vec<Diagnostic>>,
+    spacing: Spacing,
+    fractions: Fractions,
+    symbols: SymbolTable,
+    // how many superscripts or subscripts deep the expression being rendered is
+    script_depth: Cell<usize>,
+}
+
+// TeX's classification of math atoms, which decides the space between them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AtomClass {
+    Ord,
+    Op,
+    Bin,
+    Rel,
+    Open,
+    Close,
+    Punct,
+}
+
+// How much of TeX's spacing between atoms ends up in the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Spacing {
+    // no spaces, except between a named function and its argument
+    #[default]
+    Compact,
+    // plain spaces around relations and binary operators and after punctuation
+    Readable,
+    // TeX's thin, medium and thick spaces as the matching Unicode spaces
+    TexLike,
+}
+
+impl FromStr for Spacing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compact" => Ok(Spacing::Compact),
+            "readable" => Ok(Spacing::Readable),
+            "tex-like" => Ok(Spacing::TexLike),
+            _ => Err(format!(
+                "unknown spacing `{}` (expected compact, readable or tex-like)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Spacing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Spacing::Compact => "compact",
+            Spacing::Readable => "readable",
+            Spacing::TexLike => "tex-like",
+        })
+    }
+}
+
+// How `\frac` is written. Each strategy falls back to the next one when the
+// fraction has no such form, ending with `a/b`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Fractions {
+    // precomposed characters like `½` and `⅞`
+    #[default]
+    Vulgar,
+    // superscript and subscript digits around a fraction slash, like `¹²⁄₇`
+    Slash,
+    // `a/b`, with parentheses around anything but a single symbol or number
+    Linear,
+}
+
+impl FromStr for Fractions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vulgar" => Ok(Fractions::Vulgar),
+            "slash" => Ok(Fractions::Slash),
+            "linear" => Ok(Fractions::Linear),
+            _ => Err(format!(
+                "unknown fraction style `{}` (expected vulgar, slash or linear)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Fractions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Fractions::Vulgar => "vulgar",
+            Fractions::Slash => "slash",
+            Fractions::Linear => "linear",
+        })
+    }
+}
+
+impl Renderer for UnicodeRenderer {
+    fn render(&self, exprs: &[Expr]) -> String {
+        let atoms = self.classify(exprs);
+        let mut result = String::new();
+        let mut previous: Option<(AtomClass, bool)> = None;
+        for (i, (expr, &atom)) in exprs.iter().zip(&atoms).enumerate() {
+            if let (Some(left), Some(right)) = (previous, atom) {
+                result += self.space(left, right);
+            }
+            let item = self.render_expr(expr);
+            if is_next_to_factor(&atoms[i + 1..], &result) {
+                result += &self.parenthesize_fraction(expr, item);
+            } else {
+                result += &item;
+            }
+            // explicit spaces like `\,` don't take part in the spacing
+            previous = atom.or(previous);
+        }
+        result
+    }
+
+    fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.take()
+    }
+}
+
+// The space TeX puts between two adjacent atoms.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Gap {
+    None,
+    Thin,
+    Medium,
+    Thick,
+}
 
-impl Renderer {
+impl UnicodeRenderer {
     pub fn new() -> Self {
-        Renderer
+        UnicodeRenderer::default()
     }
 
-    pub fn render(&self, exprs: &[Expr]) -> String {
-        exprs.iter().map(|e| self.render_expr(e)).collect()
+    pub fn with_options(options: &Options) -> Self {
+        UnicodeRenderer {
+            spacing: options.spacing,
+            fractions: options.fractions,
+            symbols: options.symbols.clone(),
+            ..UnicodeRenderer::default()
+        }
     }
 
-    fn render_expr(&self, expr: &Expr) -> String {
-        match expr {
-            Expr::Literal(c) => c.to_string(),
-            Expr::Command(cmd) => self.render_command(cmd),
-            Expr::Group(group_exprs) => self.render(group_exprs),
-            Expr::Superscript(base, exp) => {
-                let base_str = self.render_expr(base);
-                let exp_str = self.render_expr(exp);
-                if let Some(sup_str) = to_superscript(&exp_str) {
-                    format!("{}{}", base_str, sup_str)
-                } else {
-                    format!("{}^{{{}}}", base_str, exp_str)
-                }
+    pub(crate) fn report(&self, kind: DiagnosticKind, span: Span) {
+        self.diagnostics
+            .borrow_mut()
+            .push(Diagnostic { kind, span });
+    }
+
+    // The space between two atoms, each given with whether it is a named function.
+    pub(crate) fn space(
+        &self,
+        (left, left_named): (AtomClass, bool),
+        (right, right_named): (AtomClass, bool),
+    ) -> &'static str {
+        // scripts are made of superscript or subscript characters, which have no spaces
+        if self.script_depth.get() > 0 {
+            return "";
+        }
+        match (self.spacing, gap(left, right)) {
+            (_, Gap::None) => "",
+            (Spacing::TexLike, Gap::Thin) => "\u{2009}",
+            (Spacing::TexLike, Gap::Medium) => "\u{205F}",
+            (Spacing::TexLike, Gap::Thick) => "\u{2005}",
+            (_, Gap::Thin) if left_named || right_named => " ",
+            (Spacing::Readable, Gap::Thin) if left == AtomClass::Punct => " ",
+            (Spacing::Readable, Gap::Medium | Gap::Thick) => " ",
+            _ => "",
+        }
+    }
+
+    // Renders a superscript, subscript or other label set in script size.
+    pub(crate) fn render_script(&self, expr: &Expr) -> String {
+        self.in_script(|| self.render_expr(expr))
+    }
+
+    // Whether TeX sets the atoms apart with a thick space, as it does around
+    // relations and arrows outside scripts.
+    pub(crate) fn is_thick_space(&self, left: AtomClass, right: AtomClass) -> bool {
+        self.script_depth.get() == 0 && matches!(gap(left, right), Gap::Thick)
+    }
+
+    // Runs `f` with the spacing of scripts.
+    pub(crate) fn in_script<T>(&self, f: impl FnOnce() -> T) -> T {
+        self.script_depth.set(self.script_depth.get() + 1);
+        let result = f();
+        self.script_depth.set(self.script_depth.get() - 1);
+        result
+    }
+
+    // The argument of a text command, whose spaces are already in the input.
+    fn render_text(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Group(exprs) => exprs.iter().map(|e| self.render_text(e)).collect(),
+            _ => self.render_expr(expr),
+        }
+    }
+
+    pub(crate) fn render_expr(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Literal(c) => c.to_string(),
+            ExprKind::Command(Command::Symbol { name }) if !self.is_known_symbol(name) => {
+                self.report(DiagnosticKind::UnknownCommand(name.clone()), expr.span);
+                self.render_symbol(name)
+            }
+            ExprKind::Command(cmd) => self.render_command(cmd),
+            ExprKind::Group(group_exprs) => self.render(group_exprs),
+            ExprKind::Superscript(base, exp) => format!(
+                "{}{}",
+                self.parenthesize_fraction(base, self.render_expr(base)),
+                self.render_superscript(exp)
+            ),
+            ExprKind::Subscript(base, sub) => format!(
+                "{}{}",
+                self.parenthesize_fraction(base, self.render_expr(base)),
+                self.render_subscript(sub)
+            ),
+            ExprKind::Environment(env) => self.render_environment(env, expr.span),
+            ExprKind::Delimited(delimited) => self.render_delimited(delimited, expr.span),
+        }
+    }
+
+    fn render_delimited(&self, delimited: &Delimited, span: Span) -> String {
+        let mut result = self.delimiter(&delimited.open, span);
+        for (i, part) in delimited.parts.iter().enumerate() {
+            if let Some(middle) = i.checked_sub(1).and_then(|i| delimited.middles.get(i)) {
+                result += &self.delimiter(middle, span);
             }
-            Expr::Subscript(base, sub) => {
-                let base_str = self.render_expr(base);
-                let sub_str = self.render_expr(sub);
-                if let Some(sub_chars) = to_subscript(&sub_str) {
-                    format!("{}{}", base_str, sub_chars)
-                } else {
-                    format!("{}_{{{}}}", base_str, sub_str)
+            result += &self.render_expr(part);
+        }
+        result + &self.delimiter(&delimited.close, span)
+    }
+
+    // A delimiter of `\left`, `\middle` or `\right`, which may be a symbol
+    // command like `\langle`.
+    pub(crate) fn delimiter(&self, delimiter: &str, span: Span) -> String {
+        match delimiter {
+            "." => String::new(),
+            "<" => "⟨".to_string(),
+            ">" => "⟩".to_string(),
+            _ => match delimiter.strip_prefix('\\') {
+                Some(name) => {
+                    if !self.is_known_symbol(name) {
+                        self.report(DiagnosticKind::UnknownCommand(name.to_string()), span);
+                    }
+                    self.render_symbol(name)
                 }
+                None => delimiter.to_string(),
+            },
+        }
+    }
+
+    // Matrices as nested lists like `[[a, b], [c, d]]`, cases as
+    // `{a, x>0; b, x≤0}` and aligned equations one per line.
+    fn render_environment(&self, env: &Environment, span: Span) -> String {
+        self.check_environment(env, span);
+        linear_environment(self, env, |cell| self.render_expr(cell), "‖")
+    }
+
+    pub(crate) fn check_environment(&self, env: &Environment, span: Span) {
+        if !matches!(
+            env.name.as_str(),
+            "matrix"
+                | "pmatrix"
+                | "bmatrix"
+                | "Bmatrix"
+                | "vmatrix"
+                | "Vmatrix"
+                | "cases"
+                | "array"
+                | "aligned"
+        ) {
+            self.report(DiagnosticKind::UnknownEnvironment(env.name.clone()), span);
+        }
+    }
+
+    // The space between two neighbouring cells of an `aligned` row, as if the
+    // `&` between them wasn't there.
+    pub(crate) fn cell_space(&self, left: &Expr, right: &Expr) -> &'static str {
+        let contents = |cell: &Expr| match &cell.kind {
+            ExprKind::Group(exprs) => self.classify(exprs),
+            _ => self.classify(std::slice::from_ref(cell)),
+        };
+        let last = contents(left).into_iter().flatten().last();
+        let first = contents(right).into_iter().flatten().next();
+        match (last, first) {
+            (Some(last), Some(first)) => self.space(last, first),
+            _ => "",
+        }
+    }
+
+    fn render_superscript(&self, exp: &Expr) -> String {
+        self.superscript(&self.render_script(exp), exp.span)
+    }
+
+    fn render_subscript(&self, sub: &Expr) -> String {
+        self.subscript(&self.render_script(sub), sub.span)
+    }
+
+    // `exp_str` in superscript letters, or as `^{...}` if that is not possible
+    pub(crate) fn superscript(&self, exp_str: &str, span: Span) -> String {
+        if let Some(sup_str) = to_superscript(exp_str) {
+            sup_str
+        } else {
+            for c in exp_str.chars().filter(|&c| superscript_char(c).is_none()) {
+                self.report(DiagnosticKind::UnrepresentableSuperscript(c), span);
+            }
+            format!("^{{{}}}", exp_str)
+        }
+    }
+
+    pub(crate) fn subscript(&self, sub_str: &str, span: Span) -> String {
+        if let Some(sub_chars) = to_subscript(sub_str) {
+            sub_chars
+        } else {
+            for c in sub_str.chars().filter(|&c| subscript_char(c).is_none()) {
+                self.report(DiagnosticKind::UnrepresentableSubscript(c), span);
+            }
+            format!("_{{{}}}", sub_str)
+        }
+    }
+
+    fn render_frac(&self, numer: &Expr, denom: &Expr) -> String {
+        let numer_str = self.render_expr(numer);
+        let denom_str = self.render_expr(denom);
+
+        if self.fractions == Fractions::Vulgar
+            && let Some(c) = vulgar_fraction(&numer_str, &denom_str)
+        {
+            return c.to_string();
+        }
+        if self.fractions != Fractions::Linear
+            && let (Some(sup), Some(sub)) = (to_superscript(&numer_str), to_subscript(&denom_str))
+        {
+            return format!("{}\u{2044}{}", sup, sub);
+        }
+
+        let parenthesize = |expr: &Expr, s: String| {
+            if self.is_atomic(expr) {
+                s
+            } else {
+                format!("({})", s)
+            }
+        };
+        format!(
+            "{}/{}",
+            parenthesize(numer, numer_str),
+            parenthesize(denom, denom_str)
+        )
+    }
+
+    // `rendered` in parentheses if `expr` is a fraction written with a slash,
+    // for when it is next to another factor or has a script, as `a/b²` would
+    // read as a/(b²). Precomposed fractions like `½` are already one symbol.
+    fn parenthesize_fraction(&self, expr: &Expr, rendered: String) -> String {
+        match &expr.kind {
+            ExprKind::Command(Command::Frac { .. }) if rendered.chars().count() > 1 => {
+                format!("({})", rendered)
             }
+            _ => rendered,
         }
     }
 
+    fn render_sqrt(&self, index: Option<&Expr>, content: &Expr) -> String {
+        format!("{}({})", self.radical(index), self.render_expr(content))
+    }
+
+    // The root sign for `\sqrt[index]`, with the index in superscript.
+    pub(crate) fn radical(&self, index: Option<&Expr>) -> String {
+        match index {
+            None => "√".to_string(),
+            Some(index) => match self.render_script(index).as_str() {
+                "" | "2" => "√".to_string(),
+                "3" => "∛".to_string(),
+                "4" => "∜".to_string(),
+                index_str => format!("{}√", self.superscript(index_str, index.span)),
+            },
+        }
+    }
+
+    // The label above the arrow goes in a superscript, the one below in a subscript.
+    fn render_xarrow(&self, arrow: char, above: &Expr, below: Option<&Expr>) -> String {
+        let mut result = arrow.to_string();
+        let above_str = self.render_script(above);
+        if !above_str.is_empty() {
+            result += &self.superscript(&above_str, above.span);
+        }
+        if let Some(below) = below {
+            result += &self.render_subscript(below);
+        }
+        result
+    }
+
     fn render_command(&self, cmd: &Command) -> String {
         match cmd {
-            Command::Frac { numer, denom } => {
-                format!(
-                    "({})/({})",
-                    self.render_expr(numer),
-                    self.render_expr(denom)
-                )
-            }
-            Command::Sqrt { content } => format!("√({})", self.render_expr(content)),
+            Command::Frac { numer, denom } => self.render_frac(numer, denom),
+            Command::Sqrt { index, content } => self.render_sqrt(index.as_deref(), content),
             Command::Mathcal { content } => apply_style("mathcal", &self.render_expr(content)),
             Command::Mathbb { content } => apply_style("mathbb", &self.render_expr(content)),
             Command::Mathfrak { content } => apply_style("mathfrak", &self.render_expr(content)),
@@ -61,262 +432,401 @@ impl Renderer {
             Command::Dot { content } => format!("{}\u{0307}", self.render_expr(content)),
             Command::Ddot { content } => format!("{}\u{0308}", self.render_expr(content)),
             Command::Tilde { content } => format!("{}\u{0303}", self.render_expr(content)),
+            Command::Text { content }
+            | Command::Textrm { content }
+            | Command::Mbox { content }
+            | Command::Mathrm { content }
+            | Command::Operatorname { content } => self.render_text(content),
+            Command::Xrightarrow { below, above } => {
+                self.render_xarrow('→', above, below.as_deref())
+            }
+            Command::Xleftarrow { below, above } => {
+                self.render_xarrow('←', above, below.as_deref())
+            }
             Command::Symbol { name } => self.render_symbol(name),
+            Command::Custom { name, args } => {
+                let args: Vec<_> = args.iter().map(|arg| self.render_expr(arg)).collect();
+                match self.symbols.get(name) {
+                    Some(symbol) => symbol.expand(&args),
+                    None => format!("\\{}", name),
+                }
+            }
         }
     }
 
     fn render_symbol(&self, name: &str) -> String {
-        match name {
-            "alpha" => "α".to_string(),
-            "beta" => "β".to_string(),
-            "gamma" => "γ".to_string(),
-            "delta" => "δ".to_string(),
-            "epsilon" => "ε".to_string(),
-            "zeta" => "ζ".to_string(),
-            "eta" => "η".to_string(),
-            "theta" => "θ".to_string(),
-            "iota" => "ι".to_string(),
-            "kappa" => "κ".to_string(),
-            "lambda" => "λ".to_string(),
-            "mu" => "μ".to_string(),
-            "nu" => "ν".to_string(),
-            "xi" => "ξ".to_string(),
-            "omicron" => "ο".to_string(),
-            "pi" => "π".to_string(),
-            "rho" => "ρ".to_string(),
-            "sigma" => "σ".to_string(),
-            "tau" => "τ".to_string(),
-            "upsilon" => "υ".to_string(),
-            "phi" => "φ".to_string(),
-            "chi" => "χ".to_string(),
-            "psi" => "ψ".to_string(),
-            "omega" => "ω".to_string(),
-            "Gamma" => "Γ".to_string(),
-            "Delta" => "Δ".to_string(),
-            "Theta" => "Θ".to_string(),
-            "Lambda" => "Λ".to_string(),
-            "Xi" => "Ξ".to_string(),
-            "Pi" => "Π".to_string(),
-            "Sigma" => "Σ".to_string(),
-            "Upsilon" => "Υ".to_string(),
-            "Phi" => "Φ".to_string(),
-            "Psi" => "Ψ".to_string(),
-            "Omega" => "Ω".to_string(),
-            "le" | "leq" => "≤".to_string(),
-            "ge" | "geq" => "≥".to_string(),
-            "ne" | "neq" => "≠".to_string(),
-            "approx" => "≈".to_string(),
-            "equiv" => "≡".to_string(),
-            "sim" => "∼".to_string(),
-            "cong" => "≅".to_string(),
-            "propto" => "∝".to_string(),
-            "pm" => "±".to_string(),
-            "times" => "×".to_string(),
-            "div" => "÷".to_string(),
-            "cdot" => "⋅".to_string(),
-            "in" => "∈".to_string(),
-            "notin" => "∉".to_string(),
-            "subset" => "⊂".to_string(),
-            "subseteq" => "⊆".to_string(),
-            "cup" => "∪".to_string(),
-            "cap" => "∩".to_string(),
-            "setminus" => "∖".to_string(),
-            "emptyset" => "∅".to_string(),
-            "land" | "wedge" => "∧".to_string(),
-            "lor" | "vee" => "∨".to_string(),
-            "neg" | "lnot" => "¬".to_string(),
-            "implies" => "⟹".to_string(),
-            "iff" => "⟺".to_string(),
-            "forall" => "∀".to_string(),
-            "exists" => "∃".to_string(),
-            "rightarrow" | "to" => "→".to_string(),
-            "leftarrow" => "←".to_string(),
-            "Rightarrow" => "⇒".to_string(),
-            "Leftarrow" => "⇐".to_string(),
-            "leftrightarrow" => "↔".to_string(),
-            "Leftrightarrow" => "⇔".to_string(),
-            "mapsto" => "↦".to_string(),
-            "partial" => "∂".to_string(),
-            "nabla" => "∇".to_string(),
-            "sum" => "∑".to_string(),
-            "prod" => "∏".to_string(),
-            "int" => "∫".to_string(),
-            "infty" => "∞".to_string(),
-            "ldots" | "dots" => "…".to_string(),
-            "cdots" => "⋯".to_string(),
-            "vdots" => "⋮".to_string(),
-            "ddots" => "⋱".to_string(),
-            "prime" => "′".to_string(),
-            "degree" => "°".to_string(),
-            "angle" => "∠".to_string(),
-            "triangle" => "△".to_string(),
-            "circ" => "∘".to_string(),
-            "bullet" => "∙".to_string(),
-            "star" => "⋆".to_string(),
-            "ast" => "∗".to_string(),
-            "mid" => "|".to_string(),
-            "parallel" => "∥".to_string(),
-            "perp" => "⊥".to_string(),
-            "dagger" => "†".to_string(),
-            "ddagger" => "‡".to_string(),
-            "ell" => "ℓ".to_string(),
-            "Re" => "ℜ".to_string(),
-            "Im" => "ℑ".to_string(),
-            "aleph" => "ℵ".to_string(),
-            "hbar" => "ℏ".to_string(),
-            "{" => "{".to_string(),
-            "}" => "}".to_string(),
-            "sin" | "cos" | "tan" | "csc" | "sec" | "cot" | "sinh" | "cosh" | "tanh" | "arcsin"
-            | "arccos" | "arctan" | "log" | "ln" | "lim" | "min" | "max" | "sup" | "inf"
-            | "det" | "exp" | "dim" | "ker" | "deg" | "arg" => name.to_string(),
-            "," | ";" | ":" => " ".to_string(),
-            "!" => "".to_string(),
-            "quad" => "  ".to_string(),
-            "qquad" => "    ".to_string(),
-            _ => format!("\\{}", name),
+        match self.symbols.get(name) {
+            Some(symbol) => symbol.output.clone(),
+            None => format!("\\{}", name),
+        }
+    }
+
+    pub fn is_known_symbol(&self, name: &str) -> bool {
+        self.symbols.contains(name)
+    }
+
+    pub(crate) fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+    }
+
+    pub(crate) fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    // `None` for explicit spaces such as `\,` and `\quad`, which are not atoms.
+    fn symbol_class(&self, name: &str) -> Option<AtomClass> {
+        match self.symbols.get(name) {
+            Some(symbol) => symbol.class,
+            None => Some(AtomClass::Ord),
         }
     }
+
+    fn is_named_function(&self, name: &str) -> bool {
+        self.symbols.get(name).is_some_and(|symbol| symbol.named)
+    }
 }
 
-fn to_superscript(s: &str) -> Option<String> {
-    let mut result = String::new();
-    for c in s.chars() {
-        let sup = match c {
-            '0' => '⁰',
-            '1' => '¹',
-            '2' => '²',
-            '3' => '³',
-            '4' => '⁴',
-            '5' => '⁵',
-            '6' => '⁶',
-            '7' => '⁷',
-            '8' => '⁸',
-            '9' => '⁹',
-            '+' => '⁺',
-            '-' => '⁻',
-            '=' => '⁼',
-            '(' => '⁽',
-            ')' => '⁾',
-            ',' => 'ʼ',
-            '.' => '˙',
-            '*' => '*',
-            'a' => 'ᵃ',
-            'b' => 'ᵇ',
-            'c' => 'ᶜ',
-            'd' => 'ᵈ',
-            'e' => 'ᵉ',
-            'f' => 'ᶠ',
-            'g' => 'ᵍ',
-            'h' => 'ʰ',
-            'i' => 'ⁱ',
-            'j' => 'ʲ',
-            'k' => 'ᵏ',
-            'l' => 'ˡ',
-            'm' => 'ᵐ',
-            'n' => 'ⁿ',
-            'o' => 'ᵒ',
-            'p' => 'ᵖ',
-            'r' => 'ʳ',
-            's' => 'ˢ',
-            't' => 'ᵗ',
-            'u' => 'ᵘ',
-            'v' => 'ᵛ',
-            'w' => 'ʷ',
-            'x' => 'ˣ',
-            'y' => 'ʸ',
-            'z' => 'ᶻ',
-            'A' => 'ᴬ',
-            'B' => 'ᴮ',
-            'D' => 'ᴰ',
-            'E' => 'ᴱ',
-            'G' => 'ᴳ',
-            'H' => 'ᴴ',
-            'I' => 'ᴵ',
-            'J' => 'ᴶ',
-            'K' => 'ᴷ',
-            'L' => 'ᴸ',
-            'M' => 'ᴹ',
-            'N' => 'ᴺ',
-            'O' => 'ᴼ',
-            'P' => 'ᴾ',
-            'R' => 'ᴿ',
-            'T' => 'ᵀ',
-            'U' => 'ᵁ',
-            'V' => 'ⱽ',
-            'W' => 'ᵂ',
-            'α' => 'ᵅ',
-            'β' => 'ᵝ',
-            'γ' => 'ᵞ',
-            'δ' => 'ᵟ',
-            'ε' => 'ᵋ',
-            'θ' => 'ᶿ',
-            'ι' => 'ᶥ',
-            'φ' => 'ᵠ',
-            'χ' => 'ᵡ',
-            'ʊ' => 'ᵁ',
-            'ə' => 'ᵊ',
-            'ɛ' => 'ᵋ',
-            'ɣ' => 'ˠ',
-            'ʁ' => 'ʶ',
-            'ʃ' => 'ᶴ',
-            'ʒ' => 'ᶾ',
-            'ŋ' => 'ᵑ',
-            _ => return None,
-        };
-        result.push(sup);
+impl UnicodeRenderer {
+    // The class of each expression and whether it is a named function, with
+    // TeX's rules for binary operators that have nothing to operate on applied:
+    // those become ordinary, like the minus in `-x` or `(-1)`.
+    pub(crate) fn classify(&self, exprs: &[Expr]) -> Vec<Option<(AtomClass, bool)>> {
+        let mut atoms: Vec<_> = exprs.iter().map(|e| self.atom(e)).collect();
+        let mut previous: Option<usize> = None;
+        for i in 0..atoms.len() {
+            let Some((class, _)) = atoms[i] else {
+                continue;
+            };
+            if class == AtomClass::Bin {
+                let previous_class = previous.and_then(|p| atoms[p]).map(|(c, _)| c);
+                if matches!(
+                    previous_class,
+                    None | Some(
+                        AtomClass::Bin
+                            | AtomClass::Op
+                            | AtomClass::Rel
+                            | AtomClass::Open
+                            | AtomClass::Punct
+                    )
+                ) {
+                    atoms[i] = Some((AtomClass::Ord, false));
+                }
+            }
+            if matches!(class, AtomClass::Rel | AtomClass::Close | AtomClass::Punct)
+                && let Some(p) = previous
+                && let Some((AtomClass::Bin, _)) = atoms[p]
+            {
+                atoms[p] = Some((AtomClass::Ord, false));
+            }
+            previous = Some(i);
+        }
+        // a trailing operator has nothing on its right either
+        if let Some(p) = previous
+            && let Some((AtomClass::Bin, _)) = atoms[p]
+        {
+            atoms[p] = Some((AtomClass::Ord, false));
+        }
+        atoms
+    }
+
+    fn atom(&self, expr: &Expr) -> Option<(AtomClass, bool)> {
+        match &expr.kind {
+            ExprKind::Literal(c) => Some((literal_class(*c), false)),
+            ExprKind::Command(Command::Symbol { name }) => self
+                .symbol_class(name)
+                .map(|class| (class, self.is_named_function(name))),
+            ExprKind::Command(Command::Custom { name, .. }) => self
+                .symbol_class(name)
+                .map(|class| (class, self.is_named_function(name))),
+            ExprKind::Command(Command::Operatorname { .. }) => Some((AtomClass::Op, true)),
+            ExprKind::Command(Command::Xrightarrow { .. } | Command::Xleftarrow { .. }) => {
+                Some((AtomClass::Rel, false))
+            }
+            ExprKind::Command(_)
+            | ExprKind::Group(_)
+            | ExprKind::Environment(_)
+            | ExprKind::Delimited(_) => Some((AtomClass::Ord, false)),
+            // scripts don't change the class of what they are attached to
+            ExprKind::Superscript(base, _) | ExprKind::Subscript(base, _) => self.atom(base),
+        }
     }
-    Some(result)
 }
 
-fn to_subscript(s: &str) -> Option<String> {
-    let mut result = String::new();
-    for c in s.chars() {
-        let sub = match c {
-            '0' => '₀',
-            '1' => '₁',
-            '2' => '₂',
-            '3' => '₃',
-            '4' => '₄',
-            '5' => '₅',
-            '6' => '₆',
-            '7' => '₇',
-            '8' => '₈',
-            '9' => '₉',
-            '+' => '₊',
-            '-' => '₋',
-            '=' => '₌',
-            '(' => '₍',
-            ')' => '₎',
-            ',' => '‚',
-            '.' => '.',
-            'a' => 'ₐ',
-            'e' => 'ₑ',
-            'h' => 'ₕ',
-            'i' => 'ᵢ',
-            'j' => 'ⱼ',
-            'k' => 'ₖ',
-            'l' => 'ₗ',
-            'm' => 'ₘ',
-            'n' => 'ₙ',
-            'o' => 'ₒ',
-            'p' => 'ₚ',
-            'r' => 'ᵣ',
-            's' => 'ₛ',
-            't' => 'ₜ',
-            'u' => 'ᵤ',
-            'v' => 'ᵥ',
-            'x' => 'ₓ',
-            'β' => 'ᵦ',
-            'γ' => 'ᵧ',
-            'ρ' => 'ᵨ',
-            'φ' => 'ᵩ',
-            'χ' => 'ᵪ',
-            'ə' => 'ₔ',
-            _ => return None,
-        };
-        result.push(sub);
+fn literal_class(c: char) -> AtomClass {
+    match c {
+        '+' | '-' | '*' => AtomClass::Bin,
+        '=' | '<' | '>' | ':' => AtomClass::Rel,
+        '(' | '[' => AtomClass::Open,
+        ')' | ']' | '!' | '?' => AtomClass::Close,
+        ',' | ';' => AtomClass::Punct,
+        _ => AtomClass::Ord,
+    }
+}
+
+pub(crate) fn vulgar_fraction(numer: &str, denom: &str) -> Option<char> {
+    let c = match (numer, denom) {
+        ("1", "2") => '½',
+        ("1", "3") => '⅓',
+        ("2", "3") => '⅔',
+        ("1", "4") => '¼',
+        ("3", "4") => '¾',
+        ("1", "5") => '⅕',
+        ("2", "5") => '⅖',
+        ("3", "5") => '⅗',
+        ("4", "5") => '⅘',
+        ("1", "6") => '⅙',
+        ("5", "6") => '⅚',
+        ("1", "7") => '⅐',
+        ("1", "8") => '⅛',
+        ("3", "8") => '⅜',
+        ("5", "8") => '⅝',
+        ("7", "8") => '⅞',
+        ("1", "9") => '⅑',
+        ("1", "10") => '⅒',
+        ("0", "3") => '↉',
+        _ => return None,
+    };
+    Some(c)
+}
+
+impl UnicodeRenderer {
+    // Whether `expr` reads as a single unit next to a `/`: a symbol, a number, or
+    // one of those with scripts or an accent.
+    pub(crate) fn is_atomic(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Literal(c) => literal_class(*c) == AtomClass::Ord,
+            ExprKind::Command(Command::Symbol { name }) => {
+                self.symbol_class(name) == Some(AtomClass::Ord)
+            }
+            ExprKind::Group(exprs) => match exprs.as_slice() {
+                [expr] => self.is_atomic(expr),
+                _ => !exprs.is_empty()
+                    && exprs.iter().all(
+                        |e| matches!(e.kind, ExprKind::Literal(c) if c.is_ascii_digit() || c == '.'),
+                    ),
+            },
+            ExprKind::Superscript(base, _) | ExprKind::Subscript(base, _) => self.is_atomic(base),
+            // a matrix is delimited by its brackets
+            ExprKind::Environment(env) => env.name != "aligned",
+            ExprKind::Delimited(delimited) => delimited.open != "." && delimited.close != ".",
+            ExprKind::Command(cmd) => match cmd.args().as_slice() {
+                [content] => !matches!(cmd, Command::Sqrt { .. }) && self.is_atomic(content),
+                _ => false,
+            },
+        }
     }
-    Some(result)
+}
+
+// Whether something in a sequence is multiplied by its neighbours: the atom
+// after it is a factor, or the output before it ends with one. `a/bc` reads
+// as a/(bc), so a fraction there is written `(a/b)c`.
+pub(crate) fn is_next_to_factor(next: &[Option<(AtomClass, bool)>], before: &str) -> bool {
+    matches!(
+        next.iter().flatten().next(),
+        Some((AtomClass::Ord | AtomClass::Op | AtomClass::Open, _))
+    ) || before.ends_with(|c: char| c.is_alphanumeric() || c == ')')
+}
+
+// Matrices as nested lists like `[[a, b], [c, d]]`, cases as
+// `{a, x>0; b, x<=0}` and aligned equations one per line, with `cell`
+// rendering each cell and `norm` the bars around a `Vmatrix`.
+pub(crate) fn linear_environment(
+    renderer: &UnicodeRenderer,
+    env: &Environment,
+    cell: impl Fn(&Expr) -> String,
+    norm: &str,
+) -> String {
+    let cells = |row: &Vec<Expr>| -> Vec<String> { row.iter().map(&cell).collect() };
+    match env.name.as_str() {
+        "cases" => {
+            let rows: Vec<_> = env.rows.iter().map(|row| cells(row).join(", ")).collect();
+            format!("{{{}}}", rows.join("; "))
+        }
+        "aligned" => {
+            let rows: Vec<_> = env
+                .rows
+                .iter()
+                .map(|row| {
+                    let mut line = String::new();
+                    for (i, cell) in cells(row).iter().enumerate() {
+                        if i > 0 {
+                            line += renderer.cell_space(&row[i - 1], &row[i]);
+                        }
+                        line += cell;
+                    }
+                    line
+                })
+                .collect();
+            rows.join("\n")
+        }
+        name => {
+            let rows: Vec<_> = env
+                .rows
+                .iter()
+                .map(|row| format!("[{}]", cells(row).join(", ")))
+                .collect();
+            let matrix = format!("[{}]", rows.join(", "));
+            match name {
+                "vmatrix" => format!("|{}|", matrix),
+                "Vmatrix" => format!("{}{}{}", norm, matrix, norm),
+                _ => matrix,
+            }
+        }
+    }
+}
+
+// TeX's table of inter-atom spacing (The TeXbook, chapter 18) for text style.
+fn gap(left: AtomClass, right: AtomClass) -> Gap {
+    use AtomClass::*;
+    match (left, right) {
+        (Ord | Op | Close, Op) | (Op, Ord) => Gap::Thin,
+        (Punct, _) => Gap::Thin,
+        (Bin, _) | (_, Bin) => Gap::Medium,
+        (Rel, Rel | Close | Punct) | (Open, Rel) => Gap::None,
+        (Rel, _) | (_, Rel) => Gap::Thick,
+        _ => Gap::None,
+    }
+}
+
+pub(crate) fn to_superscript(s: &str) -> Option<String> {
+    s.chars().map(superscript_char).collect()
+}
+
+pub(crate) fn superscript_char(c: char) -> Option<char> {
+    let sup = match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '-' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        ',' => 'ʼ',
+        '.' => '˙',
+        '*' => '*',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        'A' => 'ᴬ',
+        'B' => 'ᴮ',
+        'D' => 'ᴰ',
+        'E' => 'ᴱ',
+        'G' => 'ᴳ',
+        'H' => 'ᴴ',
+        'I' => 'ᴵ',
+        'J' => 'ᴶ',
+        'K' => 'ᴷ',
+        'L' => 'ᴸ',
+        'M' => 'ᴹ',
+        'N' => 'ᴺ',
+        'O' => 'ᴼ',
+        'P' => 'ᴾ',
+        'R' => 'ᴿ',
+        'T' => 'ᵀ',
+        'U' => 'ᵁ',
+        'V' => 'ⱽ',
+        'W' => 'ᵂ',
+        'α' => 'ᵅ',
+        'β' => 'ᵝ',
+        'γ' => 'ᵞ',
+        'δ' => 'ᵟ',
+        'ε' | 'ϵ' => 'ᵋ',
+        'θ' => 'ᶿ',
+        'ι' => 'ᶥ',
+        'φ' | 'ϕ' => 'ᵠ',
+        'χ' => 'ᵡ',
+        'ʊ' => 'ᵁ',
+        'ə' => 'ᵊ',
+        'ɛ' => 'ᵋ',
+        'ɣ' => 'ˠ',
+        'ʁ' => 'ʶ',
+        'ʃ' => 'ᶴ',
+        'ʒ' => 'ᶾ',
+        'ŋ' => 'ᵑ',
+        _ => return None,
+    };
+    Some(sup)
+}
+
+pub(crate) fn to_subscript(s: &str) -> Option<String> {
+    s.chars().map(subscript_char).collect()
+}
+
+pub(crate) fn subscript_char(c: char) -> Option<char> {
+    let sub = match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '-' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        ',' => '‚',
+        '.' => '.',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        'β' => 'ᵦ',
+        'γ' => 'ᵧ',
+        'ρ' => 'ᵨ',
+        'φ' | 'ϕ' => 'ᵩ',
+        'χ' => 'ᵪ',
+        'ə' => 'ₔ',
+        _ => return None,
+    };
+    Some(sub)
 }
 
 fn apply_style(style: &str, content: &str) -> String {
@@ -327,7 +837,7 @@ fn apply_style(style: &str, content: &str) -> String {
     result
 }
 
-fn map_char_style(style: &str, c: char) -> char {
+pub(crate) fn map_char_style(style: &str, c: char) -> char {
     let code = c as u32;
 
     match style {
@@ -397,12 +907,13 @@ mod tests {
     use super::*;
     use crate::lexer::Lexer;
     use crate::parser::Parser;
+    use crate::symbols::Symbol;
 
     fn render(input: &str) -> String {
         let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
         let ast = parser.parse();
-        let renderer = Renderer::new();
+        let renderer = UnicodeRenderer::new();
         renderer.render(&ast)
     }
 
@@ -427,8 +938,37 @@ mod tests {
 
     #[test]
     fn test_fraction() {
-        assert_eq!(render(r"\frac{1}{2}"), "(1)/(2)");
-        assert_eq!(render(r"\frac{a+b}{c}"), "(a+b)/(c)");
+        assert_eq!(render(r"\frac{1}{2}"), "½");
+        assert_eq!(render(r"\frac{7}{8}"), "⅞");
+        assert_eq!(render(r"\frac{12}{7}"), "¹²⁄₇");
+        assert_eq!(render(r"\frac{a+b}{c}"), "(a+b)/c");
+        assert_eq!(render(r"\frac{\pi}{\frac{x}{y}}"), "π/(x/y)");
+        assert_eq!(render(r"\frac{3.5}{\hat{x}}"), "3.5/x\u{0302}");
+    }
+
+    #[test]
+    fn test_fraction_strategies() {
+        let render_with = |fractions, input| {
+            let mut parser = Parser::new(Lexer::new(input));
+            let options = Options {
+                fractions,
+                ..Options::default()
+            };
+            UnicodeRenderer::with_options(&options).render(&parser.parse())
+        };
+        assert_eq!(render_with(Fractions::Slash, r"\frac{1}{2}"), "¹⁄₂");
+        assert_eq!(render_with(Fractions::Linear, r"\frac{1}{2}"), "1/2");
+        assert_eq!(render_with(Fractions::Linear, r"\frac{12}{x_1}"), "12/x₁");
+        assert_eq!(render_with(Fractions::Linear, r"\frac{-1}{2}"), "(-1)/2");
+    }
+
+    #[test]
+    fn test_fraction_next_to_factor() {
+        assert_eq!(render(r"\frac{a}{b}c"), "(a/b)c");
+        assert_eq!(render(r"2\frac{a}{b}"), "2(a/b)");
+        assert_eq!(render(r"\frac{a}{b}^2"), "(a/b)²");
+        assert_eq!(render(r"\frac{12}{7}x + \frac{1}{2}y"), "(¹²⁄₇)x+½y");
+        assert_eq!(render(r"-\frac{a}{b} = \frac{a+b}{c}"), "-a/b=(a+b)/c");
     }
 
     #[test]
@@ -441,7 +981,7 @@ mod tests {
     fn test_complex_expression() {
         let input = r"\alpha^2 + \beta_i = \frac{\gamma}{2}";
         let output = render(input);
-        assert_eq!(output, "α²+βᵢ=(γ)/(2)");
+        assert_eq!(output, "α²+βᵢ=ᵞ⁄₂");
     }
 
     #[test]
@@ -489,10 +1029,12 @@ mod tests {
 
     #[test]
     fn test_functions() {
-        assert_eq!(render(r"\sin x"), "sinx");
+        assert_eq!(render(r"\sin x"), "sin x");
         assert_eq!(render(r"\cos(x)"), "cos(x)");
-        assert_eq!(render(r"\log x"), "logx");
-        assert_eq!(render(r"\ln e"), "lne");
+        assert_eq!(render(r"\log x"), "log x");
+        assert_eq!(render(r"\ln e"), "ln e");
+        assert_eq!(render(r"2\sin^2 x"), "2 sin² x");
+        assert_eq!(render(r"\sum x"), "∑x");
         assert_eq!(render(r"\max(a,b)"), "max(a,b)");
     }
 
@@ -514,12 +1056,15 @@ mod tests {
         assert_eq!(render("x^B"), "xᴮ");
         assert_eq!(render("x^\\beta"), "xᵝ");
         assert_eq!(render("x^\\gamma"), "xᵞ");
+        assert_eq!(render("x^\\phi"), "xᵠ");
+        assert_eq!(render("x^\\epsilon"), "xᵋ");
     }
 
     #[test]
     fn test_extended_subscript() {
         assert_eq!(render("x_\\beta"), "xᵦ");
         assert_eq!(render("x_\\rho"), "xᵨ");
+        assert_eq!(render("x_\\phi"), "xᵩ");
     }
 
     #[test]
@@ -537,4 +1082,142 @@ mod tests {
         assert_eq!(render("1^{*}"), "1*");
         assert_eq!(render("A^*"), "A*");
     }
+
+    #[test]
+    fn test_diagnostics() {
+        let input = r"\foo + x^{q2} + y_{\beta z}";
+        let ast = Parser::new(Lexer::new(input)).parse();
+        let renderer = UnicodeRenderer::new();
+        assert_eq!(renderer.render(&ast), r"\foo+x^{q2}+y_{βz}");
+
+        let diagnostics = renderer.take_diagnostics();
+        let kinds: Vec<_> = diagnostics.iter().map(|d| d.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::UnknownCommand("foo".to_string()),
+                DiagnosticKind::UnrepresentableSuperscript('q'),
+                DiagnosticKind::UnrepresentableSubscript('z'),
+            ]
+        );
+        assert_eq!(&input[diagnostics[0].span.bytes()], r"\foo");
+        assert_eq!(&input[diagnostics[1].span.bytes()], "{q2}");
+        assert_eq!(&input[diagnostics[2].span.bytes()], r"{\beta z}");
+        assert!(renderer.take_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_sqrt_index() {
+        assert_eq!(render(r"\sqrt{x}"), "√(x)");
+        assert_eq!(render(r"\sqrt[2]{x}"), "√(x)");
+        assert_eq!(render(r"\sqrt[3]{x}"), "∛(x)");
+        assert_eq!(render(r"\sqrt[4]{x+1}"), "∜(x+1)");
+        assert_eq!(render(r"\sqrt[n]{x}"), "ⁿ√(x)");
+        assert_eq!(render(r"\sqrt[10]{2}"), "¹⁰√(2)");
+    }
+
+    #[test]
+    fn test_xarrows() {
+        assert_eq!(render(r"\xrightarrow{f}"), "→ᶠ");
+        assert_eq!(render(r"\xrightarrow[n]{}"), "→ₙ");
+        assert_eq!(render(r"\xleftarrow[0]{g}"), "←ᵍ₀");
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(render(r"\text{if } x > 0"), "if x>0");
+        assert_eq!(render(r"\textrm{for all}\ n"), "for all n");
+        assert_eq!(render(r"\mbox{a  b}"), "a b");
+        assert_eq!(render(r"\mathrm{d}x"), "dx");
+        assert_eq!(render(r"\operatorname{arg max}_x f"), "arg maxₓ f");
+        assert_eq!(render(r"\text{\alpha{} and \beta}"), "α and β");
+    }
+
+    #[test]
+    fn test_registered_symbols() {
+        let mut options = Options {
+            spacing: Spacing::Readable,
+            ..Options::default()
+        };
+        options
+            .symbols
+            .register("defeq", Symbol::new("≔", AtomClass::Rel));
+        options
+            .symbols
+            .register("abs", Symbol::new("|#1|", AtomClass::Ord).with_arity(1));
+        let mut parser =
+            Parser::new(Lexer::new(r"f \defeq \abs{x^2} + \abs y")).with_symbols(&options.symbols);
+        let renderer = UnicodeRenderer::with_options(&options);
+        assert_eq!(renderer.render(&parser.parse()), "f ≔ |x²| + |y|");
+        assert!(renderer.take_diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_spacing_profiles() {
+        let render_with = |spacing, input| {
+            let mut parser = Parser::new(Lexer::new(input));
+            let options = Options {
+                spacing,
+                ..Options::default()
+            };
+            UnicodeRenderer::with_options(&options).render(&parser.parse())
+        };
+        let input = r"f(a, -b) \le x^{a+b} + \sin y";
+        assert_eq!(render_with(Spacing::Compact, input), "f(a,-b)≤xᵃ⁺ᵇ+sin y");
+        assert_eq!(
+            render_with(Spacing::Readable, input),
+            "f(a, -b) ≤ xᵃ⁺ᵇ + sin y"
+        );
+        assert_eq!(
+            render_with(Spacing::TexLike, input),
+            "f(a,\u{2009}-b)\u{2005}≤\u{2005}xᵃ⁺ᵇ\u{205F}+\u{205F}sin\u{2009}y"
+        );
+        // explicit spaces are kept as they are and don't add to the spacing
+        assert_eq!(render_with(Spacing::Readable, r"a \quad = b"), "a   = b");
+        assert_eq!(
+            render_with(Spacing::Readable, r"\{ a \mid a > 0 \}"),
+            "{a | a > 0}"
+        );
+        assert_eq!("tex-like".parse(), Ok(Spacing::TexLike));
+        // like the other settings, names are case-sensitive
+        assert!("TeX-like".parse::<Spacing>().is_err());
+    }
+
+    #[test]
+    fn test_environments() {
+        let matrix = r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}";
+        assert_eq!(render(matrix), "[[a, b], [c, d]]");
+        assert_eq!(
+            render(r"\begin{vmatrix} 1 & 0 \\ 0 & 1 \end{vmatrix}"),
+            "|[[1, 0], [0, 1]]|"
+        );
+        assert_eq!(
+            render(r"|x| = \begin{cases} x & x \ge 0 \\ -x & x < 0 \end{cases}"),
+            "|x|={x, x≥0; -x, x<0}"
+        );
+        assert_eq!(
+            render(r"\begin{aligned} a &= b + c \\ &= d \end{aligned}"),
+            "a=b+c\n=d"
+        );
+        assert_eq!(
+            render(r"\begin{array}{cc} \hline 1 & 2 \end{array}"),
+            "[[1, 2]]"
+        );
+    }
+
+    #[test]
+    fn test_delimiters() {
+        assert_eq!(render(r"\left( \frac{a}{b} \right)"), "(a/b)");
+        assert_eq!(render(r"\left\langle x \middle| y \right\rangle"), "⟨x|y⟩");
+        assert_eq!(
+            render(r"\left. \frac{dy}{dx} \right|_{x=0}"),
+            "(dy)/(dx)|ₓ₌₀"
+        );
+        assert_eq!(
+            render(r"\bigl\lceil x \bigr\rceil + \Big\lfloor y \Big\rfloor"),
+            "⌈x⌉+⌊y⌋"
+        );
+        assert_eq!(render(r"\lVert v \rVert = \| v \|"), "‖v‖=‖v‖");
+        assert_eq!(render(r"\{ x \mid x > 0 \}"), "{x|x>0}");
+    }
 }
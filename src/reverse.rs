@@ -0,0 +1,329 @@
+use crate::renderer::{map_char_style, subscript_char, superscript_char, vulgar_fraction};
+use crate::symbols::SymbolTable;
+use std::collections::HashMap;
+
+pub(crate) const STYLES: [&str; 7] = [
+    "mathbb", "mathcal", "mathfrak", "mathbf", "mathit", "mathsf", "mathtt",
+];
+
+const ACCENTS: [(char, &str); 6] = [
+    ('\u{0304}', "bar"),
+    ('\u{0302}', "hat"),
+    ('\u{20D7}', "vec"),
+    ('\u{0307}', "dot"),
+    ('\u{0308}', "ddot"),
+    ('\u{0303}', "tilde"),
+];
+
+// Precomposed letters with the accents above, as pairs of the plain letter
+// and the accented one.
+const PRECOMPOSED: [(&str, &str); 5] = [
+    (
+        "hat",
+        "AÂCĈEÊGĜHĤIÎJĴOÔSŜUÛWŴYŶZẐaâcĉeêgĝhĥiîjĵoôsŝuûwŵyŷzẑ",
+    ),
+    ("bar", "AĀEĒGḠIĪOŌUŪYȲaāeēgḡiīoōuūyȳ"),
+    ("tilde", "AÃEẼIĨNÑOÕUŨVṼYỸaãeẽiĩnñoõuũvṽyỹ"),
+    (
+        "dot",
+        "AȦBḂCĊDḊEĖFḞGĠHḢIİMṀNṄOȮPṖRṘSṠTṪWẆXẊYẎZŻaȧbḃcċdḋeėfḟgġhḣmṁnṅoȯpṗrṙsṡtṫwẇxẋyẏzż",
+    ),
+    ("ddot", "AÄEËHḦIÏOÖUÜWẄXẌYŸaäeëhḧiïoötẗuüwẅxẍyÿ"),
+];
+
+const FRACTION_SLASH: char = '\u{2044}';
+
+// Turns Unicode math like `∀ε>0` back into LaTeX like
+// `\forall\varepsilon>0`, inverting the renderer's tables: symbols, script
+// characters, styled letters, accents, fractions and roots. Everything else is
+// kept as is.
+pub fn to_latex(input: &str, symbols: &SymbolTable) -> String {
+    Reverser::new(symbols).convert(input)
+}
+
+struct Reverser<'a> {
+    // the command for every character that one renders as
+    commands: HashMap<char, &'a str>,
+    // styled letters and digits, like `𝐱`, with their style and plain form
+    styled: HashMap<char, (&'static str, char)>,
+    // precomposed accented letters, like `â`, with their accent and letter
+    accented: HashMap<char, (&'static str, char)>,
+    superscripts: HashMap<char, char>,
+    subscripts: HashMap<char, char>,
+    fractions: HashMap<char, (String, String)>,
+}
+
+impl<'a> Reverser<'a> {
+    fn new(symbols: &'a SymbolTable) -> Self {
+        let mut commands: HashMap<char, &str> = HashMap::new();
+        for (name, symbol) in symbols.iter() {
+            let mut chars = symbol.output.chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                continue;
+            };
+            if c.is_ascii() || symbol.class.is_none() || symbol.arity > 0 {
+                continue;
+            }
+            // the shortest name is usually the familiar one, like `\le` over
+            // `\leq`, but unicode-math's upright aliases like `\upphi` are
+            // only used for characters nothing else renders as
+            let key = |name: &str| {
+                let alias = name
+                    .strip_prefix("up")
+                    .is_some_and(|rest| symbols.contains(rest));
+                (alias, name.len(), name.to_string())
+            };
+            let better = |current: &&str| key(name) < key(current);
+            if commands.get(&c).is_none_or(better) {
+                commands.insert(c, name);
+            }
+        }
+
+        let plain = (' '..='~').chain('α'..='ω').chain("ʊəɛɣʁʃʒŋ".chars());
+        let mut superscripts = HashMap::new();
+        let mut subscripts = HashMap::new();
+        for c in plain {
+            if let Some(sup) = superscript_char(c).filter(|&sup| sup != c) {
+                superscripts.entry(sup).or_insert(c);
+            }
+            if let Some(sub) = subscript_char(c).filter(|&sub| sub != c) {
+                subscripts.entry(sub).or_insert(c);
+            }
+        }
+
+        let mut styled = HashMap::new();
+        for style in STYLES {
+            for c in ('A'..='Z').chain('a'..='z').chain('0'..='9') {
+                let styled_c = map_char_style(style, c);
+                if styled_c != c {
+                    styled.entry(styled_c).or_insert((style, c));
+                }
+            }
+        }
+
+        let mut accented = HashMap::new();
+        for (accent, pairs) in PRECOMPOSED {
+            let chars: Vec<char> = pairs.chars().collect();
+            for pair in chars.chunks(2) {
+                accented.insert(pair[1], (accent, pair[0]));
+            }
+        }
+
+        let mut fractions = HashMap::new();
+        for denom in 1..=10 {
+            for numer in 0..denom {
+                let (numer, denom) = (numer.to_string(), denom.to_string());
+                if let Some(c) = vulgar_fraction(&numer, &denom) {
+                    fractions.insert(c, (numer, denom));
+                }
+            }
+        }
+
+        Reverser {
+            commands,
+            styled,
+            accented,
+            superscripts,
+            subscripts,
+            fractions,
+        }
+    }
+
+    fn convert(&self, input: &str) -> String {
+        let chars: Vec<char> = input.chars().collect();
+        // each piece with whether it is a command like `\alpha`, which a
+        // letter after it would run into
+        let mut pieces: Vec<(String, bool)> = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            i += 1;
+            let mut command = false;
+            let piece = if self.superscripts.contains_key(&c) {
+                let numer = self.run(&chars, &mut i, c, &self.superscripts);
+                if chars.get(i) == Some(&FRACTION_SLASH)
+                    && let Some(&next) = chars.get(i + 1)
+                    && self.subscripts.contains_key(&next)
+                {
+                    i += 2;
+                    let denom = self.run(&chars, &mut i, next, &self.subscripts);
+                    format!("\\frac{{{}}}{{{}}}", numer, denom)
+                } else {
+                    script('^', &numer)
+                }
+            } else if self.subscripts.contains_key(&c) {
+                script('_', &self.run(&chars, &mut i, c, &self.subscripts))
+            } else if let Some(&(_, accent)) = ACCENTS.iter().find(|(mark, _)| *mark == c) {
+                let (base, _) = pieces.pop().unwrap_or_default();
+                format!("\\{}{{{}}}", accent, base)
+            } else if let Some(&(accent, plain)) = self.accented.get(&c) {
+                format!("\\{}{{{}}}", accent, plain)
+            } else if let Some(&(style, plain)) = self.styled.get(&c) {
+                let mut content = plain.to_string();
+                while let Some(&(next_style, next)) = chars.get(i).and_then(|c| self.styled.get(c))
+                    && next_style == style
+                {
+                    content.push(next);
+                    i += 1;
+                }
+                format!("\\{}{{{}}}", style, content)
+            } else if let Some((numer, denom)) = self.fractions.get(&c) {
+                format!("\\frac{{{}}}{{{}}}", numer, denom)
+            } else if let Some(index) = radical_index(c) {
+                let content = self.radicand(&chars, &mut i);
+                match index {
+                    Some(index) => format!("\\sqrt[{}]{{{}}}", index, content),
+                    None => format!("\\sqrt{{{}}}", content),
+                }
+            } else {
+                match c {
+                    '−' => "-".to_string(),
+                    '′' => "'".to_string(),
+                    _ if c.is_whitespace() => " ".to_string(),
+                    _ => match self.commands.get(&c) {
+                        Some(name) => {
+                            command = name.chars().all(|c| c.is_ascii_alphabetic());
+                            format!("\\{}", name)
+                        }
+                        None => c.to_string(),
+                    },
+                }
+            };
+            pieces.push((piece, command));
+        }
+
+        let mut result = String::new();
+        let mut after_command = false;
+        for (piece, command) in pieces {
+            // `\alpha x`, not `\alphax`
+            if after_command && piece.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                result.push(' ');
+            }
+            result += &piece;
+            after_command = command;
+        }
+        result
+    }
+
+    // The LaTeX for a run of script characters starting with `first`, with
+    // `i` moved past its end.
+    fn run(&self, chars: &[char], i: &mut usize, first: char, map: &HashMap<char, char>) -> String {
+        let mut plain = String::from(map[&first]);
+        while let Some(c) = chars.get(*i).and_then(|c| map.get(c)) {
+            plain.push(*c);
+            *i += 1;
+        }
+        self.convert(&plain)
+    }
+
+    // What a root sign applies to: a parenthesized group, whose parentheses
+    // the renderer added, or else the next character.
+    fn radicand(&self, chars: &[char], i: &mut usize) -> String {
+        if chars.get(*i) == Some(&'(') {
+            let mut depth = 0;
+            for (end, &c) in chars.iter().enumerate().skip(*i) {
+                match c {
+                    '(' => depth += 1,
+                    ')' if depth == 1 => {
+                        let inner: String = chars[*i + 1..end].iter().collect();
+                        *i = end + 1;
+                        return self.convert(&inner);
+                    }
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+            }
+        }
+        match chars.get(*i) {
+            Some(c) => {
+                *i += 1;
+                self.convert(&c.to_string())
+            }
+            None => String::new(),
+        }
+    }
+}
+
+// `None` for a square root, the index for the other root signs.
+fn radical_index(c: char) -> Option<Option<u8>> {
+    match c {
+        '√' => Some(None),
+        '∛' => Some(Some(3)),
+        '∜' => Some(Some(4)),
+        _ => None,
+    }
+}
+
+// `^2` for a single letter or digit, `^{...}` for anything else.
+fn script(marker: char, content: &str) -> String {
+    let mut chars = content.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => format!("{}{}", marker, c),
+        _ => format!("{}{{{}}}", marker, content),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert_latex_to_unicode;
+
+    fn reverse(input: &str) -> String {
+        to_latex(input, SymbolTable::builtin())
+    }
+
+    #[test]
+    fn test_symbols() {
+        assert_eq!(
+            reverse("∀ε>0 ∃δ: |x−a|<δ ⟹ …"),
+            r"\forall\varepsilon>0 \exists\delta: |x-a|<\delta \implies \dots"
+        );
+        assert_eq!(reverse("α x ≤ β′"), r"\alpha x \le \beta'");
+        assert_eq!(reverse("☃ text"), "☃ text");
+        // LaTeX that is already there is left alone
+        assert_eq!(reverse(r"\alpha ≤ β"), r"\alpha \le \beta");
+    }
+
+    #[test]
+    fn test_scripts() {
+        assert_eq!(reverse("x²+yᵢ₊₁"), "x^2+y_{i+1}");
+        assert_eq!(reverse("eⁱᵅ"), r"e^{i\alpha}");
+        assert_eq!(reverse("½+¹²⁄₇"), r"\frac{1}{2}+\frac{12}{7}");
+    }
+
+    #[test]
+    fn test_styles_and_accents() {
+        assert_eq!(
+            reverse("𝐯⋅𝐰 ∈ ℝⁿ"),
+            r"\mathbf{v}\cdot\mathbf{w} \in \mathbb{R}^n"
+        );
+        assert_eq!(reverse("𝔤𝔩 ℋ"), r"\mathfrak{gl} \mathcal{H}");
+        assert_eq!(reverse("x̂ + v⃗̇"), r"\hat{x} + \dot{\vec{v}}");
+        assert_eq!(reverse("√(x+1) ∛2"), r"\sqrt{x+1} \sqrt[3]{2}");
+        assert_eq!(reverse("â+ȳ=ñ"), r"\hat{a}+\bar{y}=\tilde{n}");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for latex in [
+            r"\sum_{i=1}^n x_i^2 \le \frac{1}{2}",
+            r"\mathbb{E}[X] \approx \sqrt{\hat{\sigma}^2}",
+            r"f: \mathbb{R} \to \mathbb{R}, x \mapsto \alpha x",
+        ] {
+            let unicode = convert_latex_to_unicode(latex);
+            assert_eq!(convert_latex_to_unicode(&reverse(&unicode)), unicode);
+        }
+        for latex in [
+            r"\epsilon\varepsilon\phi\varphi\theta\vartheta",
+            r"\rho\varrho\sigma\varsigma\pi\varpi\kappa\varkappa",
+            r"\hat{x}\bar{x}\vec{x}\dot{x}\ddot{x}\tilde{x}",
+        ] {
+            assert_eq!(reverse(&convert_latex_to_unicode(latex)), latex);
+        }
+        // precomposed letters come back with a combining accent instead
+        for (unicode, latex) in [("â", r"\hat{a}"), ("Ȳ", r"\bar{Y}"), ("ö", r"\ddot{o}")] {
+            assert_eq!(reverse(unicode), latex);
+            assert_eq!(reverse(&convert_latex_to_unicode(latex)), latex);
+        }
+    }
+}
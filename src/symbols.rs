@@ -0,0 +1,320 @@
+use crate::renderer::AtomClass;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+
+// the generated unicode-math table, with the hand-written entries on top
+static BUILTIN: LazyLock<SymbolTable> = LazyLock::new(|| {
+    let mut table = SymbolTable::parse(UNIMATH).expect("unimath.tsv is valid");
+    table
+        .load_overrides(include_str!("symbols.tsv"))
+        .expect("symbols.tsv is valid");
+    table
+});
+
+const UNIMATH: &str = include_str!("unimath.tsv");
+
+// A command that renders as fixed text, like `\alpha` or `\sin`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    // the Unicode text, with `#1`..`#9` standing for the arguments
+    pub output: String,
+    // `None` for explicit spaces like `\,`, which are not atoms
+    pub class: Option<AtomClass>,
+    // an operator written as an upright word, like `\sin`
+    pub named: bool,
+    pub arity: usize,
+}
+
+impl Symbol {
+    pub fn new(output: impl Into<String>, class: AtomClass) -> Self {
+        Symbol {
+            output: output.into(),
+            class: Some(class),
+            named: false,
+            arity: 0,
+        }
+    }
+
+    // Text with `#1`..`#n` for the `arity` arguments, like `|#1|`.
+    pub fn with_arity(mut self, arity: usize) -> Self {
+        self.arity = arity;
+        self
+    }
+
+    // `output` with `#1`..`#9` replaced by the rendered arguments.
+    pub fn expand(&self, args: &[String]) -> String {
+        let mut result = String::new();
+        let mut chars = self.output.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '#'
+                && let Some(arg) = chars
+                    .peek()
+                    .and_then(|d| d.to_digit(10))
+                    .and_then(|n| args.get((n as usize).checked_sub(1)?))
+            {
+                chars.next();
+                result += arg;
+            } else {
+                result.push(c);
+            }
+        }
+        result
+    }
+
+    // The combining mark of a one-argument accent like `\overline`, whose
+    // output is `#1` followed by the mark.
+    pub fn accent(&self) -> Option<char> {
+        let mut chars = self.output.strip_prefix("#1")?.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c @ ('\u{0300}'..='\u{036F}' | '\u{20D0}'..='\u{20FF}')), None)
+                if self.arity == 1 =>
+            {
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+}
+
+// The commands known besides those with their own layout, like `\frac`,
+// by name without the backslash. Starts out with the built-in tables
+// `unimath.tsv` and `symbols.tsv`; entries can be added or replaced at runtime.
+// Clones share their entries until one of them is changed, so every set of
+// options and every renderer can have its own table for free.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolTable {
+    symbols: Arc<HashMap<String, Symbol>>,
+}
+
+impl Default for SymbolTable {
+    // shares the built-in entries rather than copying them
+    fn default() -> Self {
+        SymbolTable::builtin().clone()
+    }
+}
+
+impl SymbolTable {
+    pub fn builtin() -> &'static SymbolTable {
+        &BUILTIN
+    }
+
+    pub fn empty() -> Self {
+        SymbolTable {
+            symbols: Arc::new(HashMap::new()),
+        }
+    }
+
+    // Reads a table in the format of `symbols.tsv`: one command per line with
+    // tab-separated name, output, class and arity. Blank lines and lines
+    // starting with `#` are skipped.
+    pub fn parse(source: &str) -> Result<SymbolTable, String> {
+        let mut table = SymbolTable::empty();
+        for (number, line) in source.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, symbol) =
+                parse_line(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+            table.register(name, symbol);
+        }
+        Ok(table)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.symbols.contains_key(name)
+    }
+
+    // Every command and its symbol, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Symbol)> {
+        self.symbols
+            .iter()
+            .map(|(name, symbol)| (name.as_str(), symbol))
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    // Adds a command, replacing any existing one with the same name.
+    pub fn register(&mut self, name: impl Into<String>, symbol: Symbol) {
+        Arc::make_mut(&mut self.symbols).insert(name.into(), symbol);
+    }
+
+    // Adds the commands of a file in the format of `symbols.tsv`, replacing
+    // those with the same name. Nothing is changed if the file is invalid.
+    pub fn load_overrides(&mut self, source: &str) -> Result<(), String> {
+        let overrides = SymbolTable::parse(source)?;
+        Arc::make_mut(&mut self.symbols).extend(Arc::unwrap_or_clone(overrides.symbols));
+        Ok(())
+    }
+}
+
+fn parse_line(line: &str) -> Result<(String, Symbol), String> {
+    let fields: Vec<_> = line.split('\t').collect();
+    let [name, output, class, arity] = fields[..] else {
+        return Err(format!(
+            "expected 4 tab-separated fields, found {}",
+            fields.len()
+        ));
+    };
+    let name = unescape(name)?;
+    if name.is_empty() {
+        return Err("empty command name".to_string());
+    }
+    let (class, named) = match class {
+        "ord" => (Some(AtomClass::Ord), false),
+        "op" => (Some(AtomClass::Op), false),
+        "fn" => (Some(AtomClass::Op), true),
+        "bin" => (Some(AtomClass::Bin), false),
+        "rel" => (Some(AtomClass::Rel), false),
+        "open" => (Some(AtomClass::Open), false),
+        "close" => (Some(AtomClass::Close), false),
+        "punct" => (Some(AtomClass::Punct), false),
+        "space" => (None, false),
+        _ => return Err(format!("unknown class `{}`", class)),
+    };
+    let arity = match arity.parse() {
+        Ok(arity @ 0..=9) => arity,
+        _ => return Err(format!("arity `{}` is not a number from 0 to 9", arity)),
+    };
+    let symbol = Symbol {
+        output: unescape(output)?,
+        class,
+        named,
+        arity,
+    };
+    Ok((name, symbol))
+}
+
+// Replaces escapes like `\u{20}` by their character.
+fn unescape(field: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = field;
+    while let Some(start) = rest.find("\\u{") {
+        result += &rest[..start];
+        let escape = &rest[start + 3..];
+        let c = escape
+            .find('}')
+            .and_then(|end| {
+                let c = u32::from_str_radix(&escape[..end], 16).ok()?;
+                Some((char::from_u32(c)?, end))
+            })
+            .ok_or_else(|| format!("invalid escape in `{}`", field))?;
+        result.push(c.0);
+        rest = &escape[c.1 + 1..];
+    }
+    Ok(result + rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin() {
+        let table = SymbolTable::builtin();
+        assert_eq!(table.get("alpha"), Some(&Symbol::new("α", AtomClass::Ord)));
+        let sin = table.get("sin").unwrap();
+        assert!(sin.named);
+        assert_eq!(sin.class, Some(AtomClass::Op));
+        assert_eq!(table.get("quad").unwrap().output, "  ");
+        assert_eq!(table.get(" ").unwrap().class, None);
+        assert_eq!(table.get("backslash").unwrap().output, "\\");
+        assert!(!table.contains("frac"));
+        for (name, output) in [("#", "#"), ("$", "$"), ("%", "%"), ("&", "&"), ("_", "_")] {
+            assert_eq!(table.get(name).unwrap().output, output, "\\{}", name);
+        }
+        assert_eq!(table.get("mbfA").unwrap().output, "𝐀");
+        assert_eq!(
+            table.get("hookrightarrow").unwrap().class,
+            Some(AtomClass::Rel)
+        );
+        // the hand-written table wins over unicode-math's choice of glyph
+        assert_eq!(table.get("mid").unwrap().output, "|");
+        // the variant letters are the ones LaTeX draws
+        assert_eq!(table.get("epsilon").unwrap().output, "ϵ");
+        assert_eq!(table.get("varepsilon").unwrap().output, "ε");
+        assert_eq!(table.get("phi").unwrap().output, "ϕ");
+        assert_eq!(table.get("varphi").unwrap().output, "φ");
+        for (name, output) in [
+            ("backepsilon", "϶"),
+            ("lightning", "↯"),
+            ("diagup", "╱"),
+            ("diagdown", "╲"),
+            ("smallfrown", "⌢"),
+        ] {
+            assert_eq!(table.get(name).unwrap().output, output, "\\{}", name);
+        }
+    }
+
+    #[test]
+    fn test_unimath_round_trip() {
+        let table = SymbolTable::parse(UNIMATH).unwrap();
+        let entries = UNIMATH.lines().filter(|line| !line.starts_with('#'));
+        assert_eq!(entries.count(), table.len(), "duplicate names");
+        assert!(table.len() > 1500);
+        for name in [
+            "colon", "coloneqq", "overline", "widehat", "mathring", "dag",
+        ] {
+            assert!(table.contains(name), "\\{}", name);
+        }
+
+        let options = crate::Options {
+            scope: crate::Scope::Whole,
+            symbols: table.clone(),
+            ..crate::Options::default()
+        };
+        for (name, symbol) in table.iter() {
+            // accents are tried on a letter
+            let input = format!("\\{}{}", name, "{x}".repeat(symbol.arity));
+            let expected = symbol.expand(&vec!["x".to_string(); symbol.arity]);
+            let (output, report) = crate::convert_with_report(&input, &options);
+            assert_eq!(output, expected, "\\{}", name);
+            assert!(report.is_lossless(), "\\{}: {:?}", name, report);
+
+            // and back, to this command or another one with the same output,
+            // except for ASCII like `}`, which is kept as LaTeX
+            if expected.is_ascii() {
+                continue;
+            }
+            let latex = crate::reverse::to_latex(&output, &table);
+            // `−` and `′` are written as `-` and `'` on purpose
+            if !latex.contains('\\') && latex.is_ascii() {
+                continue;
+            }
+            let (output, report) = crate::convert_with_report(&latex, &options);
+            assert_eq!(output, expected, "\\{} back as `{}`", name, latex);
+            assert!(report.is_lossless(), "`{}`: {:?}", latex, report);
+        }
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut table = SymbolTable::default();
+        assert!(Arc::ptr_eq(&table.symbols, &SymbolTable::builtin().symbols));
+        table
+            .load_overrides("# mine\nepsilon\tε\tord\t0\nabs\t|#1|\tord\t1\n")
+            .unwrap();
+        assert_eq!(table.get("epsilon").unwrap().output, "ε");
+        let abs = table.get("abs").unwrap();
+        assert_eq!(abs.arity, 1);
+        assert_eq!(abs.expand(&["x".to_string()]), "|x|");
+        assert!(!SymbolTable::builtin().contains("abs"));
+
+        let err = table
+            .load_overrides("a\tb\tord\t0\nc\td\tbig\t0")
+            .unwrap_err();
+        assert_eq!(err, "line 2: unknown class `big`");
+        assert!(!table.contains("a"));
+        assert!(table.load_overrides("a\tb\tord").is_err());
+        assert!(table.load_overrides("a\t\\u{zz}\tord\t0").is_err());
+    }
+}
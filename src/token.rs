@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Command(String),
@@ -9,6 +11,79 @@ pub enum Token {
     RParen,
     Superscript,
     Subscript,
+    // `&`, between the cells of an environment
+    Ampersand,
+    // `\\`, between the rows of an environment
+    Newline,
     Char(char),
     EOF,
 }
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+// A region of the input, both as byte offsets (for slicing the `&str`) and as
+// char offsets (for editors and other tools that count characters).
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+}
+
+impl Span {
+    pub fn new(bytes: Range<usize>, chars: Range<usize>) -> Self {
+        Span {
+            start: bytes.start,
+            end: bytes.end,
+            char_start: chars.start,
+            char_end: chars.end,
+        }
+    }
+
+    pub fn bytes(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn chars(&self) -> Range<usize> {
+        self.char_start..self.char_end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    // The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+            char_start: self.char_start.min(other.char_start),
+            char_end: self.char_end.max(other.char_end),
+        }
+    }
+
+    // The same region in a larger input where this span's input starts at
+    // byte `bytes` and char `chars`.
+    pub fn offset(self, bytes: usize, chars: usize) -> Span {
+        Span {
+            start: self.start + bytes,
+            end: self.end + bytes,
+            char_start: self.char_start + chars,
+            char_end: self.char_end + chars,
+        }
+    }
+
+    // The empty span right after this one.
+    pub fn shrink_to_end(self) -> Span {
+        Span {
+            start: self.end,
+            char_start: self.char_end,
+            ..self
+        }
+    }
+}
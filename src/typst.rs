@@ -0,0 +1,569 @@
+use crate::Options;
+use crate::ast::{Command, Delimited, Environment, Expr, ExprKind};
+use crate::diagnostics::Diagnostic;
+use crate::renderer::{AtomClass, Renderer, Spacing, UnicodeRenderer};
+use crate::token::Span;
+use std::cell::Cell;
+
+// The operators Typst writes as upright words by itself, like `sin`.
+const OPERATORS: [&str; 46] = [
+    "arccos", "arcsin", "arctan", "arg", "cos", "cosh", "cot", "coth", "csc", "csch", "ctg", "deg",
+    "det", "dim", "exp", "gcd", "lcm", "hom", "id", "im", "inf", "ker", "lg", "lim", "liminf",
+    "limsup", "ln", "log", "max", "min", "mod", "Pr", "sec", "sech", "sin", "sinc", "sinh", "sup",
+    "tan", "tanh", "tg", "tr", "arcsec", "arccot", "arccsc", "arccosh",
+];
+
+// Typst math, like `sum_(i=1)^n frac(1, i^2)`. Symbols with a Typst name use
+// it, everything else is written as the Unicode character, which Typst
+// accepts as is.
+pub struct TypstRenderer {
+    // for symbols, the classes of atoms and the spaces between them
+    inline: UnicodeRenderer,
+    // inside the arguments of a call like `frac(a, b)`, where commas and
+    // semicolons would separate arguments
+    args: Cell<usize>,
+}
+
+impl Default for TypstRenderer {
+    fn default() -> Self {
+        TypstRenderer::with_options(&Options::default())
+    }
+}
+
+impl TypstRenderer {
+    pub fn new() -> Self {
+        TypstRenderer::default()
+    }
+
+    // Typst does its own spacing; the spaces in the output only separate
+    // relations and operators for readability.
+    pub fn with_options(options: &Options) -> Self {
+        let options = Options {
+            spacing: Spacing::Readable,
+            ..options.clone()
+        };
+        TypstRenderer {
+            inline: UnicodeRenderer::with_options(&options),
+            args: Cell::new(0),
+        }
+    }
+}
+
+impl Renderer for TypstRenderer {
+    fn render(&self, exprs: &[Expr]) -> String {
+        self.sequence(exprs)
+    }
+
+    fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        self.inline.take_diagnostics()
+    }
+}
+
+impl TypstRenderer {
+    fn sequence(&self, exprs: &[Expr]) -> String {
+        let mut result = String::new();
+        let mut previous: Option<(AtomClass, bool)> = None;
+        let mut after_spacing = false;
+        for (expr, atom) in exprs.iter().zip(self.inline.classify(exprs)) {
+            let item = self.expr(expr);
+            if item.is_empty() {
+                continue;
+            }
+            // spacing like `quad` stands apart from its neighbours
+            let spacing = atom.is_none();
+            let space = match (previous, atom) {
+                (Some(left), Some(right)) => !self.inline.space(left, right).is_empty(),
+                _ => false,
+            };
+            if (space || spacing || after_spacing) && !result.is_empty() {
+                result.push(' ');
+            }
+            push(&mut result, &item);
+            previous = atom.or(previous);
+            after_spacing = spacing;
+        }
+        result
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Literal(c) if c.is_alphanumeric() => c.to_string(),
+            ExprKind::Literal(c) if c.is_whitespace() => String::new(),
+            ExprKind::Literal(c) => self.escape(&c.to_string()),
+            ExprKind::Group(exprs) => self.sequence(exprs),
+            ExprKind::Superscript(base, sup) => {
+                format!("{}^{}", self.base(base), self.script(sup))
+            }
+            ExprKind::Subscript(base, sub) => {
+                format!("{}_{}", self.base(base), self.script(sub))
+            }
+            ExprKind::Environment(env) => {
+                self.inline.check_environment(env, expr.span);
+                self.environment(env)
+            }
+            ExprKind::Delimited(delimited) => self.delimited(delimited, expr.span),
+            ExprKind::Command(cmd) => self.command(expr, cmd),
+        }
+    }
+
+    // Scripts need something to attach to.
+    fn base(&self, base: &Expr) -> String {
+        match self.expr(base) {
+            s if s.is_empty() => "\"\"".to_string(),
+            s => s,
+        }
+    }
+
+    // `x^2` and `x^alpha`, but `x^(n+1)`: Typst drops the parentheses.
+    fn script(&self, expr: &Expr) -> String {
+        let script = self.inline.in_script(|| self.expr(expr));
+        let name = script.trim_start_matches(|c: char| c.is_alphabetic() || c == '.');
+        let single = script.chars().count() == 1
+            || script.chars().all(|c| c.is_ascii_digit() || c == '.')
+            || (name.is_empty() && script.starts_with(char::is_alphabetic))
+            || (name.len() < script.len() && is_call_arguments(name));
+        if single && !script.is_empty() {
+            script
+        } else {
+            format!("({})", script)
+        }
+    }
+
+    fn command(&self, expr: &Expr, cmd: &Command) -> String {
+        match cmd {
+            Command::Frac { numer, denom } => self.call("frac", &[numer, denom]),
+            Command::Sqrt {
+                index: Some(index),
+                content,
+            } if !matches!(&index.kind, ExprKind::Group(exprs) if exprs.is_empty()) => {
+                self.call("root", &[index, content])
+            }
+            Command::Sqrt { content, .. } => self.call("sqrt", &[content]),
+            Command::Mathcal { content } => self.call("cal", &[content]),
+            Command::Mathbb { content } => self.call("bb", &[content]),
+            Command::Mathfrak { content } => self.call("frak", &[content]),
+            Command::Mathbf { content } => self.call("bold", &[content]),
+            Command::Mathit { content } => self.call("italic", &[content]),
+            Command::Mathsf { content } => self.call("sans", &[content]),
+            Command::Mathtt { content } => self.call("mono", &[content]),
+            Command::Bar { content } => self.call("macron", &[content]),
+            Command::Hat { content } => self.call("hat", &[content]),
+            Command::Vec { content } => self.call("arrow", &[content]),
+            Command::Dot { content } => self.call("dot", &[content]),
+            Command::Ddot { content } => self.call("dot.double", &[content]),
+            Command::Tilde { content } => self.call("tilde", &[content]),
+            Command::Text { .. }
+            | Command::Textrm { .. }
+            | Command::Mbox { .. }
+            | Command::Mathrm { .. } => quote(&self.inline.render_expr(expr)),
+            Command::Operatorname { .. } => operator(&self.inline.render_expr(expr)),
+            Command::Xrightarrow { below, above } => self.xarrow("->", above, below.as_deref()),
+            Command::Xleftarrow { below, above } => self.xarrow("<-", above, below.as_deref()),
+            Command::Custom { name, args } => {
+                let accent = self.inline.symbol(name).and_then(|symbol| symbol.accent());
+                match (accent.and_then(typst_accent), args.as_slice()) {
+                    (Some(function), [arg]) => self.call(function, &[arg]),
+                    _ => self.symbol(expr, name),
+                }
+            }
+            Command::Symbol { name } => self.symbol(expr, name),
+        }
+    }
+
+    fn call(&self, function: &str, args: &[&Expr]) -> String {
+        let args: Vec<_> = args.iter().map(|arg| self.argument(arg)).collect();
+        format!("{}({})", function, args.join(", "))
+    }
+
+    fn argument(&self, expr: &Expr) -> String {
+        self.args.set(self.args.get() + 1);
+        let result = self.expr(expr);
+        self.args.set(self.args.get() - 1);
+        escape_unbalanced(result)
+    }
+
+    fn xarrow(&self, arrow: &str, above: &Expr, below: Option<&Expr>) -> String {
+        let mut result = format!("stretch({})", arrow);
+        if !matches!(&above.kind, ExprKind::Group(exprs) if exprs.is_empty()) {
+            result += &format!("^{}", self.script(above));
+        }
+        if let Some(below) = below {
+            result += &format!("_{}", self.script(below));
+        }
+        result
+    }
+
+    fn symbol(&self, expr: &Expr, name: &str) -> String {
+        // reports unknown commands, which are left as `\name`
+        let output = self.inline.render_expr(expr);
+        let Some(symbol) = self.inline.symbol(name) else {
+            return quote(&output);
+        };
+        if symbol.named {
+            return operator(&output);
+        }
+        if symbol.class.is_none() {
+            return match name {
+                "," => "thin",
+                ":" | ">" => "med",
+                ";" => "thick",
+                " " => "space",
+                "quad" => "quad",
+                "qquad" => "wide",
+                "!" => "#h(-0.167em)",
+                _ => "",
+            }
+            .to_string();
+        }
+        let mut chars = output.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => match typst_name(c) {
+                Some(name) => name.to_string(),
+                None => self.escape(&output),
+            },
+            _ => self.escape(&output),
+        }
+    }
+
+    fn environment(&self, env: &Environment) -> String {
+        let mut rows = env.rows.as_slice();
+        // a `\\` after the last row leaves an empty one
+        if let [init @ .., last] = rows
+            && let [cell] = last.as_slice()
+            && matches!(&cell.kind, ExprKind::Group(exprs) if exprs.is_empty())
+        {
+            rows = init;
+        }
+        let cells = |row: &Vec<Expr>, separator: &str| {
+            let cells: Vec<_> = row.iter().map(|cell| self.argument(cell)).collect();
+            cells.join(separator)
+        };
+
+        match env.name.as_str() {
+            "aligned" => {
+                let lines: Vec<_> = rows.iter().map(|row| cells(row, " & ")).collect();
+                lines.join(" \\ ")
+            }
+            "cases" => {
+                let lines: Vec<_> = rows.iter().map(|row| cells(row, " & ")).collect();
+                format!("cases({})", lines.join(", "))
+            }
+            name => {
+                let delimiter = match name {
+                    "pmatrix" => "",
+                    "bmatrix" => "delim: \"[\", ",
+                    "Bmatrix" => "delim: \"{\", ",
+                    "vmatrix" => "delim: \"|\", ",
+                    "Vmatrix" => "delim: \"||\", ",
+                    _ => "delim: #none, ",
+                };
+                let lines: Vec<_> = rows.iter().map(|row| cells(row, ", ")).collect();
+                format!("mat({}{})", delimiter, lines.join("; "))
+            }
+        }
+    }
+
+    // `lr(...)` makes the delimiters grow with what they enclose; it needs
+    // both of them.
+    fn delimited(&self, delimited: &Delimited, span: Span) -> String {
+        let delimiter = |d: &str| self.escape(&self.inline.delimiter(d, span));
+        let mut body = delimiter(&delimited.open);
+        self.args.set(self.args.get() + 1);
+        for (i, part) in delimited.parts.iter().enumerate() {
+            if let Some(middle) = i.checked_sub(1).and_then(|i| delimited.middles.get(i)) {
+                push(&mut body, &format!(" mid({}) ", delimiter(middle)));
+            }
+            push(&mut body, &self.expr(part));
+        }
+        self.args.set(self.args.get() - 1);
+        push(&mut body, &delimiter(&delimited.close));
+
+        if delimited.open == "." || delimited.close == "." {
+            body
+        } else {
+            format!("lr({})", escape_unbalanced(body))
+        }
+    }
+
+    // `text` with the characters that mean something in Typst math escaped,
+    // or in quotes if it has words, which Typst would take for variables.
+    fn escape(&self, text: &str) -> String {
+        let letters = text.chars().collect::<Vec<_>>();
+        if letters
+            .windows(2)
+            .any(|pair| pair.iter().all(char::is_ascii_alphabetic))
+        {
+            return quote(text);
+        }
+        let mut result = String::new();
+        for c in text.chars() {
+            let special = matches!(
+                c,
+                '\\' | '#' | '$' | '"' | '_' | '^' | '&' | '/' | '@' | '~' | '\''
+            ) || (self.args.get() > 0 && matches!(c, ',' | ';'));
+            if special && c != '\'' {
+                result.push('\\');
+            }
+            result.push(c);
+        }
+        result
+    }
+}
+
+// Appends `next`, with a space in between where the two would otherwise run
+// together into a name, like `alpha x`, or a call, like `dot.op (`.
+fn push(result: &mut String, next: &str) {
+    let before = result.trim_end_matches(|c: char| c.is_alphanumeric() || c == '.');
+    let word = &result[before.len()..];
+    // a script, which `x^10 e` keeps apart from what follows
+    let script = !word.is_empty() && before.ends_with(['^', '_']);
+    // the name at the end, after the digits of a number like `2x`
+    let name = word.trim_start_matches(|c: char| !c.is_alphabetic());
+    let joins = match next.chars().next() {
+        Some(c) if c.is_alphanumeric() || c == '"' => {
+            !name.is_empty() || script || result.ends_with(['"', ')'])
+        }
+        Some('(') => name.chars().count() > 1 || result.ends_with(')'),
+        _ => false,
+    };
+    if joins {
+        result.push(' ');
+    }
+    *result += next;
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn operator(name: &str) -> String {
+    if OPERATORS.contains(&name) {
+        name.to_string()
+    } else {
+        format!("op({})", quote(name))
+    }
+}
+
+// Whether `s` is the `(...)` of a single call.
+fn is_call_arguments(s: &str) -> bool {
+    if !s.starts_with('(') {
+        return false;
+    }
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '(' => depth += 1,
+            ')' if depth == 1 => return i == s.len() - 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    false
+}
+
+// Parentheses that don't pair up would end the call an argument is part of.
+fn escape_unbalanced(arg: String) -> String {
+    let mut depth = 0i32;
+    let mut escaped = false;
+    for c in arg.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '(' => depth += 1,
+            ')' if depth == 0 => return escape_parens(&arg),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    if depth == 0 { arg } else { escape_parens(&arg) }
+}
+
+fn escape_parens(arg: &str) -> String {
+    let mut result = String::new();
+    let mut escaped = false;
+    for c in arg.chars() {
+        if matches!(c, '(' | ')') && !escaped {
+            result.push('\\');
+        }
+        escaped = c == '\\' && !escaped;
+        result.push(c);
+    }
+    result
+}
+
+// The name Typst gives a character, for those more readable by name.
+fn typst_name(c: char) -> Option<&'static str> {
+    let name = match c {
+        'α' => "alpha",
+        'β' => "beta",
+        'γ' => "gamma",
+        'δ' => "delta",
+        'ε' => "epsilon",
+        'ϵ' => "epsilon.alt",
+        'ζ' => "zeta",
+        'η' => "eta",
+        'θ' => "theta",
+        'ϑ' => "theta.alt",
+        'ι' => "iota",
+        'κ' => "kappa",
+        'ϰ' => "kappa.alt",
+        'λ' => "lambda",
+        'μ' => "mu",
+        'ν' => "nu",
+        'ξ' => "xi",
+        'ο' => "omicron",
+        'π' => "pi",
+        'ϖ' => "pi.alt",
+        'ρ' => "rho",
+        'ϱ' => "rho.alt",
+        'σ' => "sigma",
+        'ς' => "sigma.alt",
+        'τ' => "tau",
+        'υ' => "upsilon",
+        'φ' => "phi",
+        'ϕ' => "phi.alt",
+        'χ' => "chi",
+        'ψ' => "psi",
+        'ω' => "omega",
+        'Γ' => "Gamma",
+        'Δ' => "Delta",
+        'Θ' => "Theta",
+        'Λ' => "Lambda",
+        'Ξ' => "Xi",
+        'Π' => "Pi",
+        'Σ' => "Sigma",
+        'Υ' => "Upsilon",
+        'Φ' => "Phi",
+        'Ψ' => "Psi",
+        'Ω' => "Omega",
+        '∑' => "sum",
+        '∏' => "product",
+        '∫' => "integral",
+        '∮' => "integral.cont",
+        '∞' => "infinity",
+        '∂' => "partial",
+        '∇' => "nabla",
+        '…' => "dots.h",
+        '⋯' => "dots.c",
+        '≤' => "<=",
+        '≥' => ">=",
+        '≠' => "!=",
+        '→' => "->",
+        '←' => "<-",
+        '⇒' => "=>",
+        '⟹' => "==>",
+        '↦' => "|->",
+        '≈' => "approx",
+        '∈' => "in",
+        '∉' => "in.not",
+        '⊂' => "subset",
+        '⊆' => "subset.eq",
+        '∪' => "union",
+        '∩' => "sect",
+        '×' => "times",
+        '⋅' => "dot.op",
+        '±' => "plus.minus",
+        '∀' => "forall",
+        '∃' => "exists",
+        '∅' => "emptyset",
+        _ => return None,
+    };
+    Some(name)
+}
+
+// The Typst function for a combining accent, like `overline` for U+0305.
+fn typst_accent(c: char) -> Option<&'static str> {
+    let name = match c {
+        '\u{0300}' => "grave",
+        '\u{0301}' => "acute",
+        '\u{0302}' => "hat",
+        '\u{0303}' => "tilde",
+        '\u{0304}' => "macron",
+        '\u{0305}' => "overline",
+        '\u{0306}' => "breve",
+        '\u{0307}' => "dot",
+        '\u{0308}' => "dot.double",
+        '\u{030A}' => "circle",
+        '\u{030B}' => "acute.double",
+        '\u{030C}' => "caron",
+        '\u{0332}' => "underline",
+        '\u{20D0}' => "harpoon.lt",
+        '\u{20D1}' => "harpoon",
+        '\u{20D6}' => "arrow.l",
+        '\u{20D7}' => "arrow",
+        '\u{20DB}' => "dot.triple",
+        '\u{20DC}' => "dot.quad",
+        '\u{20E1}' => "arrow.l.r",
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn render(input: &str) -> String {
+        let mut parser = Parser::new(Lexer::new(input));
+        TypstRenderer::new().render(&parser.parse())
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(render(r"\frac{a}{b} + \sqrt{x}"), "frac(a, b) + sqrt(x)");
+        assert_eq!(render(r"\sqrt[3]{x+1}"), "root(3, x + 1)");
+        assert_eq!(
+            render(r"\mathbb{R} \mathcal{A} \hat{x}"),
+            "bb(R) cal(A) hat(x)"
+        );
+        assert_eq!(render(r"\alpha \le \beta_i"), "alpha <= beta_i");
+        assert_eq!(
+            render(r"\sin x \operatorname{sgn} y"),
+            "sin x op(\"sgn\") y"
+        );
+        assert_eq!(render(r"\text{if } xy"), "\"if \" x y");
+        assert_eq!(
+            render(r"\overline{AB} \widehat{x} \check y"),
+            "overline(A B) hat(x) caron(y)"
+        );
+    }
+
+    #[test]
+    fn test_scripts() {
+        assert_eq!(render("x^{n+1}"), "x^(n+1)");
+        assert_eq!(render(r"x^{10} e^{\pi}"), "x^10 e^pi");
+        assert_eq!(render(r"\sum_{i=1}^n x_i"), "sum_(i=1)^n x_i");
+        assert_eq!(render(r"e^{\frac{1}{2}}"), "e^frac(1, 2)");
+        assert_eq!(render("x^{2y} x^{ab}"), "x^(2y) x^(a b)");
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(render("a/b"), "a\\/b");
+        assert_eq!(render(r"\frac{a,b}{(c}"), "frac(a\\, b, \\(c)");
+        assert_eq!(render(r"\foo"), "\"\\\\foo\"");
+    }
+
+    #[test]
+    fn test_structures() {
+        assert_eq!(
+            render(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"),
+            "mat(a, b; c, d)"
+        );
+        assert_eq!(
+            render(r"\begin{bmatrix} 1 \\ \end{bmatrix}"),
+            "mat(delim: \"[\", 1)"
+        );
+        assert_eq!(
+            render(r"\begin{cases} 1 & x > 0 \\ 0 & \text{else} \end{cases}"),
+            "cases(1 & x > 0, 0 & \"else\")"
+        );
+        assert_eq!(render(r"\left( x \middle| y \right)"), "lr((x mid(|) y))");
+        assert_eq!(render(r"\left. x \right|"), "x|");
+    }
+}